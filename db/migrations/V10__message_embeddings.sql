@@ -0,0 +1,14 @@
+-- Embeddings of messages for semantic search, compared in the application
+CREATE TABLE message_embeddings (
+    message_id uuid NOT NULL PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    -- Embeddings from different models can't be compared
+    model varchar(255) NOT NULL,
+    embedding real[] NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+-- Create index for picking the embeddings of one model
+CREATE INDEX idx_message_embeddings_model ON message_embeddings (model);
+
+-- Grant permissions
+GRANT SELECT, INSERT, UPDATE, DELETE ON TABLE message_embeddings TO thread_manager;
@@ -0,0 +1,14 @@
+-- Sessions issued by the auth service; a token is only valid while its row is
+CREATE TABLE sessions (
+    id uuid DEFAULT gen_random_uuid() NOT NULL PRIMARY KEY,
+    account_id uuid NOT NULL REFERENCES accounts(id) ON DELETE CASCADE,
+    user_id uuid NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    expires_at timestamp with time zone NOT NULL,
+    revoked_at timestamp with time zone
+);
+
+CREATE INDEX idx_sessions_user_id ON sessions (user_id);
+
+-- Grant permissions
+GRANT SELECT,INSERT,DELETE,UPDATE ON TABLE sessions TO user_manager;
@@ -0,0 +1,5 @@
+-- Accounts that sign in with a password instead of an external provider
+ALTER TYPE auth_provider ADD VALUE 'Local';
+
+-- Argon2 PHC string, only set for local accounts
+ALTER TABLE accounts ADD COLUMN password_hash text;
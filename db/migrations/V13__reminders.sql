@@ -0,0 +1,20 @@
+-- Messages users asked the artilect to post into a thread at a later time
+CREATE TABLE reminders (
+    id uuid DEFAULT gen_random_uuid() NOT NULL PRIMARY KEY,
+    -- Who asked for the reminder; only they may list or cancel it
+    user_id uuid NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    thread_id uuid NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
+    content text NOT NULL,
+    due_at timestamp with time zone NOT NULL,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    delivered_at timestamp with time zone
+);
+
+-- Create partial index for the timer picking up due reminders
+CREATE INDEX idx_reminders_due ON reminders (due_at) WHERE delivered_at IS NULL;
+
+-- Create index for listing a user's reminders
+CREATE INDEX idx_reminders_user ON reminders (user_id, due_at);
+
+-- Grant permissions
+GRANT SELECT, INSERT, UPDATE, DELETE ON TABLE reminders TO thread_manager;
@@ -0,0 +1,5 @@
+-- Deleted messages stay in the table but are hidden everywhere
+ALTER TABLE messages ADD COLUMN deleted_at timestamp with time zone;
+
+-- Create partial index for reading the visible messages of a thread
+CREATE INDEX idx_messages_thread_visible ON messages (thread_id, created_at) WHERE deleted_at IS NULL;
@@ -0,0 +1,16 @@
+-- Messages form a tree: each one answers its parent, and a thread branches
+-- wherever a message has several answers
+ALTER TABLE messages ADD COLUMN parent_id uuid REFERENCES messages(id) ON DELETE SET NULL;
+
+-- Backfill the linear threads as a single branch each
+UPDATE messages
+SET parent_id = previous.parent_id
+FROM (
+    SELECT id, LAG(id) OVER (PARTITION BY thread_id ORDER BY created_at) AS parent_id
+    FROM messages
+    WHERE deleted_at IS NULL
+) previous
+WHERE messages.id = previous.id;
+
+-- Create index for finding the answers to a message
+CREATE INDEX idx_messages_parent ON messages (parent_id);
@@ -0,0 +1,12 @@
+-- Full-text search over what users can read, 'simple' like memories as threads may be in any language
+ALTER TABLE messages
+    ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED;
+
+ALTER TABLE threads
+    ADD COLUMN search_vector tsvector GENERATED ALWAYS AS (to_tsvector('simple', coalesce(name, ''))) STORED;
+
+-- Create index for searching messages
+CREATE INDEX idx_messages_search ON messages USING GIN (search_vector);
+
+-- Create index for searching thread names
+CREATE INDEX idx_threads_search ON threads USING GIN (search_vector);
@@ -0,0 +1,28 @@
+-- Numbers every change clients sync, so that they can ask for what changed since they last looked.
+-- Writes take the next number themselves when they change what clients see.
+CREATE SEQUENCE change_seq;
+
+ALTER TABLE threads ADD COLUMN change_seq bigint NOT NULL DEFAULT nextval('change_seq');
+
+ALTER TABLE messages ADD COLUMN change_seq bigint NOT NULL DEFAULT nextval('change_seq');
+
+-- When the user joined, as a thread counts as changed for whoever joins it
+ALTER TABLE thread_participants ADD COLUMN change_seq bigint NOT NULL DEFAULT nextval('change_seq');
+
+-- Who left or was removed from which thread, so that syncing can tell them it's gone
+CREATE TABLE thread_departures (
+    thread_id uuid NOT NULL REFERENCES threads(id) ON DELETE CASCADE,
+    user_id uuid NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    change_seq bigint NOT NULL DEFAULT nextval('change_seq'),
+    PRIMARY KEY (thread_id, user_id)
+);
+
+-- Create indexes for finding changes after a cursor
+CREATE INDEX idx_threads_change_seq ON threads (change_seq);
+CREATE INDEX idx_messages_change_seq ON messages (change_seq);
+CREATE INDEX idx_thread_participants_change_seq ON thread_participants (user_id, change_seq);
+CREATE INDEX idx_thread_departures_change_seq ON thread_departures (user_id, change_seq);
+
+-- Grant permissions
+GRANT SELECT, INSERT, UPDATE, DELETE ON TABLE thread_departures TO thread_manager;
+GRANT USAGE, SELECT ON SEQUENCE change_seq TO thread_manager;
@@ -0,0 +1,16 @@
+-- Which transaction made each change. Sequence numbers are taken before changes commit, so
+-- one may commit after a higher number was already synced; syncing goes by transactions
+-- instead, and only hands out those that no older running transaction could still precede.
+ALTER TABLE threads ADD COLUMN change_xid bigint NOT NULL DEFAULT pg_current_xact_id()::text::bigint;
+
+ALTER TABLE messages ADD COLUMN change_xid bigint NOT NULL DEFAULT pg_current_xact_id()::text::bigint;
+
+ALTER TABLE thread_participants ADD COLUMN change_xid bigint NOT NULL DEFAULT pg_current_xact_id()::text::bigint;
+
+ALTER TABLE thread_departures ADD COLUMN change_xid bigint NOT NULL DEFAULT pg_current_xact_id()::text::bigint;
+
+-- Create indexes for finding changes after a cursor
+CREATE INDEX idx_threads_change_xid ON threads (change_xid);
+CREATE INDEX idx_messages_change_xid ON messages (change_xid);
+CREATE INDEX idx_thread_participants_change_xid ON thread_participants (user_id, change_xid);
+CREATE INDEX idx_thread_departures_change_xid ON thread_departures (user_id, change_xid);
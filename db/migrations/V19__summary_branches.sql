@@ -0,0 +1,4 @@
+-- The last message folded into the summary. The summary only belongs to the branches that
+-- run through it; those forking earlier are prompted without it.
+-- Summaries from before don't know theirs, so they're left out until summarized again.
+ALTER TABLE thread_summaries ADD COLUMN covered_message_id uuid REFERENCES messages(id) ON DELETE SET NULL;
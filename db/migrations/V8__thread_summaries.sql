@@ -0,0 +1,11 @@
+-- Running summary of the older part of each thread
+CREATE TABLE thread_summaries (
+    thread_id uuid NOT NULL PRIMARY KEY REFERENCES threads(id) ON DELETE CASCADE,
+    content text NOT NULL,
+    -- Messages up to and including this point are folded into the summary
+    covered_until timestamp with time zone NOT NULL,
+    updated_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+-- Grant permissions
+GRANT SELECT, INSERT, UPDATE, DELETE ON TABLE thread_summaries TO thread_manager;
@@ -0,0 +1,21 @@
+-- Facts the artilect has learned, owned by the user they were learned from
+CREATE TABLE memories (
+    id uuid DEFAULT gen_random_uuid() NOT NULL PRIMARY KEY,
+    user_id uuid NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Where the fact came up, if the thread still exists
+    thread_id uuid REFERENCES threads(id) ON DELETE SET NULL,
+    content text NOT NULL,
+    -- 'simple' rather than a language config, as conversations may be in any language
+    search_vector tsvector GENERATED ALWAYS AS (to_tsvector('simple', content)) STORED,
+    created_at timestamp with time zone DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    UNIQUE (user_id, content)
+);
+
+-- Create index for full-text retrieval
+CREATE INDEX idx_memories_search ON memories USING GIN (search_vector);
+
+-- Create index for listing a user's memories
+CREATE INDEX idx_memories_user ON memories (user_id, created_at DESC);
+
+-- Grant permissions
+GRANT SELECT, INSERT, UPDATE, DELETE ON TABLE memories TO thread_manager;
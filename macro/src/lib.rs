@@ -90,6 +90,15 @@ pub fn message_handler(attr: TokenStream, item: TokenStream) -> TokenStream {
     let function = parse_macro_input!(item as syn::ItemFn);
     let fn_name = &function.sig.ident;
 
+    // Handlers that spawn background work take the `Arc` itself instead of a reference
+    let first_arg = function.sig.inputs.iter().next()
+        .expect("Function must have a state argument");
+    let state_arg = match first_arg {
+        syn::FnArg::Typed(pat_type) if matches!(*pat_type.ty, syn::Type::Reference(_)) => quote! { &*state },
+        syn::FnArg::Typed(_) => quote! { state },
+        _ => panic!("State argument must be typed"),
+    };
+
     // Get second argument type (the message type)
     let second_arg = function.sig.inputs.iter().nth(1)
         .expect("Function must have a second argument");
@@ -113,7 +122,7 @@ pub fn message_handler(attr: TokenStream, item: TokenStream) -> TokenStream {
             fn handle(&mut self, message: #arg_type, _: &mut Self::Context) -> Self::Result {
                 let state = self.state.clone();
                 Box::pin(async move {
-                    #fn_name(&*state, message).await
+                    #fn_name(#state_arg, message).await
                 })
             }
         }
@@ -9,8 +9,22 @@ use super::dto::User;
 mod prompts;
 mod handlers;
 mod actor;
+mod embeddings;
+mod events;
+mod memories;
+mod messages;
+mod participants;
+mod reminders;
+mod replies;
+mod scheduler;
+mod search;
+mod summaries;
+mod sync;
 
 use actor::ChatService;
+use reminders::ReminderTimer;
+use scheduler::Scheduler;
+use crate::auth::client::AuthClient;
 use crate::infer::{Client, RootChain};
 
 // const AGENT_PROMPT_TEXT: &str = "You are the chat agent. \
@@ -50,7 +64,7 @@ async fn ensure_artilect_user(pool: &PgPool, name: Box<str>) -> Result<User, sql
     Ok(user)
 }
 
-pub async fn serve(name: Box<str>, database_url: Box<str>, port: Option<u16>, client: Client) {
+pub async fn serve(name: Box<str>, database_url: Box<str>, port: Option<u16>, client: Client, auth: AuthClient) {
     // Create database connection pool
     let pool = PgPool::connect(&database_url)
         .await
@@ -64,10 +78,13 @@ pub async fn serve(name: Box<str>, database_url: Box<str>, port: Option<u16>, cl
     let system_prompt = RootChain::from_message(client, crate::prompts::system(AGENT_PROMPT_TEXT));
 
     // Create shared state
-    let actor = ChatService::new(pool, self_user, system_prompt).start();
+    let service = ChatService::new(pool, self_user, system_prompt);
+    Scheduler::new(service.state.clone()).start();
+    ReminderTimer::new(service.state.clone()).start();
+    let actor = service.start();
     let state = Arc::new(actor.clone());
 
-    let router = handlers::build_router(state);
+    let router = handlers::build_router(state, auth);
 
     // Start server
     if let Some(port) = port {
@@ -2,27 +2,32 @@ use std::{ops::Deref, sync::Arc};
 
 use actix::prelude::*;
 use artilect_macro::message_handler;
+use futures_util::StreamExt;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use super::prompts;
+use super::{
+    embeddings, events::EventHub, memories, prompts, reminders, replies::{self, ReplyQueue}, summaries,
+};
 use crate::{
     actuators::chat::dto::{
-        ChatMessage, FetchThreadRequest, FetchThreadResponse, FetchUserThreadsRequest,
-        FetchUserThreadsResponse, OneToManyChild, OneToManyUpdate, SendMessageRequest,
+        ChatEvent, ChatMessage, FetchThreadRequest, FetchThreadResponse, FetchUserThreadsRequest,
+        FetchUserThreadsResponse, OneToManyChild, OneToManyUpdate, PageCursor, SendMessageRequest,
         SendMessageResponse, SyncUpdate, Thread, User,
     },
-    infer::{self, PlainText, RootChain},
+    infer::{self, InferDelta, InferError, PlainText, RootChain},
     service::{self, CoercibleResult},
 };
 pub struct State {
     pub pool: PgPool,
     pub self_user: User,
     pub system_prompt: RootChain,
+    pub events: EventHub,
+    pub replies: ReplyQueue,
 }
 
 pub struct ChatService {
-    state: Arc<State>,
+    pub(super) state: Arc<State>,
 }
 
 impl ChatService {
@@ -32,6 +37,8 @@ impl ChatService {
                 pool,
                 self_user,
                 system_prompt,
+                events: EventHub::default(),
+                replies: ReplyQueue::default(),
             }),
         }
     }
@@ -41,7 +48,7 @@ impl Actor for ChatService {
     type Context = actix::Context<Self>;
 }
 
-async fn fetch_thread(
+pub(super) async fn fetch_thread(
     state: &State,
     thread_id: Uuid,
 ) -> service::Result<Thread> {
@@ -120,11 +127,13 @@ async fn create_thread(
     Ok(thread)
 }
 
-async fn create_message(
+/// Adds a message answering `parent_id`, or the latest message of the thread without one.
+pub(super) async fn create_message(
     pool: &PgPool,
     user_id: Option<Uuid>,
     thread_id: Uuid,
     message_id: Option<Uuid>,
+    parent_id: Option<Uuid>,
     message: &str,
 ) -> service::Result<(ChatMessage, Thread)> {
     let mut tx = pool.begin().await.into_service_result()?;
@@ -151,16 +160,48 @@ async fn create_message(
         // "User is not a participant in this thread".into(),
     }
 
+    if let Some(parent_id) = parent_id {
+        let is_parent_in_thread = sqlx::query_scalar!(
+            r#"--sql
+            SELECT EXISTS (
+                SELECT 1 FROM messages
+                WHERE id = $1 AND thread_id = $2 AND deleted_at IS NULL
+            )
+            "#,
+            parent_id,
+            thread_id,
+        )
+            .fetch_one(&mut *tx)
+            .await
+            .into_service_result()?
+            .unwrap_or(false);
+        if !is_parent_in_thread {
+            return Err(service::Error::BadRequest("The message to answer isn't in this thread".into()));
+        }
+    }
+
     let message = sqlx::query_as!(
         ChatMessage,
         r#"--sql
-        INSERT INTO messages (id, user_id, thread_id, content)
-        VALUES (COALESCE($1, gen_random_uuid()), $2, $3, $4)
-        RETURNING id, thread_id, user_id, content, created_at, updated_at
+        INSERT INTO messages (id, user_id, thread_id, parent_id, content)
+        VALUES (
+            COALESCE($1, gen_random_uuid()),
+            $2,
+            $3,
+            COALESCE($4, (
+                SELECT id FROM messages
+                WHERE thread_id = $3 AND deleted_at IS NULL
+                ORDER BY created_at DESC
+                LIMIT 1
+            )),
+            $5
+        )
+        RETURNING id, thread_id, user_id, parent_id, content, created_at, updated_at
         "#,
         message_id,
         user_id,
         thread_id,
+        parent_id,
         message,
     )
         .fetch_one(&mut *tx)
@@ -170,11 +211,17 @@ async fn create_message(
     let thread = sqlx::query_as!(
         Thread,
         r#"--sql
-        UPDATE threads SET updated_at = $1 WHERE id = $2
+        UPDATE threads SET
+            updated_at = $1,
+            pending_updates = pending_updates OR $3,
+            change_seq = nextval('change_seq'), change_xid = DEFAULT
+        WHERE id = $2
         RETURNING id, name, owner_id, created_at, updated_at
         "#,
         message.created_at,
         thread_id,
+        // Only humans give the scheduler something to follow up on
+        user_id.is_some_and(|user_id| !user_id.is_nil()),
     )
         .fetch_one(&mut *tx)
         .await
@@ -184,37 +231,86 @@ async fn create_message(
     Ok((message, thread))
 }
 
-async fn generate_thread_name(
-    state: &State,
+/// Messages of a thread's branch newer than `after`, newest first, with times in the local timezone.
+/// The branch is the one ending at `leaf_id`, or at the latest message of the thread without one.
+pub(super) async fn fetch_message_log(
+    pool: &PgPool,
     thread_id: Uuid,
-) -> anyhow::Result<Thread> {
+    leaf_id: Option<Uuid>,
+    after: Option<time::OffsetDateTime>,
+) -> service::Result<Vec<prompts::MessageLogItem>> {
+    let timezone = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
     let messages = sqlx::query_as!(
         prompts::MessageLogItemRow,
         r#"--sql
+            WITH RECURSIVE branch AS (
+                SELECT id, parent_id
+                FROM messages
+                WHERE thread_id = $1
+                    AND id = COALESCE($2, (
+                        SELECT id FROM messages
+                        WHERE thread_id = $1 AND deleted_at IS NULL
+                        ORDER BY created_at DESC
+                        LIMIT 1
+                    ))
+                UNION ALL
+                SELECT messages.id, messages.parent_id
+                FROM messages
+                INNER JOIN branch ON messages.id = branch.parent_id
+            )
             SELECT
+                messages.id,
                 messages.user_id,
                 users.name AS "user_name?", 
                 messages.created_at,
                 messages.content
-            FROM messages
+            FROM branch
+            INNER JOIN messages ON messages.id = branch.id
             LEFT JOIN users ON messages.user_id = users.id
-            WHERE messages.thread_id = $1 
+            WHERE messages.deleted_at IS NULL
+                AND ($3::timestamptz IS NULL OR messages.created_at > $3)
             ORDER BY messages.created_at DESC
         "#,
         // @note: DESC sorting b/c we will have to eventually introduce LIMIT
         thread_id,
+        leaf_id,
+        after,
     )
-        .fetch_all(&state.pool)
+        .fetch_all(pool)
         .await
-        .map_err(|_| service::Error::NotFound)?
+        .into_service_result()?
         .into_iter()
         .map(prompts::MessageLogItem::from)
+        .map(|mut message| {
+            message.created_at = message.created_at.to_offset(timezone);
+            message
+        })
         .collect::<Vec<_>>();
     // @todo Make it less ugly by using .fetch instead of .fetch_all
+    Ok(messages)
+}
+
+pub(super) async fn generate_thread_name(
+    state: &State,
+    thread_id: Uuid,
+) -> anyhow::Result<Thread> {
+    let summary = summaries::fetch_summary(&state.pool, thread_id, None).await?;
+    let messages = fetch_message_log(
+        &state.pool,
+        thread_id,
+        None,
+        summary.as_ref().map(|summary| summary.covered_until),
+    ).await?;
 
-    let inference = state.system_prompt
-        .fork()
-        .with_messages(prompts::message_log(messages)?)
+    let chain = state.system_prompt.fork().with_utility_model();
+    // The title only needs the gist, so half the window is plenty
+    let budget = chain.model().profile.prompt_budget().saturating_sub(chain.estimated_tokens()) / 2;
+    let inference = chain
+        .with_messages(prompts::message_log_within_budget(
+            summary.as_ref().map(|summary| &*summary.content),
+            messages,
+            budget,
+        )?)
         // @todo: make the next message system message when the model no longer has problems with it.
         .with_message(infer::Message::new_text_user(markup::new! {
             systemInstructions {
@@ -237,7 +333,8 @@ async fn generate_thread_name(
                         WHEN char_length($1) > 255 THEN 
                             LEFT($1, 254) || '…'
                         ELSE $1
-                    END
+                    END,
+                    change_seq = nextval('change_seq'), change_xid = DEFAULT
                 WHERE id = $2
                 RETURNING id, name, owner_id, created_at, updated_at
                 "#,
@@ -248,89 +345,154 @@ async fn generate_thread_name(
                 .await?
         }
         Err(e) => {
-            create_message(&state.pool, None, thread_id, None, &e.to_string()).await?;
+            create_message(&state.pool, None, thread_id, None, None, &e.to_string()).await?;
             fetch_thread(&state, thread_id).await?
         }
     };
     Ok(thread)
 }
 
-async fn get_thread_message_ids(
-    pool: &PgPool,
-    thread_id: Uuid,
-) -> service::Result<Vec<Uuid>> {
-    let messages = sqlx::query!(
-        r#"--sql
-            SELECT id
-            FROM messages
-            WHERE thread_id = $1
-            ORDER BY created_at ASC
-        "#,
-        thread_id,
-    )
-        .fetch_all(pool)
+/// Tells the thread's participants about a new message.
+pub(super) async fn publish_message(state: &State, message: &ChatMessage) -> service::Result<()> {
+    let event = ChatEvent::Messages(vec![SyncUpdate::Updated(message.clone())]);
+    state.events.publish_to_thread(&state.pool, message.thread_id, event).await
+}
+
+pub(super) async fn publish_thread(state: &State, thread: &Thread) -> service::Result<()> {
+    state
+        .events
+        .publish_to_thread(&state.pool, thread.id, ChatEvent::Threads(vec![SyncUpdate::Updated(thread.clone())]))
         .await
-        .map_err(|_| service::Error::NotFound)?;
-    Ok(messages.into_iter().map(|m| m.id).collect())
 }
 
-async fn respond_to_thread(
+/// How many threads the sidebar gets at once
+const THREAD_PAGE_SIZE: usize = 50;
+
+/// How many messages of a thread the client gets at once
+const MESSAGE_PAGE_SIZE: usize = 50;
+
+/// Trims `rows`, fetched newest first with one row to spare, to a page. The spare row
+/// only tells whether there is a next page, which starts after the page's last row.
+fn paginate<T>(mut rows: Vec<T>, page_size: usize, cursor: impl Fn(&T) -> PageCursor) -> (Vec<T>, Option<PageCursor>) {
+    if rows.len() <= page_size {
+        return (rows, None);
+    }
+    rows.truncate(page_size);
+    let next_page = rows.last().map(cursor);
+    (rows, next_page)
+}
+
+/// How many times to halve the message log when the model still reports a context overflow
+const CONTEXT_LENGTH_RETRIES: usize = 3;
+
+/// How many of the latest messages from others are searched for in memories and other threads
+const RECALL_QUERY_MESSAGE_COUNT: usize = 3;
+
+/// How many semantically related messages from other threads go into the context
+const RELATED_MESSAGE_COUNT: usize = 3;
+
+/// Answers `reply_to`, seeing only the branch of the thread that leads up to it.
+pub(super) async fn respond_to_thread(
     state: &State,
     thread_id: Uuid,
+    reply_to: Uuid,
 ) -> anyhow::Result<(ChatMessage, Thread)> {
-    let timezone = time::UtcOffset::current_local_offset().unwrap_or(time::UtcOffset::UTC);
-
     // @note: we don't need the thread, but we need to fetch it to ensure the artilect is a participant
     let _ = fetch_thread_for_user(&state, state.self_user.id, thread_id).await?;
 
-    let mut messages = sqlx::query_as!(
-        prompts::MessageLogItemRow,
-        r#"--sql
-            SELECT
-                messages.user_id,
-                users.name AS "user_name?", 
-                messages.created_at,
-                messages.content
-            FROM messages
-            LEFT JOIN users ON messages.user_id = users.id
-            WHERE messages.thread_id = $1 
-            ORDER BY messages.created_at DESC
-        "#,
+    let summary = summaries::fetch_summary(&state.pool, thread_id, Some(reply_to)).await?;
+    let messages = fetch_message_log(
+        &state.pool,
         thread_id,
-    )
-        .fetch_all(&state.pool)
-        .await?
-        .into_iter()
-        .map(prompts::MessageLogItem::from)
-        .collect::<Vec<_>>();
-    // @todo Make it less ugly by using .fetch instead of .fetch_all
+        Some(reply_to),
+        summary.as_ref().map(|summary| summary.covered_until),
+    ).await?;
 
-    for msg in &mut messages {
-        msg.created_at = msg.created_at.to_offset(timezone);
-    }
-    
-    let inference = state.system_prompt
-        .fork()
-        .with_messages(prompts::message_log(messages)?)
-        .with_message(infer::Message::new_text_system(markup::new! {
-            systemInstructions {
-                "Do not just repeat back the question. "
-                "Note to respond in the language the message above."
+    let instructions = infer::Message::new_text_system(markup::new! {
+        systemInstructions {
+            "Do not just repeat back the question. "
+            "Note to respond in the language the message above."
+        }
+    }.to_string());
+    // Recall by what the others said last, not by our own replies
+    let recall_query = messages
+        .iter()
+        .filter(|message| !message.is_own_message())
+        .take(RECALL_QUERY_MESSAGE_COUNT)
+        .map(|message| message.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let recalled_memories = memories::recall(&state.pool, thread_id, &recall_query).await?;
+    let related_messages = embeddings::find_related_messages(state, thread_id, &recall_query, RELATED_MESSAGE_COUNT)
+        .await
+        .unwrap_or_else(|error| {
+            tracing::warn!("Failed to search related messages: {error}");
+            Vec::new()
+        });
+    let upcoming_reminders = reminders::fetch_upcoming(&state.pool, thread_id).await?;
+    let context = prompts::context(&recalled_memories, &related_messages, &upcoming_reminders);
+
+    let base_chain = state.system_prompt.fork();
+    let mut budget = base_chain
+        .model()
+        .profile
+        .prompt_budget()
+        .saturating_sub(
+            base_chain.estimated_tokens()
+                + context.as_ref().map_or(0, |context| context.estimated_tokens())
+                + instructions.estimated_tokens(),
+        );
+
+    let message_id = Uuid::new_v4();
+    let participant_ids = super::events::fetch_thread_participant_ids(&state.pool, thread_id).await?;
+    let mut retries_left = CONTEXT_LENGTH_RETRIES;
+    let inference = loop {
+        let chain = base_chain
+            .clone()
+            .with_messages(prompts::message_log_within_budget(
+                summary.as_ref().map(|summary| &*summary.content),
+                messages.clone(),
+                budget,
+            )?)
+            .with_messages(context.clone())
+            .with_message(instructions.clone());
+        let inference: Result<String, InferError> = async {
+            let mut deltas = chain.infer_stream(Some(false)).await?;
+            let mut content = String::new();
+            while let Some(delta) = deltas.next().await {
+                if let InferDelta::Content(delta) = delta? {
+                    content.push_str(&delta);
+                    state.events.publish(&participant_ids, ChatEvent::MessageDelta {
+                        thread_id,
+                        message_id,
+                        parent_id: reply_to,
+                        content: delta.into(),
+                    });
+                }
             }
-        }.to_string()))
-        .infer_drop::<PlainText>(false)
-        .await;
+            Ok(content.trim().to_string())
+        }.await;
+        match inference {
+            // The estimate is only a guess, so try again with a smaller window
+            Err(InferError::ContextLengthError(error)) if retries_left > 0 => {
+                tracing::warn!("Prompt of ~{budget} tokens didn't fit, retrying with half: {error}");
+                retries_left -= 1;
+                budget /= 2;
+            },
+            inference => break inference,
+        }
+    };
 
     match inference {
-        Ok(response) => {
-            let PlainText(content) = response.value;
+        Ok(content) => {
             Ok(
                 create_message(
                     &state.pool,
                     Some(state.self_user.id),
                     thread_id,
-                    None,
-                    content.deref(),
+                    Some(message_id),
+                    Some(reply_to),
+                    &content,
                 )
                     .await?
             )
@@ -341,6 +503,7 @@ async fn respond_to_thread(
                 None,
                 thread_id,
                 None,
+                Some(reply_to),
                 &e.to_string(), //
             )
                 .await?
@@ -351,7 +514,7 @@ async fn respond_to_thread(
 #[message_handler(ChatService)]
 async fn fetch_user_threads(
     state: &State,
-    FetchUserThreadsRequest { from_user_id }: FetchUserThreadsRequest,
+    FetchUserThreadsRequest { from_user_id, before }: FetchUserThreadsRequest,
 ) -> service::Result<FetchUserThreadsResponse> {
     let user = sqlx::query_as!(
         User,
@@ -373,13 +536,22 @@ async fn fetch_user_threads(
         FROM threads t
         INNER JOIN thread_participants tp ON t.id = tp.thread_id 
         WHERE tp.user_id = $1
-        ORDER BY t.updated_at DESC
+            AND ($2::timestamptz IS NULL OR (t.created_at, t.id) < ($2, $3))
+        ORDER BY t.created_at DESC, t.id DESC
+        LIMIT $4
         "#,
         from_user_id,
+        before.map(|cursor| cursor.at),
+        before.map(|cursor| cursor.id),
+        THREAD_PAGE_SIZE as i64 + 1,
     )
         .fetch_all(&state.pool)
         .await
         .map_err(|_| service::Error::NotFound)?;
+    let (threads, next_page) = paginate(threads, THREAD_PAGE_SIZE, |thread| PageCursor {
+        at: thread.created_at,
+        id: thread.id,
+    });
 
     Ok(FetchUserThreadsResponse {
         users: vec![SyncUpdate::Updated(user)],
@@ -390,6 +562,7 @@ async fn fetch_user_threads(
                 .map(|t| OneToManyChild::Value(t))
                 .collect(),
         }],
+        next_page,
     })
 }
 
@@ -399,22 +572,32 @@ async fn fetch_thread_messages(
     FetchThreadRequest {
         from_user_id,
         thread_id,
+        before,
     }: FetchThreadRequest,
 ) -> service::Result<FetchThreadResponse> {
     let thread = fetch_thread_for_user(state, from_user_id, thread_id).await?;
     let messages = sqlx::query_as!(
         ChatMessage,
         r#"--sql
-            SELECT id, thread_id, user_id, content, created_at, updated_at
+            SELECT id, thread_id, user_id, parent_id, content, created_at, updated_at
             FROM messages
-            WHERE thread_id = $1
-            ORDER BY created_at ASC
+            WHERE thread_id = $1 AND deleted_at IS NULL
+                AND ($2::timestamptz IS NULL OR (created_at, id) < ($2, $3))
+            ORDER BY created_at DESC, id DESC
+            LIMIT $4
         "#,
         thread_id,
+        before.map(|cursor| cursor.at),
+        before.map(|cursor| cursor.id),
+        MESSAGE_PAGE_SIZE as i64 + 1,
     )
         .fetch_all(&state.pool)
         .await
         .into_service_result()?;
+    let (messages, next_page) = paginate(messages, MESSAGE_PAGE_SIZE, |message| PageCursor {
+        at: message.created_at,
+        id: message.id,
+    });
 
     Ok(FetchThreadResponse {
         threads: vec![SyncUpdate::Updated(thread)],
@@ -422,55 +605,86 @@ async fn fetch_thread_messages(
             owner_id: thread_id,
             children: messages
                 .into_iter()
+                .rev()
                 .map(|m| OneToManyChild::Value(m))
                 .collect(),
         }],
+        next_page,
     })
 }
 
 #[message_handler(ChatService)]
 async fn chat(
-    state: &State,
+    state: Arc<State>,
     request: SendMessageRequest,
 ) -> service::Result<SendMessageResponse> {
     let from_user_id = request.from_user_id;
     let thread_id = request.message.thread_id;
+    // Clients retry sends whose response got lost, those only need the response again
+    let sent_message = sqlx::query_as!(
+        ChatMessage,
+        r#"--sql
+        SELECT id, thread_id, user_id, parent_id, content, created_at, updated_at
+        FROM messages
+        WHERE id = $1 AND thread_id = $2 AND user_id = $3
+        "#,
+        request.message.id,
+        thread_id,
+        from_user_id,
+    )
+        .fetch_optional(&state.pool)
+        .await
+        .into_service_result()?;
+    if let Some(user_message) = sent_message {
+        let thread = fetch_thread_for_user(&state, from_user_id, thread_id).await?;
+        return Ok(sent_message_response(thread, user_message));
+    }
     if request.is_new_thread {
         create_thread(&state.pool, from_user_id, thread_id).await?;
     }
-    let (user_message, _) = create_message(
+    let (user_message, thread) = create_message(
         &state.pool,
         Some(from_user_id),
         thread_id,
         Some(request.message.id),
+        request.message.parent_id,
         &request.message.content,
     )
         .await?;
-    let (ai_message, thread) = respond_to_thread(&state, thread_id).await?;
-    let thread = if request.is_new_thread {
-        generate_thread_name(&state, thread_id).await?
-    } else {
-        thread
-    };
-    let threads = vec![SyncUpdate::Updated(thread)];
-    let thread_messages = OneToManyUpdate {
-        owner_id: thread_id,
-        children: get_thread_message_ids(&state.pool, thread_id)
-            .await?
-            .into_iter()
-            .map(|id| {
-                if id == user_message.id {
-                    OneToManyChild::Value(user_message.clone())
-                } else if id == ai_message.id {
-                    OneToManyChild::Value(ai_message.clone())
-                } else {
-                    OneToManyChild::Id(id)
-                }
-            })
-            .collect::<Vec<_>>(),
-    };
-    Ok(SendMessageResponse {
-        threads,
-        thread_messages: vec![thread_messages],
-    })
+    publish_thread(&state, &thread).await?;
+    publish_message(&state, &user_message).await?;
+    let utc_offset = request
+        .utc_offset_minutes
+        .and_then(|minutes| time::UtcOffset::from_whole_seconds(i32::from(minutes) * 60).ok())
+        .or_else(|| time::UtcOffset::current_local_offset().ok())
+        .unwrap_or(time::UtcOffset::UTC);
+    replies::schedule_reply(state.clone(), user_message.clone(), utc_offset);
+    Ok(sent_message_response(thread, user_message))
+}
+
+fn sent_message_response(thread: Thread, user_message: ChatMessage) -> SendMessageResponse {
+    SendMessageResponse {
+        thread_messages: vec![OneToManyUpdate {
+            owner_id: thread.id,
+            children: vec![OneToManyChild::Value(user_message)],
+        }],
+        threads: vec![SyncUpdate::Updated(thread)],
+        next_page: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cursor(id: &u128) -> PageCursor {
+        PageCursor { at: time::OffsetDateTime::UNIX_EPOCH, id: Uuid::from_u128(*id) }
+    }
+
+    #[test]
+    fn paginates_with_a_spare_row() {
+        assert_eq!(paginate(vec![3, 2, 1], 3, cursor), (vec![3, 2, 1], None));
+        assert_eq!(paginate(vec![4, 3, 2, 1], 3, cursor), (vec![4, 3, 2], Some(cursor(&2))));
+        assert_eq!(paginate(Vec::new(), 3, cursor), (Vec::new(), None));
+    }
 }
@@ -0,0 +1,119 @@
+use uuid::Uuid;
+
+use super::actor::State;
+use crate::{
+    actuators::chat::dto::ChatMessage,
+    infer::Embedding,
+};
+
+/// Newest embedded messages compared per search, since the comparison happens in Rust
+const SEARCH_CANDIDATE_LIMIT: i64 = 2000;
+
+/// Below this, a message is considered unrelated no matter how it ranks
+const MIN_SIMILARITY: f32 = 0.5;
+
+pub struct RelatedMessage {
+    pub user_name: Option<String>,
+    pub content: String,
+    pub created_at: time::OffsetDateTime,
+}
+
+/// Embeds the messages for later searches. Backends without embeddings are skipped silently.
+pub async fn store_message_embeddings(state: &State, messages: &[&ChatMessage]) -> anyhow::Result<()> {
+    let client = state.system_prompt.client();
+    let inputs = messages.iter().map(|message| message.content.as_str()).collect::<Vec<_>>();
+    let embeddings = match client.embed(&inputs).await {
+        Ok(embeddings) => embeddings,
+        Err(error) if error.is_unsupported() => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+    let model = client.model().profile.embedding_model_name();
+    for (message, embedding) in messages.iter().zip(embeddings) {
+        sqlx::query!(
+            r#"--sql
+            INSERT INTO message_embeddings (message_id, model, embedding)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (message_id) DO UPDATE
+            SET model = $2, embedding = $3, created_at = CURRENT_TIMESTAMP
+            "#,
+            message.id,
+            model,
+            embedding.as_slice(),
+        )
+            .execute(&state.pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Messages from other threads that are semantically close to `query`.
+///
+/// Only threads whose participants all take part in `thread_id` are searched,
+/// so nothing said elsewhere reaches people who weren't there.
+pub async fn find_related_messages(
+    state: &State,
+    thread_id: Uuid,
+    query: &str,
+    limit: usize,
+) -> anyhow::Result<Vec<RelatedMessage>> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    let client = state.system_prompt.client();
+    let query_embedding = match client.embed(&[query]).await {
+        Ok(embeddings) => match embeddings.into_iter().next() {
+            Some(embedding) => embedding,
+            None => return Ok(Vec::new()),
+        },
+        Err(error) if error.is_unsupported() => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let rows = sqlx::query!(
+        r#"--sql
+        SELECT
+            users.name AS "user_name?",
+            messages.content,
+            messages.created_at,
+            message_embeddings.embedding
+        FROM message_embeddings
+        INNER JOIN messages ON messages.id = message_embeddings.message_id
+        LEFT JOIN users ON users.id = messages.user_id
+        WHERE message_embeddings.model = $1
+            AND messages.thread_id <> $2
+            AND messages.deleted_at IS NULL
+            AND NOT EXISTS (
+                SELECT 1
+                FROM thread_participants other
+                WHERE other.thread_id = messages.thread_id
+                    AND other.user_id NOT IN (
+                        SELECT user_id FROM thread_participants WHERE thread_id = $2
+                    )
+            )
+        ORDER BY messages.created_at DESC
+        LIMIT $3
+        "#,
+        client.model().profile.embedding_model_name(),
+        thread_id,
+        SEARCH_CANDIDATE_LIMIT,
+    )
+        .fetch_all(&state.pool)
+        .await?;
+
+    let mut candidates = Vec::with_capacity(rows.len());
+    let mut embeddings = Vec::with_capacity(rows.len());
+    for row in rows {
+        candidates.push(RelatedMessage {
+            user_name: row.user_name,
+            content: row.content,
+            created_at: row.created_at,
+        });
+        embeddings.push(Embedding::from(row.embedding));
+    }
+    Ok(query_embedding
+        .most_similar(candidates.into_iter().zip(&embeddings), limit)
+        .into_iter()
+        .filter(|(_, similarity)| *similarity >= MIN_SIMILARITY)
+        .map(|(message, _)| message)
+        .collect())
+}
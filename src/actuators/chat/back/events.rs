@@ -0,0 +1,92 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::actor::{ChatService, State};
+use crate::{
+    actuators::chat::dto::ChatEvent,
+    service::{self, CoercibleResult},
+};
+
+const CHANNEL_CAPACITY: usize = 256;
+
+pub type EventReceiver = broadcast::Receiver<Arc<ChatEvent>>;
+
+/// Per-user broadcast channels feeding the `/events` streams.
+#[derive(Default)]
+pub struct EventHub {
+    channels: Mutex<HashMap<Uuid, broadcast::Sender<Arc<ChatEvent>>>>,
+}
+
+impl EventHub {
+    pub fn subscribe(&self, user_id: Uuid) -> EventReceiver {
+        let mut channels = self.channels.lock().unwrap();
+        channels
+            .entry(user_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub fn publish(&self, user_ids: &[Uuid], event: ChatEvent) {
+        let event = Arc::new(event);
+        let mut channels = self.channels.lock().unwrap();
+        for user_id in user_ids {
+            if let Some(sender) = channels.get(user_id)
+                && sender.send(event.clone()).is_err()
+            {
+                // Nobody is listening anymore
+                channels.remove(user_id);
+            }
+        }
+    }
+
+    pub async fn publish_to_thread(
+        &self,
+        pool: &PgPool,
+        thread_id: Uuid,
+        event: ChatEvent,
+    ) -> service::Result<()> {
+        let user_ids = fetch_thread_participant_ids(pool, thread_id).await?;
+        self.publish(&user_ids, event);
+        Ok(())
+    }
+}
+
+pub async fn fetch_thread_participant_ids(
+    pool: &PgPool,
+    thread_id: Uuid,
+) -> service::Result<Vec<Uuid>> {
+    let participants = sqlx::query!(
+        r#"--sql
+        SELECT user_id
+        FROM thread_participants
+        WHERE thread_id = $1
+        "#,
+        thread_id,
+    )
+        .fetch_all(pool)
+        .await
+        .into_service_result()?;
+    Ok(participants.into_iter().map(|p| p.user_id).collect())
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "service::Result<EventReceiver>")]
+pub struct SubscribeEventsRequest {
+    pub from_user_id: Uuid,
+}
+
+#[message_handler(ChatService)]
+async fn subscribe_events(
+    state: &State,
+    SubscribeEventsRequest { from_user_id }: SubscribeEventsRequest,
+) -> service::Result<EventReceiver> {
+    Ok(state.events.subscribe(from_user_id))
+}
@@ -1,29 +1,47 @@
 use actix::prelude::*;
 use axum::{
     Json, Router,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::{HeaderValue, Method},
-    routing::{get, post},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{delete, get, patch, post},
 };
-use axum_extra::TypedHeader;
-use headers::authorization::{Authorization, Bearer};
-use std::sync::Arc;
+use axum_macros::FromRef;
+use futures_util::{Stream, stream};
+use serde::Deserialize;
+use std::{convert::Infallible, sync::Arc};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::error::RecvError;
 use tower_http::cors::CorsLayer;
 use uuid::Uuid;
 
 use crate::actuators::chat::dto::{
-    FetchThreadRequest, FetchThreadResponse, FetchUserThreadsRequest, FetchUserThreadsResponse,
-    SendMessageRequest, SendMessageResponse,
+    CancelReminderRequest, CancelReminderResponse, DeleteMemoryRequest, DeleteMemoryResponse,
+    DeleteMessageRequest, DeleteMessageResponse, EditMessageRequest, EditMessageResponse,
+    FetchMemoriesRequest, FetchMemoriesResponse, FetchParticipantsRequest, FetchParticipantsResponse,
+    FetchRemindersRequest, FetchRemindersResponse, FetchThreadRequest, FetchThreadResponse,
+    FetchUserThreadsRequest, FetchUserThreadsResponse, InviteParticipantRequest,
+    InviteParticipantResponse, LeaveThreadRequest, LeaveThreadResponse, PageCursor, RegenerateReplyRequest,
+    RegenerateReplyResponse, RemoveParticipantRequest, RemoveParticipantResponse, SearchRequest,
+    SearchResponse, SendMessageRequest, SendMessageResponse, SyncRequest, SyncResponse,
+    TransferOwnershipRequest, TransferOwnershipResponse,
 };
+use crate::auth::client::{AuthClient, SessionUser};
 use crate::service;
 
-use super::actor::ChatService;
+use super::{actor::ChatService, events::SubscribeEventsRequest};
 
-pub fn build_router(state: Arc<Addr<ChatService>>) -> Router {
+#[derive(Clone, FromRef)]
+struct RouterState {
+    service: Arc<Addr<ChatService>>,
+    auth: AuthClient,
+}
+
+pub fn build_router(service: Arc<Addr<ChatService>>, auth: AuthClient) -> Router {
     // Configure CORS
     let cors = CorsLayer::new()
         .allow_origin("*".parse::<HeaderValue>().unwrap())
-        .allow_methods([Method::GET, Method::POST])
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
         .allow_headers([http::header::AUTHORIZATION, http::header::CONTENT_TYPE]);
 
     // Build router
@@ -31,8 +49,27 @@ pub fn build_router(state: Arc<Addr<ChatService>>) -> Router {
         .route("/chats", get(fetch_user_threads_handler))
         .route("/chat/{thread_id}", get(fetch_thread_handler))
         .route("/chat", post(chat_handler))
+        .route(
+            "/chat/{thread_id}/participants",
+            get(fetch_participants_handler).post(invite_participant_handler),
+        )
+        .route("/chat/{thread_id}/participant/{user_id}", delete(remove_participant_handler))
+        .route("/chat/{thread_id}/leave", post(leave_thread_handler))
+        .route("/chat/{thread_id}/owner", post(transfer_ownership_handler))
+        .route(
+            "/chat/{thread_id}/message/{message_id}",
+            patch(edit_message_handler).delete(delete_message_handler),
+        )
+        .route("/chat/{thread_id}/message/{message_id}/regenerate", post(regenerate_reply_handler))
+        .route("/events", get(events_handler))
+        .route("/memories", get(fetch_memories_handler))
+        .route("/memory/{memory_id}", delete(delete_memory_handler))
+        .route("/reminders", get(fetch_reminders_handler))
+        .route("/reminder/{reminder_id}", delete(cancel_reminder_handler))
+        .route("/search", get(search_handler))
+        .route("/sync", get(sync_handler))
         .layer(cors)
-        .with_state(state)
+        .with_state(RouterState { service, auth })
 }
 
 fn map_service_response<T>(
@@ -54,32 +91,214 @@ fn map_service_response<T>(
     }
 }
 
+/// `?before_at=…&before_id=…` continues a list after the last item of the previous page.
+#[derive(Deserialize)]
+pub struct PageParams {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    before_at: Option<OffsetDateTime>,
+    before_id: Option<Uuid>,
+}
+
+impl PageParams {
+    fn before(&self) -> Option<PageCursor> {
+        Some(PageCursor { at: self.before_at?, id: self.before_id? })
+    }
+}
+
 pub async fn fetch_user_threads_handler(
     State(service): State<Arc<Addr<ChatService>>>,
-    auth_header: TypedHeader<Authorization<Bearer>>,
+    SessionUser(from_user_id): SessionUser,
+    Query(page): Query<PageParams>,
 ) -> service::Result<Json<FetchUserThreadsResponse>> {
-    let from_user_id = Uuid::parse_str(&auth_header.token()).map_err(|_| service::Error::Unauthorized)?;
-    map_service_response(service.send(FetchUserThreadsRequest { from_user_id }).await)
+    map_service_response(service.send(FetchUserThreadsRequest { from_user_id, before: page.before() }).await)
 }
 
 pub async fn fetch_thread_handler(
     State(service): State<Arc<Addr<ChatService>>>,
-    auth_header: TypedHeader<Authorization<Bearer>>,
+    SessionUser(from_user_id): SessionUser,
     Path(thread_id): Path<Uuid>,
+    Query(page): Query<PageParams>,
 ) -> service::Result<Json<FetchThreadResponse>> {
-    let from_user_id = Uuid::parse_str(&auth_header.token()).map_err(|_| service::Error::Unauthorized)?;
-    map_service_response(service.send(FetchThreadRequest { from_user_id, thread_id }).await)
+    map_service_response(service.send(FetchThreadRequest { from_user_id, thread_id, before: page.before() }).await)
 }
 
 pub async fn chat_handler(
     State(service): State<Arc<Addr<ChatService>>>,
-    auth_header: TypedHeader<Authorization<Bearer>>,
+    SessionUser(from_user_id): SessionUser,
     Json(request): Json<SendMessageRequest>,
 ) -> service::Result<Json<SendMessageResponse>> {
-    let from_user_id = Uuid::parse_str(&auth_header.token()).map_err(|_| service::Error::Unauthorized)?;
     if from_user_id != request.from_user_id {
         Err(service::Error::Unauthorized)
     } else {
         map_service_response(service.send(request).await)
     }
 }
+
+pub async fn edit_message_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path((thread_id, message_id)): Path<(Uuid, Uuid)>,
+    Json(request): Json<EditMessageRequest>,
+) -> service::Result<Json<EditMessageResponse>> {
+    if from_user_id != request.from_user_id || thread_id != request.thread_id || message_id != request.message_id {
+        Err(service::Error::Unauthorized)
+    } else {
+        map_service_response(service.send(request).await)
+    }
+}
+
+pub async fn delete_message_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path((thread_id, message_id)): Path<(Uuid, Uuid)>,
+) -> service::Result<Json<DeleteMessageResponse>> {
+    map_service_response(
+        service
+            .send(DeleteMessageRequest { from_user_id, thread_id, message_id })
+            .await,
+    )
+}
+
+pub async fn regenerate_reply_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path((thread_id, message_id)): Path<(Uuid, Uuid)>,
+) -> service::Result<Json<RegenerateReplyResponse>> {
+    map_service_response(
+        service
+            .send(RegenerateReplyRequest { from_user_id, thread_id, message_id })
+            .await,
+    )
+}
+
+pub async fn fetch_participants_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path(thread_id): Path<Uuid>,
+) -> service::Result<Json<FetchParticipantsResponse>> {
+    map_service_response(service.send(FetchParticipantsRequest { from_user_id, thread_id }).await)
+}
+
+pub async fn invite_participant_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path(thread_id): Path<Uuid>,
+    Json(request): Json<InviteParticipantRequest>,
+) -> service::Result<Json<InviteParticipantResponse>> {
+    if from_user_id != request.from_user_id || thread_id != request.thread_id {
+        Err(service::Error::Unauthorized)
+    } else {
+        map_service_response(service.send(request).await)
+    }
+}
+
+pub async fn remove_participant_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path((thread_id, user_id)): Path<(Uuid, Uuid)>,
+) -> service::Result<Json<RemoveParticipantResponse>> {
+    map_service_response(
+        service
+            .send(RemoveParticipantRequest { from_user_id, thread_id, user_id })
+            .await,
+    )
+}
+
+pub async fn leave_thread_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path(thread_id): Path<Uuid>,
+) -> service::Result<Json<LeaveThreadResponse>> {
+    map_service_response(service.send(LeaveThreadRequest { from_user_id, thread_id }).await)
+}
+
+pub async fn transfer_ownership_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path(thread_id): Path<Uuid>,
+    Json(request): Json<TransferOwnershipRequest>,
+) -> service::Result<Json<TransferOwnershipResponse>> {
+    if from_user_id != request.from_user_id || thread_id != request.thread_id {
+        Err(service::Error::Unauthorized)
+    } else {
+        map_service_response(service.send(request).await)
+    }
+}
+
+pub async fn fetch_memories_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+) -> service::Result<Json<FetchMemoriesResponse>> {
+    map_service_response(service.send(FetchMemoriesRequest { from_user_id }).await)
+}
+
+pub async fn delete_memory_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path(memory_id): Path<Uuid>,
+) -> service::Result<Json<DeleteMemoryResponse>> {
+    map_service_response(service.send(DeleteMemoryRequest { from_user_id, memory_id }).await)
+}
+
+pub async fn fetch_reminders_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+) -> service::Result<Json<FetchRemindersResponse>> {
+    map_service_response(service.send(FetchRemindersRequest { from_user_id }).await)
+}
+
+pub async fn cancel_reminder_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Path(reminder_id): Path<Uuid>,
+) -> service::Result<Json<CancelReminderResponse>> {
+    map_service_response(service.send(CancelReminderRequest { from_user_id, reminder_id }).await)
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    q: String,
+}
+
+pub async fn search_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Query(SearchParams { q }): Query<SearchParams>,
+) -> service::Result<Json<SearchResponse>> {
+    map_service_response(service.send(SearchRequest { from_user_id, query: q }).await)
+}
+
+#[derive(Deserialize)]
+pub struct SyncParams {
+    since: Option<i64>,
+}
+
+pub async fn sync_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+    Query(SyncParams { since }): Query<SyncParams>,
+) -> service::Result<Json<SyncResponse>> {
+    map_service_response(service.send(SyncRequest { from_user_id, since }).await)
+}
+
+pub async fn events_handler(
+    State(service): State<Arc<Addr<ChatService>>>,
+    SessionUser(from_user_id): SessionUser,
+) -> service::Result<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let receiver = service.send(SubscribeEventsRequest { from_user_id }).await??;
+    let events = stream::unfold(receiver, move |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => match Event::default().json_data(&*event) {
+                    Ok(event) => return Some((Ok(event), receiver)),
+                    Err(error) => tracing::error!("Failed to serialize chat event: {:?}", error),
+                },
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Event stream for {from_user_id} lagged, skipped {skipped} events");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,193 @@
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use serde::Deserialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::actor::{ChatService, State};
+use crate::{
+    actuators::chat::dto::{
+        ChatMessage, DeleteMemoryRequest, DeleteMemoryResponse, FetchMemoriesRequest,
+        FetchMemoriesResponse, Memory, SyncUpdate,
+    },
+    infer::{self, FromLlmReply, JsonType, ParseError, find_and_parse_json},
+    service::{self, CoercibleResult},
+};
+
+/// How many memories go into a prompt at most
+const RECALLED_MEMORY_COUNT: i64 = 8;
+
+#[derive(FromLlmReply, Deserialize)]
+struct ExtractedFacts {
+    facts: Vec<Box<str>>,
+}
+
+pub struct RecalledMemory {
+    pub user_name: String,
+    pub content: String,
+}
+
+/// Finds memories of the thread's participants sharing words with `query`, best matches first.
+pub async fn recall(pool: &PgPool, thread_id: Uuid, query: &str) -> service::Result<Vec<RecalledMemory>> {
+    let query = search_query(query);
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+    sqlx::query_as!(
+        RecalledMemory,
+        r#"--sql
+        SELECT users.name AS user_name, memories.content
+        FROM memories
+        INNER JOIN thread_participants tp
+            ON tp.user_id = memories.user_id AND tp.thread_id = $1
+        INNER JOIN users ON users.id = memories.user_id
+        WHERE memories.search_vector @@ websearch_to_tsquery('simple', $2)
+        ORDER BY ts_rank(memories.search_vector, websearch_to_tsquery('simple', $2)) DESC
+        LIMIT $3
+        "#,
+        thread_id,
+        query,
+        RECALLED_MEMORY_COUNT,
+    )
+        .fetch_all(pool)
+        .await
+        .into_service_result()
+}
+
+/// Turns free text into a `websearch_to_tsquery` query matching any of its words.
+fn search_query(text: &str) -> String {
+    let mut words = text
+        .split(|c: char| !c.is_alphanumeric())
+        // Short words are mostly particles and pronouns
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    words.sort();
+    words.dedup();
+    words.join(" or ")
+}
+
+/// Asks the model for lasting facts in `message` and stores them as memories of its author.
+pub async fn remember_facts(state: &State, message: &ChatMessage) -> anyhow::Result<()> {
+    let Some(user_id) = message.user_id else {
+        return Ok(());
+    };
+    if user_id == state.self_user.id {
+        return Ok(());
+    }
+    let user_name = sqlx::query_scalar!(
+        r#"--sql
+        SELECT name FROM users WHERE id = $1
+        "#,
+        user_id,
+    )
+        .fetch_one(&state.pool)
+        .await?;
+
+    let (user_name, content) = (&user_name, &message.content);
+    let ExtractedFacts { facts } = state.system_prompt
+        .fork()
+        .with_utility_model()
+        .with_message(infer::Message::new_text_user(markup::new! {
+            message [from = user_name] {
+                @content
+            }
+            systemInstructions {
+                "List the facts from the message above that are worth remembering in future conversations: "
+                "the author's preferences, plans, relationships, biographical details, or things about the world. "
+                "Skip small talk, questions and anything that only matters right now. "
+                "Write each fact as a self-contained sentence that names the author. "
+                "With no preamble, respond with a JSON object in the following format: "
+                "{\"facts\": [fact, ...]}, with an empty array if there is nothing worth remembering."
+            }
+        }.to_string()))
+        .infer_drop::<ExtractedFacts>(false)
+        .await?
+        .value;
+
+    for fact in facts.iter().map(|fact| fact.trim()).filter(|fact| !fact.is_empty()) {
+        sqlx::query!(
+            r#"--sql
+            INSERT INTO memories (user_id, thread_id, content)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, content) DO NOTHING
+            "#,
+            user_id,
+            message.thread_id,
+            fact,
+        )
+            .execute(&state.pool)
+            .await?;
+    }
+    if !facts.is_empty() {
+        tracing::info!("Remembered {} facts about {user_name}", facts.len());
+    }
+    Ok(())
+}
+
+#[message_handler(ChatService)]
+async fn fetch_memories(
+    state: &State,
+    FetchMemoriesRequest { from_user_id }: FetchMemoriesRequest,
+) -> service::Result<FetchMemoriesResponse> {
+    let memories = sqlx::query_as!(
+        Memory,
+        r#"--sql
+        SELECT id, user_id, thread_id, content, created_at
+        FROM memories
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        from_user_id,
+    )
+        .fetch_all(&state.pool)
+        .await
+        .into_service_result()?;
+    Ok(FetchMemoriesResponse {
+        memories: memories.into_iter().map(SyncUpdate::Updated).collect(),
+    })
+}
+
+#[message_handler(ChatService)]
+async fn delete_memory(
+    state: &State,
+    DeleteMemoryRequest { from_user_id, memory_id }: DeleteMemoryRequest,
+) -> service::Result<DeleteMemoryResponse> {
+    // Users may only forget what was learned from them
+    let memory_id = sqlx::query_scalar!(
+        r#"--sql
+        DELETE FROM memories
+        WHERE id = $1 AND user_id = $2
+        RETURNING id
+        "#,
+        memory_id,
+        from_user_id,
+    )
+        .fetch_optional(&state.pool)
+        .await
+        .into_service_result()?
+        .ok_or(service::Error::NotFound)?;
+    Ok(DeleteMemoryResponse {
+        memories: vec![SyncUpdate::Deleted(memory_id)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_or_query_from_words() {
+        assert_eq!(search_query("Is my cat's vet, Dr. Ng, open on Sunday? cat!"), "cat or open or sunday or vet");
+        assert_eq!(search_query("ok?"), "");
+    }
+
+    #[test]
+    fn parses_extracted_facts() {
+        let ExtractedFacts { facts } = ExtractedFacts::from_reply(
+            "Sure!\n```json\n{\"facts\": [\"Ann has a cat named Tom.\"]}\n```",
+        )
+        .unwrap();
+        assert_eq!(facts, vec!["Ann has a cat named Tom.".into()]);
+    }
+}
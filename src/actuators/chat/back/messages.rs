@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use uuid::Uuid;
+
+use super::{
+    actor::{ChatService, State, fetch_thread_for_user},
+    embeddings, replies,
+};
+use crate::{
+    actuators::chat::dto::{
+        ChatEvent, ChatMessage, DeleteMessageRequest, DeleteMessageResponse, EditMessageRequest,
+        EditMessageResponse, RegenerateReplyRequest, RegenerateReplyResponse, SyncUpdate,
+    },
+    service::{self, CoercibleResult},
+};
+
+async fn fetch_message(state: &State, thread_id: Uuid, message_id: Uuid) -> service::Result<ChatMessage> {
+    sqlx::query_as!(
+        ChatMessage,
+        r#"--sql
+        SELECT id, thread_id, user_id, parent_id, content, created_at, updated_at
+        FROM messages
+        WHERE id = $1 AND thread_id = $2 AND deleted_at IS NULL
+        "#,
+        message_id,
+        thread_id,
+    )
+        .fetch_optional(&state.pool)
+        .await
+        .into_service_result()?
+        .ok_or(service::Error::NotFound)
+}
+
+/// The message, if `user_id` is a participant of its thread and wrote it.
+async fn fetch_own_message(
+    state: &State,
+    user_id: Uuid,
+    thread_id: Uuid,
+    message_id: Uuid,
+) -> service::Result<ChatMessage> {
+    fetch_thread_for_user(state, user_id, thread_id).await?;
+    let message = fetch_message(state, thread_id, message_id).await?;
+    if message.user_id != Some(user_id) {
+        return Err(service::Error::Forbidden);
+    }
+    Ok(message)
+}
+
+async fn publish_messages(
+    state: &State,
+    thread_id: Uuid,
+    messages: Vec<SyncUpdate<ChatMessage>>,
+) -> service::Result<EditMessageResponse> {
+    let event = ChatEvent::Messages(messages.iter().map(|update| match update {
+        SyncUpdate::Updated(message) => SyncUpdate::Updated(message.clone()),
+        SyncUpdate::Deleted(id) => SyncUpdate::Deleted(*id),
+    }).collect());
+    state.events.publish_to_thread(&state.pool, thread_id, event).await?;
+    Ok(EditMessageResponse { messages })
+}
+
+#[message_handler(ChatService)]
+async fn edit_message(
+    state: &State,
+    EditMessageRequest {
+        from_user_id,
+        thread_id,
+        message_id,
+        content,
+    }: EditMessageRequest,
+) -> service::Result<EditMessageResponse> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(service::Error::BadRequest("A message can't be empty, delete it instead".into()));
+    }
+    fetch_own_message(state, from_user_id, thread_id, message_id).await?;
+    let message = sqlx::query_as!(
+        ChatMessage,
+        r#"--sql
+        UPDATE messages SET content = $1, updated_at = CURRENT_TIMESTAMP, change_seq = nextval('change_seq'), change_xid = DEFAULT
+        WHERE id = $2
+        RETURNING id, thread_id, user_id, parent_id, content, created_at, updated_at
+        "#,
+        content,
+        message_id,
+    )
+        .fetch_one(&state.pool)
+        .await
+        .into_service_result()?;
+    if let Err(error) = embeddings::store_message_embeddings(state, &[&message]).await {
+        tracing::warn!("Failed to embed message {message_id}: {error}");
+    }
+    publish_messages(state, thread_id, vec![SyncUpdate::Updated(message)]).await
+}
+
+#[message_handler(ChatService)]
+async fn delete_message(
+    state: &State,
+    DeleteMessageRequest {
+        from_user_id,
+        thread_id,
+        message_id,
+    }: DeleteMessageRequest,
+) -> service::Result<DeleteMessageResponse> {
+    let message = fetch_own_message(state, from_user_id, thread_id, message_id).await?;
+    let mut tx = state.pool.begin().await.into_service_result()?;
+    sqlx::query!(
+        r#"--sql
+        UPDATE messages SET deleted_at = CURRENT_TIMESTAMP, change_seq = nextval('change_seq'), change_xid = DEFAULT
+        WHERE id = $1
+        "#,
+        message_id,
+    )
+        .execute(&mut *tx)
+        .await
+        .into_service_result()?;
+    // The answers to the message now answer what it answered, so the branch stays connected
+    let children = sqlx::query_as!(
+        ChatMessage,
+        r#"--sql
+        UPDATE messages SET parent_id = $2, change_seq = nextval('change_seq'), change_xid = DEFAULT
+        WHERE parent_id = $1 AND deleted_at IS NULL
+        RETURNING id, thread_id, user_id, parent_id, content, created_at, updated_at
+        "#,
+        message_id,
+        message.parent_id,
+    )
+        .fetch_all(&mut *tx)
+        .await
+        .into_service_result()?;
+    tx.commit().await.into_service_result()?;
+    let updates = std::iter::once(SyncUpdate::Deleted(message_id))
+        .chain(children.into_iter().map(SyncUpdate::Updated))
+        .collect();
+    publish_messages(state, thread_id, updates).await
+}
+
+#[message_handler(ChatService)]
+async fn regenerate_reply(
+    state: Arc<State>,
+    RegenerateReplyRequest {
+        from_user_id,
+        thread_id,
+        message_id,
+    }: RegenerateReplyRequest,
+) -> service::Result<RegenerateReplyResponse> {
+    fetch_thread_for_user(&state, from_user_id, thread_id).await?;
+    let message = fetch_message(&state, thread_id, message_id).await?;
+    let self_id = state.self_user.id;
+    if message.user_id.is_none_or(|user_id| user_id == self_id) {
+        return Err(service::Error::BadRequest("Only replies to someone's message can be regenerated".into()));
+    }
+    let descendants = sqlx::query!(
+        r#"--sql
+        WITH RECURSIVE descendants AS (
+            SELECT id, user_id
+            FROM messages
+            WHERE parent_id = $1 AND deleted_at IS NULL
+            UNION ALL
+            SELECT messages.id, messages.user_id
+            FROM messages
+            INNER JOIN descendants ON messages.parent_id = descendants.id
+            WHERE messages.deleted_at IS NULL
+        )
+        SELECT id AS "id!", user_id FROM descendants
+        "#,
+        message_id,
+    )
+        .fetch_all(&state.pool)
+        .await
+        .into_service_result()?;
+    // Someone answering the reply would lose their message, or start their own branch instead
+    if descendants.iter().any(|message| message.user_id.is_some_and(|user_id| user_id != self_id)) {
+        return Err(service::Error::BadRequest(
+            "Only a reply nobody has answered yet can be regenerated, branch from the message instead".into(),
+        ));
+    }
+
+    // Failed attempts have no author, and go as well
+    let discarded_ids = descendants.into_iter().map(|message| message.id).collect::<Vec<_>>();
+    sqlx::query!(
+        r#"--sql
+        UPDATE messages SET deleted_at = CURRENT_TIMESTAMP, change_seq = nextval('change_seq'), change_xid = DEFAULT
+        WHERE id = ANY($1)
+        "#,
+        &discarded_ids,
+    )
+        .execute(&state.pool)
+        .await
+        .into_service_result()?;
+    let response = publish_messages(
+        &state,
+        thread_id,
+        discarded_ids.into_iter().map(SyncUpdate::Deleted).collect(),
+    ).await?;
+    replies::reply_again(state, thread_id, message_id);
+    Ok(response)
+}
@@ -0,0 +1,216 @@
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::actor::{ChatService, State, fetch_thread_for_user};
+use crate::{
+    actuators::chat::dto::{
+        ChatEvent, FetchParticipantsRequest, FetchParticipantsResponse, InviteParticipantRequest,
+        InviteParticipantResponse, LeaveThreadRequest, LeaveThreadResponse, OneToManyChild,
+        OneToManyUpdate, RemoveParticipantRequest, RemoveParticipantResponse, SyncUpdate, Thread,
+        TransferOwnershipRequest, TransferOwnershipResponse, User,
+    },
+    service::{self, CoercibleResult},
+};
+
+pub async fn fetch_participants(pool: &PgPool, thread_id: Uuid) -> service::Result<Vec<User>> {
+    sqlx::query_as!(
+        User,
+        r#"--sql
+        SELECT users.id, users.name
+        FROM thread_participants tp
+        INNER JOIN users ON users.id = tp.user_id
+        WHERE tp.thread_id = $1
+        ORDER BY users.name
+        "#,
+        thread_id,
+    )
+        .fetch_all(pool)
+        .await
+        .into_service_result()
+}
+
+/// How many of the thread's participants aren't the artilect.
+pub async fn count_humans(state: &State, thread_id: Uuid) -> service::Result<i64> {
+    sqlx::query_scalar!(
+        r#"--sql
+        SELECT COUNT(*) AS "count!"
+        FROM thread_participants
+        WHERE thread_id = $1 AND user_id <> $2
+        "#,
+        thread_id,
+        state.self_user.id,
+    )
+        .fetch_one(&state.pool)
+        .await
+        .into_service_result()
+}
+
+fn participants_update(thread_id: Uuid, participants: &[User]) -> OneToManyUpdate<User> {
+    OneToManyUpdate {
+        owner_id: thread_id,
+        children: participants.iter().cloned().map(OneToManyChild::Value).collect(),
+    }
+}
+
+async fn participants_response(state: &State, thread: Thread) -> service::Result<FetchParticipantsResponse> {
+    let participants = fetch_participants(&state.pool, thread.id).await?;
+    Ok(FetchParticipantsResponse {
+        thread_participants: vec![participants_update(thread.id, &participants)],
+        threads: vec![SyncUpdate::Updated(thread)],
+    })
+}
+
+/// Tells everyone in the thread who is in it now, and responds with the same.
+async fn publish_participants(state: &State, thread: Thread) -> service::Result<FetchParticipantsResponse> {
+    let participants = fetch_participants(&state.pool, thread.id).await?;
+    let events = [
+        ChatEvent::Threads(vec![SyncUpdate::Updated(thread.clone())]),
+        ChatEvent::ThreadParticipants(vec![participants_update(thread.id, &participants)]),
+    ];
+    for event in events {
+        state.events.publish_to_thread(&state.pool, thread.id, event).await?;
+    }
+    Ok(FetchParticipantsResponse {
+        thread_participants: vec![participants_update(thread.id, &participants)],
+        threads: vec![SyncUpdate::Updated(thread)],
+    })
+}
+
+/// Drops the thread from the sidebar of someone who is no longer in it.
+fn publish_removal(state: &State, user_id: Uuid, thread_id: Uuid) {
+    state.events.publish(&[user_id], ChatEvent::Threads(vec![SyncUpdate::Deleted(thread_id)]));
+}
+
+/// Also records the departure, for syncing it to the user's other clients later.
+async fn remove_participant_row(pool: &PgPool, thread_id: Uuid, user_id: Uuid) -> service::Result<()> {
+    let deleted = sqlx::query!(
+        r#"--sql
+        WITH removed AS (
+            DELETE FROM thread_participants
+            WHERE thread_id = $1 AND user_id = $2
+            RETURNING thread_id, user_id
+        )
+        INSERT INTO thread_departures (thread_id, user_id)
+        SELECT thread_id, user_id FROM removed
+        ON CONFLICT (thread_id, user_id) DO UPDATE SET change_seq = nextval('change_seq'), change_xid = DEFAULT
+        "#,
+        thread_id,
+        user_id,
+    )
+        .execute(pool)
+        .await
+        .into_service_result()?;
+    match deleted.rows_affected() {
+        0 => Err(service::Error::NotFound),
+        _ => Ok(()),
+    }
+}
+
+#[message_handler(ChatService)]
+async fn fetch_thread_participants(
+    state: &State,
+    FetchParticipantsRequest { from_user_id, thread_id }: FetchParticipantsRequest,
+) -> service::Result<FetchParticipantsResponse> {
+    let thread = fetch_thread_for_user(state, from_user_id, thread_id).await?;
+    participants_response(state, thread).await
+}
+
+#[message_handler(ChatService)]
+async fn invite_participant(
+    state: &State,
+    InviteParticipantRequest { from_user_id, thread_id, user_id }: InviteParticipantRequest,
+) -> service::Result<InviteParticipantResponse> {
+    let thread = fetch_thread_for_user(state, from_user_id, thread_id).await?;
+    let user_exists = sqlx::query_scalar!(
+        r#"--sql
+        SELECT EXISTS (SELECT 1 FROM users WHERE id = $1) AS "exists!"
+        "#,
+        user_id,
+    )
+        .fetch_one(&state.pool)
+        .await
+        .into_service_result()?;
+    if !user_exists {
+        return Err(service::Error::BadRequest("There is no such user".into()));
+    }
+    sqlx::query!(
+        r#"--sql
+        INSERT INTO thread_participants (thread_id, user_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        thread_id,
+        user_id,
+    )
+        .execute(&state.pool)
+        .await
+        .into_service_result()?;
+    publish_participants(state, thread).await
+}
+
+#[message_handler(ChatService)]
+async fn remove_participant(
+    state: &State,
+    RemoveParticipantRequest { from_user_id, thread_id, user_id }: RemoveParticipantRequest,
+) -> service::Result<RemoveParticipantResponse> {
+    let thread = fetch_thread_for_user(state, from_user_id, thread_id).await?;
+    if thread.owner_id != from_user_id {
+        return Err(service::Error::Forbidden);
+    }
+    if user_id == from_user_id {
+        return Err(service::Error::BadRequest("Transfer the thread to someone else before leaving".into()));
+    }
+    remove_participant_row(&state.pool, thread_id, user_id).await?;
+    publish_removal(state, user_id, thread_id);
+    publish_participants(state, thread).await
+}
+
+#[message_handler(ChatService)]
+async fn leave_thread(
+    state: &State,
+    LeaveThreadRequest { from_user_id, thread_id }: LeaveThreadRequest,
+) -> service::Result<LeaveThreadResponse> {
+    let thread = fetch_thread_for_user(state, from_user_id, thread_id).await?;
+    if thread.owner_id == from_user_id {
+        return Err(service::Error::BadRequest("Transfer the thread to someone else before leaving".into()));
+    }
+    remove_participant_row(&state.pool, thread_id, from_user_id).await?;
+    publish_removal(state, from_user_id, thread_id);
+    publish_participants(state, thread).await?;
+    Ok(LeaveThreadResponse {
+        threads: vec![SyncUpdate::Deleted(thread_id)],
+        thread_participants: Vec::new(),
+    })
+}
+
+#[message_handler(ChatService)]
+async fn transfer_ownership(
+    state: &State,
+    TransferOwnershipRequest { from_user_id, thread_id, new_owner_id }: TransferOwnershipRequest,
+) -> service::Result<TransferOwnershipResponse> {
+    let thread = fetch_thread_for_user(state, from_user_id, thread_id).await?;
+    if thread.owner_id != from_user_id {
+        return Err(service::Error::Forbidden);
+    }
+    if new_owner_id == state.self_user.id {
+        return Err(service::Error::BadRequest("The artilect can't own threads".into()));
+    }
+    let thread = sqlx::query_as!(
+        Thread,
+        r#"--sql
+        UPDATE threads SET owner_id = $2, change_seq = nextval('change_seq'), change_xid = DEFAULT
+        WHERE id = $1
+            AND EXISTS (SELECT 1 FROM thread_participants WHERE thread_id = $1 AND user_id = $2)
+        RETURNING id, name, owner_id, created_at, updated_at
+        "#,
+        thread_id,
+        new_owner_id,
+    )
+        .fetch_optional(&state.pool)
+        .await
+        .into_service_result()?
+        .ok_or_else(|| service::Error::BadRequest("The new owner has to be a participant".into()))?;
+    publish_participants(state, thread).await
+}
@@ -1,3 +1,5 @@
+pub mod context;
 pub mod message_log;
-pub use message_log::message_log;
+pub use context::context;
+pub use message_log::{message_log_within_budget, oldest_within_budget};
 pub use message_log::{MessageLogItem, MessageLogItemRow};
@@ -0,0 +1,44 @@
+use time::{UtcOffset, format_description::well_known::Rfc3339};
+
+use crate::{actuators::chat::dto::Reminder, infer};
+use super::super::{embeddings::RelatedMessage, memories::RecalledMemory};
+
+/// Renders recalled memories, related messages from other threads and upcoming reminders
+/// as a `<context>` block, if there are any.
+pub fn context(
+    memories: &[RecalledMemory],
+    related_messages: &[RelatedMessage],
+    reminders: &[Reminder],
+) -> Option<infer::Message> {
+    if memories.is_empty() && related_messages.is_empty() && reminders.is_empty() {
+        return None;
+    }
+    // Same timezone as the message log
+    let timezone = UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC);
+    let reminders = reminders
+        .iter()
+        .map(|reminder| {
+            let due_at = reminder.due_at.to_offset(timezone).replace_nanosecond(0).unwrap_or(reminder.due_at);
+            (due_at.format(&Rfc3339).unwrap_or_default(), &reminder.content)
+        })
+        .collect::<Vec<_>>();
+    Some(infer::Message::new_text_system(markup::new! {
+        context {
+            @for memory in memories {
+                memory [about = &memory.user_name] {
+                    @memory.content
+                }
+            }
+            @for message in related_messages {
+                pastMessage [date = message.created_at.date().to_string(), from = &message.user_name] {
+                    @message.content
+                }
+            }
+            @for (due_at, content) in &reminders {
+                scheduledReminder [due = due_at] {
+                    @content
+                }
+            }
+        }
+    }.to_string()))
+}
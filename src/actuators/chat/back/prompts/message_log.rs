@@ -22,13 +22,16 @@ static TIME_FORMAT_SHORT: Lazy<Vec<FormatItem>> = Lazy::new(|| {
 
 #[derive(sqlx::FromRow)]
 pub struct MessageLogItemRow {
+    pub id: Uuid,
     pub user_id: Option<Uuid>,
     pub user_name: Option<String>,
     pub content: String,
     pub created_at: time::OffsetDateTime,
 }
 
+#[derive(Clone)]
 pub struct MessageLogItem {
+    pub id: Uuid,
     pub user: Option<User>,
     pub content: String,
     pub created_at: time::OffsetDateTime,
@@ -37,6 +40,7 @@ pub struct MessageLogItem {
 impl From<MessageLogItemRow> for MessageLogItem {
     fn from(row: MessageLogItemRow) -> Self {
         Self {
+            id: row.id,
             user: match (row.user_id, row.user_name) {
                 (Some(id), Some(name)) => Some(User { id, name }),
                 _ => None
@@ -55,13 +59,29 @@ impl MessageLogItem {
     pub fn is_own_message(&self) -> bool {
         self.user.as_ref().map(|u| u.id == Uuid::nil()).unwrap_or(false)
     }
+
+    /// Roughly what the item costs once rendered by `message_log`, tags included.
+    pub fn estimated_tokens(&self) -> usize {
+        const MARKUP_TOKENS: usize = 32;
+        let name_tokens = self.user.as_ref().map_or(0, |user| infer::estimate_tokens(&user.name));
+        MARKUP_TOKENS + name_tokens + infer::estimate_tokens(&self.content)
+    }
 }
 
-pub fn message_log(messages: Vec<MessageLogItem>) -> Result<impl Iterator<Item = infer::Message>, time::error::Format> {
+/// Renders the messages (newest first) in chronological order, preceded by the summary
+/// of whatever came before them.
+pub fn message_log(
+    summary: Option<&str>,
+    messages: Vec<MessageLogItem>,
+) -> Result<impl Iterator<Item = infer::Message>, time::error::Format> {
     let now = time::OffsetDateTime::now_utc();
     let mut last_date = None;
     let mut output_messages = Vec::new();
 
+    if let Some(summary) = summary {
+        output_messages.push(summary_message(summary));
+    }
+
     for message in messages.into_iter().rev() {
         let date = message.created_at.date();
         let do_show_date = match last_date {
@@ -109,3 +129,119 @@ pub fn message_log(messages: Vec<MessageLogItem>) -> Result<impl Iterator<Item =
 
     Ok(output_messages.into_iter())
 }
+
+fn summary_message(summary: &str) -> infer::Message {
+    infer::Message::new_text_system(markup::new! {
+        summary {
+            @summary
+        }
+    }.to_string())
+}
+
+/// Like `message_log`, but only keeps as many of the newest messages as fit into `budget`
+/// tokens, leaving a note about the earlier ones. The newest message is always kept.
+pub fn message_log_within_budget(
+    summary: Option<&str>,
+    mut messages: Vec<MessageLogItem>,
+    budget: usize,
+) -> Result<impl Iterator<Item = infer::Message>, time::error::Format> {
+    // Newest first, same as `message_log` takes them
+    let mut used_tokens = summary.map_or(0, |summary| summary_message(summary).estimated_tokens());
+    let kept = messages
+        .iter()
+        .take_while(|message| {
+            used_tokens += message.estimated_tokens();
+            used_tokens <= budget
+        })
+        .count()
+        .max(1)
+        .min(messages.len());
+    let omitted = messages.len() - kept;
+    messages.truncate(kept);
+
+    let omitted_notice = (omitted > 0).then(|| {
+        infer::Message::new_text_system(markup::new! {
+            omittedMessages [count = omitted];
+        }.to_string())
+    });
+    let mut output_messages = message_log(summary, messages)?.collect::<Vec<_>>();
+    if let Some(omitted_notice) = omitted_notice {
+        // After the summary, which covers even earlier messages
+        output_messages.insert(summary.is_some() as usize, omitted_notice);
+    }
+    Ok(output_messages.into_iter())
+}
+
+/// How many of the oldest messages (given newest first) fit into `budget` tokens after the
+/// summary. At least one, so that summarizing them a chunk at a time always advances.
+pub fn oldest_within_budget(summary: Option<&str>, messages: &[MessageLogItem], budget: usize) -> usize {
+    let mut used_tokens = summary.map_or(0, |summary| summary_message(summary).estimated_tokens());
+    messages
+        .iter()
+        .rev()
+        .take_while(|message| {
+            used_tokens += message.estimated_tokens();
+            used_tokens <= budget
+        })
+        .count()
+        .max(1)
+        .min(messages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(content: &str) -> MessageLogItem {
+        MessageLogItem {
+            id: Uuid::new_v4(),
+            user: Some(User { id: Uuid::new_v4(), name: "Ann".into() }),
+            content: content.into(),
+            created_at: time::OffsetDateTime::now_utc(),
+        }
+    }
+
+    fn texts(messages: impl Iterator<Item = infer::Message>) -> Vec<String> {
+        messages
+            .map(|message| match &message.content[0] {
+                infer::ContentBlock::Text(text) => text.to_string(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_newest_messages_within_budget() {
+        let messages = vec![item("newest"), item("middle"), item(&"x".repeat(400))];
+        let budget = messages[0].estimated_tokens() + messages[1].estimated_tokens();
+        let texts = texts(message_log_within_budget(None, messages, budget).unwrap());
+        assert_eq!(texts.len(), 5);
+        assert_eq!(texts[0], r#"<omittedMessages count="1">"#);
+        assert_eq!(texts[2], "middle");
+        assert_eq!(texts[4], "newest");
+    }
+
+    #[test]
+    fn always_keeps_newest_message() {
+        let texts = texts(message_log_within_budget(None, vec![item("newest"), item("older")], 0).unwrap());
+        assert_eq!(texts.len(), 3);
+        assert_eq!(texts[2], "newest");
+    }
+
+    #[test]
+    fn counts_oldest_messages_within_budget() {
+        let messages = vec![item(&"x".repeat(400)), item("middle"), item("oldest")];
+        let budget = messages[1].estimated_tokens() + messages[2].estimated_tokens();
+        assert_eq!(oldest_within_budget(None, &messages, budget), 2);
+        assert_eq!(oldest_within_budget(None, &messages, 0), 1);
+        assert_eq!(oldest_within_budget(None, &[], budget), 0);
+    }
+
+    #[test]
+    fn puts_summary_before_omitted_notice() {
+        let texts = texts(message_log_within_budget(Some("They met."), vec![item("newest"), item("older")], 0).unwrap());
+        assert_eq!(texts[0], "<summary>They met.</summary>");
+        assert!(texts[1].starts_with("<omittedMessages"));
+        assert_eq!(texts[3], "newest");
+    }
+}
@@ -0,0 +1,280 @@
+use std::{sync::Arc, time::Duration};
+
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset, format_description::well_known::{Iso8601, Rfc3339}};
+use uuid::Uuid;
+
+use super::actor::{self, ChatService, State};
+use crate::{
+    actuators::chat::dto::{
+        CancelReminderRequest, CancelReminderResponse, ChatMessage, FetchRemindersRequest,
+        FetchRemindersResponse, Reminder, SyncUpdate,
+    },
+    infer::{self, FromLlmReply, JsonType, ParseError, find_and_parse_json},
+    service::{self, CoercibleResult},
+};
+
+/// How often the timer checks for due reminders
+const TICK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How many upcoming reminders of a thread go into a prompt at most
+const UPCOMING_REMINDER_COUNT: i64 = 5;
+
+#[derive(FromLlmReply, Deserialize)]
+struct ExtractedReminders {
+    reminders: Vec<ExtractedReminder>,
+}
+
+#[derive(Deserialize)]
+struct ExtractedReminder {
+    due_at: Box<str>,
+    content: Box<str>,
+}
+
+/// Reads a local date and time like `2025-03-14T09:00` as a moment at `offset`.
+fn parse_due_at(due_at: &str, offset: UtcOffset) -> Option<OffsetDateTime> {
+    // Models tend to add seconds, or a space instead of the `T`, or an offset anyway
+    let due_at = due_at.trim().replacen(' ', "T", 1);
+    if let Ok(due_at) = OffsetDateTime::parse(&due_at, &Iso8601::DEFAULT) {
+        return Some(due_at);
+    }
+    let due_at = match due_at.matches(':').count() {
+        1 => format!("{due_at}:00"),
+        _ => due_at,
+    };
+    PrimitiveDateTime::parse(&due_at, &Iso8601::DEFAULT)
+        .ok()
+        .map(|due_at| due_at.assume_offset(offset))
+}
+
+/// Asks the model whether `message` asks for a reminder or another message at a later time,
+/// and schedules it. Times are read relative to `utc_offset`, the author's timezone.
+pub async fn schedule_reminders(state: &State, message: &ChatMessage, utc_offset: UtcOffset) -> anyhow::Result<()> {
+    let Some(user_id) = message.user_id else {
+        return Ok(());
+    };
+    if user_id == state.self_user.id {
+        return Ok(());
+    }
+    // Only a participant can post the reminder later
+    let participant_ids = super::events::fetch_thread_participant_ids(&state.pool, message.thread_id).await?;
+    if !participant_ids.contains(&state.self_user.id) {
+        return Ok(());
+    }
+
+    let now = OffsetDateTime::now_utc().to_offset(utc_offset);
+    let now_text = format!(
+        "{} {}",
+        now.weekday(),
+        now.replace_nanosecond(0)?.format(&Rfc3339)?,
+    );
+    let content = &message.content;
+    let ExtractedReminders { reminders } = state.system_prompt
+        .fork()
+        .with_utility_model()
+        .with_message(infer::Message::new_text_user(markup::new! {
+            message {
+                @content
+            }
+            systemInstructions {
+                "It is " @now_text " for the author of the message above. "
+                "List what the author asks you to remind them of, or to tell them, at a later time. "
+                "Skip anything that isn't an explicit request for a later message. "
+                "For each, give the local date and time it is due in the format YYYY-MM-DDTHH:MM, "
+                "and the message to send then, addressed to the author, in the language of their message. "
+                "With no preamble, respond with a JSON object in the following format: "
+                "{\"reminders\": [{\"due_at\": date and time, \"content\": message}, ...]}, "
+                "with an empty array if there is nothing to schedule."
+            }
+        }.to_string()))
+        .infer_drop::<ExtractedReminders>(false)
+        .await?
+        .value;
+
+    for reminder in reminders {
+        let Some(due_at) = parse_due_at(&reminder.due_at, utc_offset) else {
+            tracing::warn!("Ignoring reminder with unreadable time {:?}", reminder.due_at);
+            continue;
+        };
+        let content = reminder.content.trim();
+        if content.is_empty() || due_at <= now {
+            continue;
+        }
+        sqlx::query!(
+            r#"--sql
+            INSERT INTO reminders (user_id, thread_id, content, due_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            user_id,
+            message.thread_id,
+            content,
+            due_at,
+        )
+            .execute(&state.pool)
+            .await?;
+        tracing::info!("Scheduled a reminder in thread {} for {due_at}", message.thread_id);
+    }
+    Ok(())
+}
+
+/// Reminders of the thread that are still to come, soonest first.
+pub async fn fetch_upcoming(pool: &PgPool, thread_id: Uuid) -> service::Result<Vec<Reminder>> {
+    sqlx::query_as!(
+        Reminder,
+        r#"--sql
+        SELECT id, user_id, thread_id, content, due_at, created_at
+        FROM reminders
+        WHERE thread_id = $1 AND delivered_at IS NULL
+        ORDER BY due_at ASC
+        LIMIT $2
+        "#,
+        thread_id,
+        UPCOMING_REMINDER_COUNT,
+    )
+        .fetch_all(pool)
+        .await
+        .into_service_result()
+}
+
+/// Posts reminders into their threads once they are due.
+pub struct ReminderTimer {
+    state: Arc<State>,
+}
+
+impl ReminderTimer {
+    pub fn new(state: Arc<State>) -> Self {
+        Self { state }
+    }
+}
+
+impl Actor for ReminderTimer {
+    type Context = actix::Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(TICK_INTERVAL, |timer, _| {
+            let state = timer.state.clone();
+            actix::spawn(async move {
+                if let Err(error) = deliver_due(&state).await {
+                    tracing::error!("Failed to deliver reminders: {error:?}");
+                }
+            });
+        });
+    }
+}
+
+async fn deliver_due(state: &State) -> anyhow::Result<()> {
+    // Claiming marks them delivered right away, so a slow tick never posts one twice
+    let reminders = sqlx::query_as!(
+        Reminder,
+        r#"--sql
+        UPDATE reminders SET delivered_at = CURRENT_TIMESTAMP
+        WHERE id IN (
+            SELECT id FROM reminders
+            WHERE delivered_at IS NULL AND due_at <= CURRENT_TIMESTAMP
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, user_id, thread_id, content, due_at, created_at
+        "#,
+    )
+        .fetch_all(&state.pool)
+        .await?;
+
+    for reminder in reminders {
+        let delivery = async {
+            let (message, thread) = actor::create_message(
+                &state.pool,
+                Some(state.self_user.id),
+                reminder.thread_id,
+                None,
+                None,
+                &reminder.content,
+            ).await?;
+            actor::publish_thread(state, &thread).await?;
+            actor::publish_message(state, &message).await?;
+            service::Result::Ok(())
+        }.await;
+        if let Err(error) = delivery {
+            tracing::warn!("Failed to deliver reminder {}: {error:?}", reminder.id);
+        }
+    }
+    Ok(())
+}
+
+#[message_handler(ChatService)]
+async fn fetch_reminders(
+    state: &State,
+    FetchRemindersRequest { from_user_id }: FetchRemindersRequest,
+) -> service::Result<FetchRemindersResponse> {
+    let reminders = sqlx::query_as!(
+        Reminder,
+        r#"--sql
+        SELECT id, user_id, thread_id, content, due_at, created_at
+        FROM reminders
+        WHERE user_id = $1 AND delivered_at IS NULL
+        ORDER BY due_at ASC
+        "#,
+        from_user_id,
+    )
+        .fetch_all(&state.pool)
+        .await
+        .into_service_result()?;
+    Ok(FetchRemindersResponse {
+        reminders: reminders.into_iter().map(SyncUpdate::Updated).collect(),
+    })
+}
+
+#[message_handler(ChatService)]
+async fn cancel_reminder(
+    state: &State,
+    CancelReminderRequest { from_user_id, reminder_id }: CancelReminderRequest,
+) -> service::Result<CancelReminderResponse> {
+    // Delivered reminders are already in the thread, so there's nothing left to cancel
+    let reminder_id = sqlx::query_scalar!(
+        r#"--sql
+        DELETE FROM reminders
+        WHERE id = $1 AND user_id = $2 AND delivered_at IS NULL
+        RETURNING id
+        "#,
+        reminder_id,
+        from_user_id,
+    )
+        .fetch_optional(&state.pool)
+        .await
+        .into_service_result()?
+        .ok_or(service::Error::NotFound)?;
+    Ok(CancelReminderResponse {
+        reminders: vec![SyncUpdate::Deleted(reminder_id)],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_due_at_in_the_authors_timezone() {
+        let offset = UtcOffset::from_hms(2, 0, 0).unwrap();
+        let expected = time::Date::from_calendar_date(2025, time::Month::March, 14)
+            .unwrap()
+            .with_hms(7, 0, 0)
+            .unwrap()
+            .assume_utc();
+        assert_eq!(parse_due_at("2025-03-14T09:00", offset), Some(expected));
+        assert_eq!(parse_due_at(" 2025-03-14 09:00:00 ", offset), Some(expected));
+        assert_eq!(parse_due_at("2025-03-14T07:00Z", offset), Some(expected));
+        assert_eq!(parse_due_at("tomorrow at 9", offset), None);
+    }
+
+    #[test]
+    fn parses_extracted_reminders() {
+        let ExtractedReminders { reminders } = ExtractedReminders::from_reply(
+            "```json\n{\"reminders\": [{\"due_at\": \"2025-03-14T09:00\", \"content\": \"Call the vet!\"}]}\n```",
+        )
+        .unwrap();
+        assert_eq!(&*reminders[0].due_at, "2025-03-14T09:00");
+        assert_eq!(&*reminders[0].content, "Call the vet!");
+    }
+}
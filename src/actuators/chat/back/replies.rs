@@ -0,0 +1,271 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::Deserialize;
+use time::UtcOffset;
+use uuid::Uuid;
+
+use super::{
+    actor::{self, State, fetch_message_log},
+    embeddings, memories, participants, prompts, reminders, summaries,
+};
+use crate::{
+    actuators::chat::dto::ChatMessage,
+    infer::{self, FromLlmReply, JsonType, ParseError, find_and_parse_json},
+};
+
+/// How long to wait for more messages before answering, so that a few
+/// quick messages in a row get a single reply
+const COALESCE_WINDOW: Duration = Duration::from_secs(3);
+
+/// How much of the message log, in recent messages, decides whether and when to reply
+const REPLY_DECISION_MESSAGE_COUNT: usize = 10;
+
+const DEFAULT_LATER_DELAY_MINUTES: u64 = 10;
+/// Delayed replies are kept in memory, so they shouldn't wait for too long
+const MAX_LATER_DELAY_MINUTES: u64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Now,
+    Later,
+    Never,
+}
+
+/// Whether and when the artilect should answer the latest messages of a thread.
+#[derive(Debug, FromLlmReply, Deserialize)]
+pub struct ReplyDecision {
+    pub reply: Urgency,
+    #[serde(default)]
+    pub delay_minutes: Option<u64>,
+}
+
+impl ReplyDecision {
+    pub const NOW: Self = Self { reply: Urgency::Now, delay_minutes: None };
+    pub const NEVER: Self = Self { reply: Urgency::Never, delay_minutes: None };
+
+    /// How long to wait before replying, or `None` to stay silent.
+    pub fn delay(&self) -> Option<Duration> {
+        match self.reply {
+            Urgency::Now => Some(Duration::ZERO),
+            Urgency::Later => {
+                let minutes = self
+                    .delay_minutes
+                    .unwrap_or(DEFAULT_LATER_DELAY_MINUTES)
+                    .clamp(1, MAX_LATER_DELAY_MINUTES);
+                Some(Duration::from_secs(minutes * 60))
+            }
+            Urgency::Never => None,
+        }
+    }
+}
+
+/// Numbers the messages of each thread with a reply underway, so that a scheduled reply
+/// can tell it was overtaken by a newer message and leave the answer to that one.
+/// Numbers count across threads, so none is reused once a thread's reply is done.
+#[derive(Default)]
+pub struct ReplyQueue {
+    latest: Mutex<HashMap<Uuid, u64>>,
+    last_generation: AtomicU64,
+}
+
+impl ReplyQueue {
+    fn bump(&self, thread_id: Uuid) -> u64 {
+        let generation = self.last_generation.fetch_add(1, Ordering::Relaxed) + 1;
+        self.latest.lock().unwrap().insert(thread_id, generation);
+        generation
+    }
+
+    fn is_latest(&self, thread_id: Uuid, generation: u64) -> bool {
+        self.latest.lock().unwrap().get(&thread_id) == Some(&generation)
+    }
+
+    /// Forgets the thread once its latest reply is sent or dropped.
+    fn finish(&self, thread_id: Uuid, generation: u64) {
+        let mut latest = self.latest.lock().unwrap();
+        if latest.get(&thread_id) == Some(&generation) {
+            latest.remove(&thread_id);
+        }
+    }
+}
+
+/// Learns from the message in the background, then replies to the thread
+/// once it has been quiet for a moment, if the artilect decides to at all.
+/// The author's `utc_offset` is for reading times the message mentions.
+pub fn schedule_reply(state: Arc<State>, message: ChatMessage, utc_offset: UtcOffset) {
+    let thread_id = message.thread_id;
+    let generation = state.replies.bump(thread_id);
+    actix::spawn(async move {
+        // The window runs from when the message came, so learning from it doesn't delay
+        // the reply, yet the reply can still draw on what was learned
+        let ((), ()) = futures_util::join!(
+            learn_from_message(&state, &message, utc_offset),
+            actix::clock::sleep(COALESCE_WINDOW),
+        );
+        // Only the latest of several quick messages decides, once, for all of them
+        if state.replies.is_latest(thread_id, generation) {
+            reply_when_decided(&state, thread_id, message.id, generation).await;
+        }
+        state.replies.finish(thread_id, generation);
+    });
+}
+
+async fn learn_from_message(state: &State, message: &ChatMessage, utc_offset: UtcOffset) {
+    if let Err(error) = embeddings::store_message_embeddings(state, &[message]).await {
+        tracing::warn!("Failed to embed message {}: {error}", message.id);
+    }
+    if let Err(error) = memories::remember_facts(state, message).await {
+        tracing::warn!("Failed to remember facts from message {}: {error}", message.id);
+    }
+    if let Err(error) = reminders::schedule_reminders(state, message, utc_offset).await {
+        tracing::warn!("Failed to schedule reminders from message {}: {error}", message.id);
+    }
+}
+
+async fn reply_when_decided(state: &State, thread_id: Uuid, reply_to: Uuid, generation: u64) {
+    let decision = decide(state, thread_id, reply_to).await;
+    tracing::info!("Reply decision for thread {thread_id}: {decision:?}");
+    let Some(delay) = decision.delay() else {
+        name_if_untitled(state, thread_id).await;
+        return;
+    };
+    if !delay.is_zero() {
+        name_if_untitled(state, thread_id).await;
+        actix::clock::sleep(delay).await;
+        if !state.replies.is_latest(thread_id, generation) {
+            return;
+        }
+    }
+    if let Err(error) = reply(state, thread_id, reply_to).await {
+        tracing::error!("Failed to reply in thread {thread_id}: {error:?}");
+    }
+    name_if_untitled(state, thread_id).await;
+}
+
+/// Answers `reply_to` right away, superseding any reply still waiting to be sent.
+pub fn reply_again(state: Arc<State>, thread_id: Uuid, reply_to: Uuid) {
+    let generation = state.replies.bump(thread_id);
+    actix::spawn(async move {
+        if let Err(error) = reply(&state, thread_id, reply_to).await {
+            tracing::error!("Failed to reply in thread {thread_id}: {error:?}");
+        }
+        state.replies.finish(thread_id, generation);
+    });
+}
+
+async fn reply(state: &State, thread_id: Uuid, reply_to: Uuid) -> anyhow::Result<()> {
+    let (message, _) = actor::respond_to_thread(state, thread_id, reply_to).await?;
+    actor::publish_message(state, &message).await?;
+    if let Err(error) = summaries::update_summary_if_needed(state, thread_id).await {
+        tracing::warn!("Failed to update summary of thread {thread_id}: {error}");
+    }
+    if let Err(error) = embeddings::store_message_embeddings(state, &[&message]).await {
+        tracing::warn!("Failed to embed message {}: {error}", message.id);
+    }
+    Ok(())
+}
+
+async fn name_if_untitled(state: &State, thread_id: Uuid) {
+    let result = async {
+        if actor::fetch_thread(state, thread_id).await?.name.is_none() {
+            let thread = actor::generate_thread_name(state, thread_id).await?;
+            actor::publish_thread(state, &thread).await?;
+        }
+        anyhow::Ok(())
+    }.await;
+    if let Err(error) = result {
+        tracing::warn!("Failed to name thread {thread_id}: {error}");
+    }
+}
+
+/// The artilect always answers right away when it's the only one a human is
+/// talking to. Among several humans, the model decides whether to join in, and when.
+pub async fn decide(state: &State, thread_id: Uuid, message_id: Uuid) -> ReplyDecision {
+    match try_decide(state, thread_id, message_id).await {
+        Ok(decision) => decision,
+        Err(error) => {
+            // Better to speak up once too often than to ignore a question
+            tracing::warn!("Failed to decide whether to reply in thread {thread_id}: {error}");
+            ReplyDecision::NOW
+        }
+    }
+}
+
+async fn try_decide(state: &State, thread_id: Uuid, message_id: Uuid) -> anyhow::Result<ReplyDecision> {
+    let participant_ids = super::events::fetch_thread_participant_ids(&state.pool, thread_id).await?;
+    if !participant_ids.contains(&state.self_user.id) {
+        return Ok(ReplyDecision::NEVER);
+    }
+    if participants::count_humans(state, thread_id).await? <= 1 {
+        return Ok(ReplyDecision::NOW);
+    }
+
+    let mut messages = fetch_message_log(&state.pool, thread_id, Some(message_id), None).await?;
+    messages.truncate(REPLY_DECISION_MESSAGE_COUNT);
+    let self_name = &state.self_user.name;
+    let chain = state.system_prompt.fork().with_utility_model();
+    let budget = chain.model().profile.prompt_budget().saturating_sub(chain.estimated_tokens()) / 2;
+    let decision = chain
+        .with_messages(prompts::message_log_within_budget(None, messages, budget)?)
+        .with_message(infer::Message::new_text_user(markup::new! {
+            systemInstructions {
+                "Several people are talking in this thread and you, " @self_name ", are one of them. "
+                "Decide whether and when you should reply to the latest messages. "
+                "Reply now when you are addressed or asked something. "
+                "Reply later when you can add something useful but the others should get a chance to answer first. "
+                "Never reply when the others are talking among themselves. "
+                "With no preamble, respond with a JSON object in the following format: "
+                "{\"reply\": \"now\" | \"later\" | \"never\", \"delay_minutes\": minutes to wait, only for later}."
+            }
+        }.to_string()))
+        .infer_drop::<ReplyDecision>(false)
+        .await?
+        .value;
+    Ok(decision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_reply_decisions() {
+        let decision = ReplyDecision::from_reply("```json\n{\"reply\": \"later\", \"delay_minutes\": 5}\n```").unwrap();
+        assert_eq!(decision.reply, Urgency::Later);
+        assert_eq!(decision.delay(), Some(Duration::from_secs(5 * 60)));
+        let decision = ReplyDecision::from_reply("{\"reply\": \"never\"}").unwrap();
+        assert_eq!(decision.delay(), None);
+    }
+
+    #[test]
+    fn forgets_threads_once_replied() {
+        let queue = ReplyQueue::default();
+        let thread_id = Uuid::new_v4();
+        let first = queue.bump(thread_id);
+        let second = queue.bump(thread_id);
+        assert!(!queue.is_latest(thread_id, first));
+        queue.finish(thread_id, first);
+        assert!(queue.is_latest(thread_id, second));
+        queue.finish(thread_id, second);
+        assert!(queue.latest.lock().unwrap().is_empty());
+        // Numbers aren't reused, so a stale reply still waiting can't pass for the next one
+        let third = queue.bump(thread_id);
+        assert!(third != first && third != second);
+    }
+
+    #[test]
+    fn clamps_later_delays() {
+        let decision = |delay_minutes| ReplyDecision { reply: Urgency::Later, delay_minutes };
+        assert_eq!(decision(None).delay(), Some(Duration::from_secs(DEFAULT_LATER_DELAY_MINUTES * 60)));
+        assert_eq!(decision(Some(0)).delay(), Some(Duration::from_secs(60)));
+        assert_eq!(decision(Some(10_000)).delay(), Some(Duration::from_secs(MAX_LATER_DELAY_MINUTES * 60)));
+        assert_eq!(ReplyDecision::NOW.delay(), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,162 @@
+use std::{sync::Arc, time::Duration};
+
+use actix::prelude::*;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{
+    actor::{self, State, fetch_message_log},
+    embeddings, prompts, summaries,
+};
+use crate::infer::{self, FromLlmReply, JsonType, ParseError, find_and_parse_json};
+
+/// How often the scheduler looks for threads to follow up on
+const SCAN_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long a thread has to be quiet before the artilect follows up on its own
+const FOLLOW_UP_AFTER: time::Duration = time::Duration::hours(2);
+
+/// Threads quiet for longer than this are left alone
+const FOLLOW_UP_WITHIN: time::Duration = time::Duration::days(7);
+
+/// How many threads a single scan looks at
+const THREADS_PER_SCAN: i64 = 10;
+
+#[derive(Debug, FromLlmReply, Deserialize)]
+struct FollowUp {
+    message: Option<Box<str>>,
+}
+
+/// Periodically goes through threads with new activity from humans and lets the
+/// artilect post on its own, e.g. to remind of something or to check in.
+pub struct Scheduler {
+    state: Arc<State>,
+}
+
+impl Scheduler {
+    pub fn new(state: Arc<State>) -> Self {
+        Self { state }
+    }
+}
+
+impl Actor for Scheduler {
+    type Context = actix::Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(SCAN_INTERVAL, |scheduler, _| {
+            let state = scheduler.state.clone();
+            actix::spawn(async move {
+                if let Err(error) = scan(&state).await {
+                    tracing::error!("Failed to scan threads for follow-ups: {error:?}");
+                }
+            });
+        });
+    }
+}
+
+async fn scan(state: &State) -> anyhow::Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    // Claiming clears the flag right away, so overlapping scans never pick the same thread
+    let thread_ids = sqlx::query_scalar!(
+        r#"--sql
+        UPDATE threads SET pending_updates = false
+        WHERE id IN (
+            SELECT id FROM threads
+            WHERE pending_updates = true
+                AND updated_at < $1
+                AND updated_at > $2
+                AND EXISTS (
+                    SELECT 1 FROM thread_participants
+                    WHERE thread_id = threads.id AND user_id = $3
+                )
+            ORDER BY updated_at DESC
+            LIMIT $4
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id
+        "#,
+        now - FOLLOW_UP_AFTER,
+        now - FOLLOW_UP_WITHIN,
+        state.self_user.id,
+        THREADS_PER_SCAN,
+    )
+        .fetch_all(&state.pool)
+        .await?;
+
+    for thread_id in thread_ids {
+        if let Err(error) = follow_up(state, thread_id).await {
+            tracing::warn!("Failed to follow up on thread {thread_id}: {error:?}");
+        }
+    }
+    Ok(())
+}
+
+async fn follow_up(state: &State, thread_id: Uuid) -> anyhow::Result<()> {
+    let summary = summaries::fetch_summary(&state.pool, thread_id, None).await?;
+    let messages = fetch_message_log(
+        &state.pool,
+        thread_id,
+        None,
+        summary.as_ref().map(|summary| summary.covered_until),
+    ).await?;
+
+    let instructions = infer::Message::new_text_user(markup::new! {
+        systemInstructions {
+            "Nobody has written in this thread for a while. "
+            "Decide whether you should follow up on your own, for example to remind of something that was agreed on, "
+            "to check in on how something went or to share what you promised to look into. "
+            "Don't follow up just to keep the conversation going. "
+            "With no preamble, respond with a JSON object in the following format: "
+            "{\"message\": \"your message\"} to follow up or {\"message\": null} to stay silent. "
+            "Write the message in the language of the thread."
+        }
+    }.to_string());
+    let chain = state.system_prompt.fork();
+    let budget = chain
+        .model()
+        .profile
+        .prompt_budget()
+        .saturating_sub(chain.estimated_tokens() + instructions.estimated_tokens());
+    let FollowUp { message } = chain
+        .with_messages(prompts::message_log_within_budget(
+            summary.as_ref().map(|summary| &*summary.content),
+            messages,
+            budget,
+        )?)
+        .with_message(instructions)
+        .infer_drop::<FollowUp>(false)
+        .await?
+        .value;
+    let Some(content) = message.filter(|content| !content.trim().is_empty()) else {
+        return Ok(());
+    };
+
+    tracing::info!("Following up on thread {thread_id}");
+    let (message, thread) = actor::create_message(
+        &state.pool,
+        Some(state.self_user.id),
+        thread_id,
+        None,
+        None,
+        content.trim(),
+    ).await?;
+    actor::publish_thread(state, &thread).await?;
+    actor::publish_message(state, &message).await?;
+    if let Err(error) = embeddings::store_message_embeddings(state, &[&message]).await {
+        tracing::warn!("Failed to embed message {}: {error}", message.id);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_follow_ups() {
+        let follow_up = FollowUp::from_reply("{\"message\": \"How did the interview go?\"}").unwrap();
+        assert_eq!(follow_up.message.as_deref(), Some("How did the interview go?"));
+        let follow_up = FollowUp::from_reply("```json\n{\"message\": null}\n```").unwrap();
+        assert_eq!(follow_up.message, None);
+    }
+}
@@ -0,0 +1,139 @@
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::actor::{ChatService, State};
+use crate::{
+    actuators::chat::dto::{SearchHit, SearchRequest, SearchResponse, SnippetPart},
+    service::{self, CoercibleResult},
+};
+
+/// How many hits a search returns at most
+const SEARCH_HIT_COUNT: i64 = 30;
+
+/// Marks the matching words in `ts_headline` output; control characters won't clash with what people type
+const HIGHLIGHT_START: char = '\u{2}';
+const HIGHLIGHT_STOP: char = '\u{3}';
+
+struct SearchRow {
+    thread_id: Uuid,
+    message_id: Option<Uuid>,
+    thread_name: Option<String>,
+    snippet: String,
+    created_at: OffsetDateTime,
+    rank: f32,
+}
+
+/// Splits `ts_headline` output into plain and highlighted parts.
+fn split_snippet(snippet: &str) -> Vec<SnippetPart> {
+    let mut parts = Vec::new();
+    let mut rest = snippet;
+    while let Some(start) = rest.find(HIGHLIGHT_START) {
+        if start > 0 {
+            parts.push(SnippetPart { text: rest[..start].to_string(), highlighted: false });
+        }
+        rest = &rest[start + HIGHLIGHT_START.len_utf8()..];
+        let stop = rest.find(HIGHLIGHT_STOP).unwrap_or(rest.len());
+        parts.push(SnippetPart { text: rest[..stop].to_string(), highlighted: true });
+        rest = rest.get(stop + HIGHLIGHT_STOP.len_utf8()..).unwrap_or_default();
+    }
+    if !rest.is_empty() {
+        parts.push(SnippetPart { text: rest.to_string(), highlighted: false });
+    }
+    parts
+}
+
+#[message_handler(ChatService)]
+async fn search(
+    state: &State,
+    SearchRequest { from_user_id, query }: SearchRequest,
+) -> service::Result<SearchResponse> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(SearchResponse { hits: Vec::new() });
+    }
+    let headline_options = format!(
+        r#"StartSel="{HIGHLIGHT_START}", StopSel="{HIGHLIGHT_STOP}", MaxWords=24, MinWords=8, MaxFragments=2"#
+    );
+    // Thread names count double, as they sum up the whole thread
+    let rows = sqlx::query_as!(
+        SearchRow,
+        r#"--sql
+        WITH query AS (SELECT websearch_to_tsquery('simple', $2) AS query)
+        SELECT
+            thread_id AS "thread_id!",
+            message_id,
+            thread_name,
+            snippet AS "snippet!",
+            created_at AS "created_at!",
+            rank::real AS "rank!"
+        FROM (
+            SELECT
+                t.id AS thread_id,
+                NULL::uuid AS message_id,
+                t.name AS thread_name,
+                ts_headline('simple', t.name, query.query, $3) AS snippet,
+                t.updated_at AS created_at,
+                ts_rank(t.search_vector, query.query) * 2 AS rank
+            FROM threads t
+            CROSS JOIN query
+            INNER JOIN thread_participants tp ON tp.thread_id = t.id AND tp.user_id = $1
+            WHERE t.search_vector @@ query.query
+            UNION ALL
+            SELECT
+                m.thread_id,
+                m.id,
+                t.name,
+                ts_headline('simple', m.content, query.query, $3),
+                m.created_at,
+                ts_rank(m.search_vector, query.query)
+            FROM messages m
+            CROSS JOIN query
+            INNER JOIN threads t ON t.id = m.thread_id
+            INNER JOIN thread_participants tp ON tp.thread_id = m.thread_id AND tp.user_id = $1
+            WHERE m.search_vector @@ query.query AND m.deleted_at IS NULL
+        ) hits
+        ORDER BY rank DESC, created_at DESC
+        LIMIT $4
+        "#,
+        from_user_id,
+        query,
+        headline_options,
+        SEARCH_HIT_COUNT,
+    )
+        .fetch_all(&state.pool)
+        .await
+        .into_service_result()?;
+    let hits = rows
+        .into_iter()
+        .map(|row| SearchHit {
+            thread_id: row.thread_id,
+            message_id: row.message_id,
+            thread_name: row.thread_name,
+            snippet: split_snippet(&row.snippet),
+            created_at: row.created_at,
+            rank: row.rank,
+        })
+        .collect();
+    Ok(SearchResponse { hits })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(text: &str, highlighted: bool) -> SnippetPart {
+        SnippetPart { text: text.to_string(), highlighted }
+    }
+
+    #[test]
+    fn splits_highlighted_words() {
+        assert_eq!(
+            split_snippet("the \u{2}cat\u{3} sat on the \u{2}mat\u{3}"),
+            vec![part("the ", false), part("cat", true), part(" sat on the ", false), part("mat", true)],
+        );
+        assert_eq!(split_snippet("\u{2}cat\u{3}"), vec![part("cat", true)]);
+        assert_eq!(split_snippet("no match"), vec![part("no match", false)]);
+    }
+}
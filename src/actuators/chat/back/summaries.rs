@@ -0,0 +1,123 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{actor::{State, fetch_message_log}, prompts};
+use crate::{
+    infer::{self, PlainText},
+    service::{self, CoercibleResult},
+};
+
+/// Newest messages that always stay verbatim in the prompt
+const RECENT_MESSAGE_COUNT: usize = 10;
+
+/// Unsummarized messages beyond the recent ones that trigger a new summary
+const SUMMARIZE_EVERY: usize = 20;
+
+pub struct ThreadSummary {
+    pub content: String,
+    pub covered_until: time::OffsetDateTime,
+}
+
+/// The thread's summary, if the branch ending in `leaf_id`, or the latest branch without
+/// one, runs through the last message it covers. Branches forking earlier do without it.
+pub async fn fetch_summary(
+    pool: &PgPool,
+    thread_id: Uuid,
+    leaf_id: Option<Uuid>,
+) -> service::Result<Option<ThreadSummary>> {
+    sqlx::query_as!(
+        ThreadSummary,
+        r#"--sql
+        WITH RECURSIVE branch AS (
+            SELECT id, parent_id
+            FROM messages
+            WHERE thread_id = $1
+                AND id = COALESCE($2, (
+                    SELECT id FROM messages
+                    WHERE thread_id = $1 AND deleted_at IS NULL
+                    ORDER BY created_at DESC
+                    LIMIT 1
+                ))
+            UNION ALL
+            SELECT messages.id, messages.parent_id
+            FROM messages
+            INNER JOIN branch ON messages.id = branch.parent_id
+        )
+        SELECT content, covered_until
+        FROM thread_summaries
+        WHERE thread_id = $1 AND covered_message_id IN (SELECT id FROM branch)
+        "#,
+        thread_id,
+        leaf_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()
+}
+
+/// Folds older messages of the latest branch into its summary once there are `SUMMARIZE_EVERY` of them
+/// beyond the recent ones, or once the unsummarized part takes more than half the prompt.
+pub async fn update_summary_if_needed(state: &State, thread_id: Uuid) -> anyhow::Result<()> {
+    // Starts over when the latest branch forks before the summary's end
+    let summary = fetch_summary(&state.pool, thread_id, None).await?;
+    let mut messages = fetch_message_log(
+        &state.pool,
+        thread_id,
+        None,
+        summary.as_ref().map(|summary| summary.covered_until),
+    ).await?;
+    if messages.len() <= RECENT_MESSAGE_COUNT {
+        return Ok(());
+    }
+
+    let prompt_budget = state.system_prompt.fork().model().profile.prompt_budget();
+    let unsummarized_tokens = messages.iter().map(|message| message.estimated_tokens()).sum::<usize>();
+    if messages.len() < RECENT_MESSAGE_COUNT + SUMMARIZE_EVERY && unsummarized_tokens <= prompt_budget / 2 {
+        return Ok(());
+    }
+
+    // Newest first, so everything past the recent ones gets folded in: oldest first, in
+    // chunks that fit the prompt, each one continuing the summary of those before it
+    let mut to_summarize = messages.split_off(RECENT_MESSAGE_COUNT);
+    let mut content = summary.map(|summary| summary.content);
+    while !to_summarize.is_empty() {
+        let chain = state.system_prompt.fork().with_utility_model();
+        let budget = chain.model().profile.prompt_budget().saturating_sub(chain.estimated_tokens());
+        let chunk_len = prompts::oldest_within_budget(content.as_deref(), &to_summarize, budget);
+        let chunk = to_summarize.split_off(to_summarize.len() - chunk_len);
+        let (covered_message_id, covered_until) = (chunk[0].id, chunk[0].created_at);
+        let PlainText(chunk_summary) = chain
+            .with_messages(prompts::message_log_within_budget(content.as_deref(), chunk, budget)?)
+            .with_message(infer::Message::new_text_user(markup::new! {
+                systemInstructions {
+                    "Write a summary of the conversation so far, continuing the earlier summary if there is one. "
+                    "Keep the names, facts, decisions, open questions and promises that may matter later. "
+                    "Respond with just the summary, no preamble. "
+                    "The summary should be in the same language as the most messages are."
+                }
+            }.to_string()))
+            .infer_drop::<PlainText>(false)
+            .await?
+            .value;
+        let chunk_summary = chunk_summary.trim().to_string();
+
+        // Saved after each chunk, so that a failure later on doesn't lose it
+        sqlx::query!(
+            r#"--sql
+            INSERT INTO thread_summaries (thread_id, content, covered_until, covered_message_id)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (thread_id) DO UPDATE
+            SET content = $2, covered_until = $3, covered_message_id = $4, updated_at = CURRENT_TIMESTAMP
+            "#,
+            thread_id,
+            chunk_summary,
+            covered_until,
+            covered_message_id,
+        )
+            .execute(&state.pool)
+            .await?;
+        tracing::info!("Summarized thread {thread_id} up to {covered_until}");
+        content = Some(chunk_summary);
+    }
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::actor::{ChatService, State};
+use crate::{
+    actuators::chat::dto::{ChatMessage, SyncRequest, SyncResponse, SyncUpdate, Thread},
+    service::{self, CoercibleResult},
+};
+
+/// How many changes a sync returns at most, the client asks again for the rest
+const SYNC_CHANGE_COUNT: usize = 500;
+
+struct Change {
+    kind: String,
+    id: Uuid,
+    change_xid: i64,
+}
+
+struct MessageRow {
+    id: Uuid,
+    thread_id: Uuid,
+    user_id: Option<Uuid>,
+    parent_id: Option<Uuid>,
+    content: String,
+    created_at: OffsetDateTime,
+    updated_at: Option<OffsetDateTime>,
+    is_deleted: bool,
+}
+
+impl From<MessageRow> for SyncUpdate<ChatMessage> {
+    fn from(row: MessageRow) -> Self {
+        if row.is_deleted {
+            return SyncUpdate::Deleted(row.id);
+        }
+        SyncUpdate::Updated(ChatMessage {
+            id: row.id,
+            thread_id: row.thread_id,
+            user_id: row.user_id,
+            parent_id: row.parent_id,
+            content: row.content,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        })
+    }
+}
+
+/// Joining a thread counts as a change of it, and leaving it as its deletion.
+async fn fetch_changes(
+    state: &State,
+    user_id: Uuid,
+    since_xid: i64,
+    until_xid: i64,
+    limit: Option<i64>,
+) -> service::Result<Vec<Change>> {
+    sqlx::query_as!(
+        Change,
+        r#"--sql
+        SELECT kind AS "kind!", id AS "id!", change_xid AS "change_xid!"
+        FROM (
+            SELECT 'thread' AS kind, t.id, t.change_xid, t.change_seq
+            FROM threads t
+            INNER JOIN thread_participants tp ON tp.thread_id = t.id AND tp.user_id = $1
+            UNION ALL
+            SELECT 'thread', tp.thread_id, tp.change_xid, tp.change_seq
+            FROM thread_participants tp
+            WHERE tp.user_id = $1
+            UNION ALL
+            SELECT 'departure', d.thread_id, d.change_xid, d.change_seq
+            FROM thread_departures d
+            WHERE d.user_id = $1
+                AND NOT EXISTS (
+                    SELECT 1 FROM thread_participants tp
+                    WHERE tp.thread_id = d.thread_id AND tp.user_id = $1
+                )
+            UNION ALL
+            SELECT 'message', m.id, m.change_xid, m.change_seq
+            FROM messages m
+            INNER JOIN thread_participants tp ON tp.thread_id = m.thread_id AND tp.user_id = $1
+        ) changes
+        WHERE change_xid >= $2 AND change_xid < $3
+        ORDER BY change_xid ASC, change_seq ASC
+        LIMIT $4
+        "#,
+        user_id,
+        since_xid,
+        until_xid,
+        limit,
+    )
+        .fetch_all(&state.pool)
+        .await
+        .into_service_result()
+}
+
+/// The cursor is a transaction ID, below which every change has been synced. Change
+/// numbers can't serve, as they're taken before changes commit: it stays below the
+/// transactions still running instead, so that none of them commits behind it.
+#[message_handler(ChatService)]
+async fn sync(
+    state: &State,
+    SyncRequest { from_user_id, since }: SyncRequest,
+) -> service::Result<SyncResponse> {
+    let horizon = sqlx::query_scalar!(
+        r#"--sql
+        SELECT pg_snapshot_xmin(pg_current_snapshot())::text::bigint AS "horizon!"
+        "#,
+    )
+        .fetch_one(&state.pool)
+        .await
+        .into_service_result()?;
+    let Some(since) = since else {
+        // New clients fetch what they show, and only need to know where changes start
+        return Ok(SyncResponse { threads: Vec::new(), messages: Vec::new(), cursor: horizon, has_more: false });
+    };
+
+    let mut changes =
+        fetch_changes(state, from_user_id, since, horizon, Some(SYNC_CHANGE_COUNT as i64 + 1)).await?;
+    let mut cursor = horizon;
+    let has_more = changes.len() > SYNC_CHANGE_COUNT;
+    if has_more {
+        // Transactions aren't split between syncs, so the page ends before the one that
+        // didn't fit, unless that one starts it and has to come whole
+        let cut_xid = changes[SYNC_CHANGE_COUNT].change_xid;
+        if cut_xid > changes[0].change_xid {
+            changes.retain(|change| change.change_xid < cut_xid);
+            cursor = cut_xid;
+        } else {
+            changes = fetch_changes(state, from_user_id, cut_xid, cut_xid + 1, None).await?;
+            cursor = cut_xid + 1;
+        }
+    }
+    let ids_of = |kind: &str| {
+        changes
+            .iter()
+            .filter(|change| change.kind == kind)
+            .map(|change| change.id)
+            .collect::<Vec<_>>()
+    };
+
+    let mut threads = ids_of("departure").into_iter().map(SyncUpdate::Deleted).collect::<Vec<_>>();
+    threads.extend(
+        sqlx::query_as!(
+            Thread,
+            r#"--sql
+            SELECT id, name, owner_id, created_at, updated_at
+            FROM threads
+            WHERE id = ANY($1)
+            ORDER BY change_seq ASC
+            "#,
+            &ids_of("thread"),
+        )
+            .fetch_all(&state.pool)
+            .await
+            .into_service_result()?
+            .into_iter()
+            .map(SyncUpdate::Updated),
+    );
+    let messages = sqlx::query_as!(
+        MessageRow,
+        r#"--sql
+        SELECT
+            id, thread_id, user_id, parent_id, content, created_at, updated_at,
+            deleted_at IS NOT NULL AS "is_deleted!"
+        FROM messages
+        WHERE id = ANY($1)
+        ORDER BY change_seq ASC
+        "#,
+        &ids_of("message"),
+    )
+        .fetch_all(&state.pool)
+        .await
+        .into_service_result()?
+        .into_iter()
+        .map(SyncUpdate::from)
+        .collect();
+
+    Ok(SyncResponse { threads, messages, cursor, has_more })
+}
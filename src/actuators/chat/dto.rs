@@ -72,6 +72,9 @@ pub struct ChatMessage {
     pub id: Uuid,
     pub thread_id: Uuid,
     pub user_id: Option<Uuid>,
+    /// The message this one answers, which makes it a branch of the thread when
+    /// there are several answers. New messages without one answer the latest message.
+    pub parent_id: Option<Uuid>,
     pub content: String,
     #[serde(with = "time::serde::rfc3339")]
     pub created_at: OffsetDateTime,
@@ -79,12 +82,23 @@ pub struct ChatMessage {
     pub updated_at: Option<OffsetDateTime>,
 }
 
+/// The last item of a page. Lists come newest first, by when the items were created and
+/// then by ID, which activity can't reorder between pages.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PageCursor {
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+    pub id: Uuid,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "chat-in", derive(Serialize))]
 #[cfg_attr(feature = "chat-out", derive(Deserialize))]
 pub struct FetchUserThreadsResponse {
     pub users: Vec<SyncUpdate<User>>,
     pub user_threads: Vec<OneToManyUpdate<Thread>>,
+    /// Where the next page of older threads starts, if there are any
+    pub next_page: Option<PageCursor>,
 }
 
 #[derive(Debug, Authenticated)]
@@ -94,6 +108,8 @@ pub struct FetchUserThreadsResponse {
 #[cfg_attr(feature = "chat-out", derive(Serialize))]
 pub struct FetchUserThreadsRequest {
     pub from_user_id: Uuid,
+    /// Continues after this thread, or starts with the latest one
+    pub before: Option<PageCursor>,
 }
 
 #[derive(Debug, Authenticated)]
@@ -104,6 +120,8 @@ pub struct FetchUserThreadsRequest {
 pub struct FetchThreadRequest {
     pub from_user_id: Uuid,
     pub thread_id: Uuid,
+    /// Continues after this message, or starts with the latest one
+    pub before: Option<PageCursor>,
 }
 
 #[derive(Debug)]
@@ -111,7 +129,10 @@ pub struct FetchThreadRequest {
 #[cfg_attr(feature = "chat-out", derive(Deserialize))]
 pub struct FetchThreadResponse {
     pub threads: Vec<SyncUpdate<Thread>>,
+    /// Messages oldest first, only one page of them when fetching the thread
     pub thread_messages: Vec<OneToManyUpdate<ChatMessage>>,
+    /// Where the next page of older messages starts, if there are any
+    pub next_page: Option<PageCursor>,
 }
 
 #[derive(Debug, Authenticated)]
@@ -123,10 +144,296 @@ pub struct SendMessageRequest {
     pub from_user_id: Uuid,
     pub message: ChatMessage,
     pub is_new_thread: bool,
+    /// The sender's current UTC offset, so that times like "tomorrow at 9" mean their 9 o'clock
+    pub utc_offset_minutes: Option<i16>,
 }
 
 pub type SendMessageResponse = FetchThreadResponse;
 
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<EditMessageResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct EditMessageRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+    pub message_id: Uuid,
+    pub content: String,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "chat-in", derive(Serialize))]
+#[cfg_attr(feature = "chat-out", derive(Deserialize))]
+pub struct EditMessageResponse {
+    pub messages: Vec<SyncUpdate<ChatMessage>>,
+}
+
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<DeleteMessageResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct DeleteMessageRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+    pub message_id: Uuid,
+}
+
+pub type DeleteMessageResponse = EditMessageResponse;
+
+/// Discards the replies to `message_id`, a message from a human nobody else
+/// has answered yet, and has the artilect answer it again.
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<RegenerateReplyResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct RegenerateReplyRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+    pub message_id: Uuid,
+}
+
+pub type RegenerateReplyResponse = EditMessageResponse;
+
+/// A fact the artilect remembers about a user.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "chat-in", derive(sqlx::FromRow))]
+#[cfg_attr(feature = "chat-out", derive(PartialEq, Identifiable))]
+pub struct Memory {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub thread_id: Option<Uuid>,
+    pub content: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<FetchMemoriesResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct FetchMemoriesRequest {
+    pub from_user_id: Uuid,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "chat-in", derive(Serialize))]
+#[cfg_attr(feature = "chat-out", derive(Deserialize))]
+pub struct FetchMemoriesResponse {
+    pub memories: Vec<SyncUpdate<Memory>>,
+}
+
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<DeleteMemoryResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct DeleteMemoryRequest {
+    pub from_user_id: Uuid,
+    pub memory_id: Uuid,
+}
+
+pub type DeleteMemoryResponse = FetchMemoriesResponse;
+
+/// A message the artilect will post into a thread once it is due.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "chat-in", derive(sqlx::FromRow))]
+pub struct Reminder {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub thread_id: Uuid,
+    pub content: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub due_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<FetchRemindersResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct FetchRemindersRequest {
+    pub from_user_id: Uuid,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "chat-in", derive(Serialize))]
+#[cfg_attr(feature = "chat-out", derive(Deserialize))]
+pub struct FetchRemindersResponse {
+    pub reminders: Vec<SyncUpdate<Reminder>>,
+}
+
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<CancelReminderResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct CancelReminderRequest {
+    pub from_user_id: Uuid,
+    pub reminder_id: Uuid,
+}
+
+pub type CancelReminderResponse = FetchRemindersResponse;
+
+/// Looks for words in the names and messages of the threads the user takes part in.
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<SearchResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct SearchRequest {
+    pub from_user_id: Uuid,
+    pub query: String,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "chat-in", derive(Serialize))]
+#[cfg_attr(feature = "chat-out", derive(Deserialize))]
+pub struct SearchResponse {
+    pub hits: Vec<SearchHit>,
+}
+
+/// A thread or message matching a search, with the matching words highlighted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub thread_id: Uuid,
+    /// `None` when the thread's name matched rather than one of its messages
+    pub message_id: Option<Uuid>,
+    pub thread_name: Option<String>,
+    pub snippet: Vec<SnippetPart>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub rank: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnippetPart {
+    pub text: String,
+    pub highlighted: bool,
+}
+
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<FetchParticipantsResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct FetchParticipantsRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "chat-in", derive(Serialize))]
+#[cfg_attr(feature = "chat-out", derive(Deserialize))]
+pub struct FetchParticipantsResponse {
+    pub threads: Vec<SyncUpdate<Thread>>,
+    pub thread_participants: Vec<OneToManyUpdate<User>>,
+}
+
+/// Any participant may invite someone else, including the artilect.
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<InviteParticipantResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct InviteParticipantRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+    pub user_id: Uuid,
+}
+
+pub type InviteParticipantResponse = FetchParticipantsResponse;
+
+/// Only the owner may remove others.
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<RemoveParticipantResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct RemoveParticipantRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+    pub user_id: Uuid,
+}
+
+pub type RemoveParticipantResponse = FetchParticipantsResponse;
+
+/// The owner has to hand the thread over before leaving.
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<LeaveThreadResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct LeaveThreadRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+}
+
+/// Marks the thread as deleted, since the user can no longer see it.
+pub type LeaveThreadResponse = FetchParticipantsResponse;
+
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<TransferOwnershipResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct TransferOwnershipRequest {
+    pub from_user_id: Uuid,
+    pub thread_id: Uuid,
+    pub new_owner_id: Uuid,
+}
+
+pub type TransferOwnershipResponse = FetchParticipantsResponse;
+
+/// Asks for the threads and messages that changed after `since`, a cursor from an
+/// earlier sync, or only for the current cursor without one.
+#[derive(Debug, Authenticated)]
+#[cfg_attr(feature = "chat-in", derive(Message))]
+#[cfg_attr(feature = "chat-in", rtype(result = "service::Result<SyncResponse>"))]
+#[cfg_attr(feature = "chat-in", derive(Deserialize))]
+#[cfg_attr(feature = "chat-out", derive(Serialize))]
+pub struct SyncRequest {
+    pub from_user_id: Uuid,
+    pub since: Option<i64>,
+}
+
+/// Changes in the order they were made. Threads the user left are deleted, as are messages.
+#[derive(Debug)]
+#[cfg_attr(feature = "chat-in", derive(Serialize))]
+#[cfg_attr(feature = "chat-out", derive(Deserialize))]
+pub struct SyncResponse {
+    pub threads: Vec<SyncUpdate<Thread>>,
+    pub messages: Vec<SyncUpdate<ChatMessage>>,
+    /// Where the next sync continues
+    pub cursor: i64,
+    /// Whether there are more changes to sync right away
+    pub has_more: bool,
+}
+
+/// Pushed to the participants of a thread over the `/events` stream.
+#[derive(Debug)]
+#[cfg_attr(feature = "chat-in", derive(Serialize))]
+#[cfg_attr(feature = "chat-out", derive(Deserialize))]
+pub enum ChatEvent {
+    Threads(Vec<SyncUpdate<Thread>>),
+    ThreadParticipants(Vec<OneToManyUpdate<User>>),
+    /// New, edited and deleted messages
+    Messages(Vec<SyncUpdate<ChatMessage>>),
+    /// A chunk of an assistant reply that is still being generated.
+    /// The complete message follows as a `Messages` event.
+    MessageDelta {
+        thread_id: Uuid,
+        message_id: Uuid,
+        parent_id: Uuid,
+        content: String,
+    },
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,12 +1,16 @@
-use dioxus::logger::tracing::info;
+use dioxus::logger::tracing::{info, warn};
 use dioxus::prelude::*;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 mod api;
 mod components;
+mod config;
 mod state;
-use components::{Chat, Layout, NewChat, Style};
-use state::actions::FetchUserThreadsAction;
+mod storage;
+use components::{Chat, Layout, Login, Logout, NewChat, Settings, SignedIn, Style};
+use config::ClientConfig;
+use state::actions::{FetchUserThreadsAction, SubscribeEventsAction};
 
 #[derive(Debug, Clone, Routable, PartialEq)]
 #[rustfmt::skip]
@@ -16,23 +20,62 @@ enum Route {
     NewChat {},
     #[route("/chat/:thread_id")]
     Chat { thread_id: Uuid },
+    #[end_layout]
+    #[route("/login")]
+    Login {},
+    #[route("/signed-in#:fragment")]
+    SignedIn { fragment: String },
+    #[route("/logout")]
+    Logout {},
+    #[route("/settings")]
+    Settings {},
 }
 
 const FAVICON: Asset = asset!("/src/actuators/chat/front/assets/favicon.ico");
 
 #[component]
 pub fn App() -> Element {
-    state::use_app_state();
+    let mut state = state::use_app_state();
     state::actions::use_app_actions();
     let dispatch_fetch_user_threads = use_coroutine_handle::<FetchUserThreadsAction>();
+    let dispatch_subscribe_events = use_coroutine_handle::<SubscribeEventsAction>();
+    let mut is_loaded = use_signal(|| false);
+    use_hook(move || {
+        spawn(async move {
+            let config = ClientConfig::load().await;
+            let session = storage::load_session().await;
+            state.config.set(config.clone());
+            state.outbox.set(storage::load_outbox().await);
+            if let Some(session) = session
+                && session.expires_at > OffsetDateTime::now_utc()
+            {
+                // A revoked session is dropped; an unreachable auth server
+                // shouldn't sign anyone out, though
+                match api::verify(&config, &session.token).await {
+                    Err(error) if error.is::<api::SessionExpired>() => state.set_session(None),
+                    Err(error) => {
+                        warn!("Couldn't verify the stored session: {}", error);
+                        state.session.set(Some(session));
+                    }
+                    Ok(verified) => state.session.set(Some(verified)),
+                }
+            }
+            is_loaded.set(true);
+            dispatch_subscribe_events.send(SubscribeEventsAction);
+        });
+    });
     use_effect(move || {
-        info!("Fetching user threads...");
-        dispatch_fetch_user_threads.send(());
+        if state.session.read().is_some() {
+            info!("Fetching user threads...");
+            dispatch_fetch_user_threads.send(FetchUserThreadsAction);
+        }
     });
     rsx! {
         document::Link { rel: "icon", href: FAVICON }
         Style {}
-        Router::<Route> {}
+        if is_loaded() {
+            Router::<Route> {}
+        }
     }
 }
 
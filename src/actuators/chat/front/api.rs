@@ -1,51 +1,118 @@
 use crate::actuators::chat::dto::{
-    FetchThreadResponse, FetchUserThreadsResponse, ChatMessage, SendMessageRequest, SendMessageResponse,
+    ChatEvent, ChatMessage, DeleteMessageResponse, EditMessageRequest, EditMessageResponse,
+    FetchParticipantsResponse, FetchThreadResponse, FetchUserThreadsResponse, InviteParticipantRequest,
+    InviteParticipantResponse, LeaveThreadResponse, PageCursor, RegenerateReplyResponse,
+    RemoveParticipantResponse, SearchResponse, SendMessageRequest, SyncResponse, SendMessageResponse, TransferOwnershipRequest, TransferOwnershipResponse,
 };
-use reqwest::Client;
+use crate::actuators::chat::front::config::ClientConfig;
+use crate::auth::dto::{
+    LoginResponse, LogoutResponse, PasswordLoginRequest, Session, VerifyTokenRequest, VerifyTokenResponse,
+};
+use crate::service::HttpErrorBody;
+use crate::sse;
+use futures_util::{Stream, StreamExt};
+use reqwest::{Client, Response, StatusCode};
 use std::error::Error;
-use uuid::{Uuid, uuid};
+use time::format_description::well_known::Rfc3339;
+use uuid::Uuid;
+
+/// A signed-in user of a chat server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connection {
+    pub base_url: String,
+    pub session: Session,
+}
+
+/// The token was rejected, so the user has to sign in again.
+#[derive(Debug)]
+pub struct SessionExpired;
+
+impl std::fmt::Display for SessionExpired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The session has expired, please sign in again")
+    }
+}
 
-static BASE_URL: &str = dotenvy_macro::dotenv!("CHAT_BASE_URL");
-static USER_ID: Uuid = uuid!(dotenvy_macro::dotenv!("CHAT_USER_ID"));
+impl Error for SessionExpired {}
 
-pub async fn fetch_user_threads() -> Result<FetchUserThreadsResponse, Box<dyn Error>> {
+/// Tells whether the same request could succeed later, i.e. the server couldn't be
+/// reached or failed, rather than refusing it.
+pub fn is_transient(error: &(dyn Error + 'static)) -> bool {
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(error) => !error.status().is_some_and(|status| status.is_client_error()),
+        None => !error.is::<SessionExpired>(),
+    }
+}
+
+fn check_status(response: Response) -> Result<Response, Box<dyn Error>> {
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Err(SessionExpired.into());
+    }
+    Ok(response.error_for_status()?)
+}
+
+/// Continues a list after `before`, or starts with its latest item without one.
+fn page_query(before: Option<PageCursor>) -> Result<Vec<(&'static str, String)>, Box<dyn Error>> {
+    let Some(before) = before else {
+        return Ok(Vec::new());
+    };
+    Ok(vec![
+        ("before_at", before.at.format(&Rfc3339)?),
+        ("before_id", before.id.to_string()),
+    ])
+}
+
+pub async fn fetch_user_threads(
+    connection: &Connection,
+    before: Option<PageCursor>,
+) -> Result<FetchUserThreadsResponse, Box<dyn Error>> {
     let client = Client::new();
     let response = client
-        .get(format!("{BASE_URL}/chats"))
-        .header("Authorization", format!("Bearer {USER_ID}"))
+        .get(format!("{}/chats", connection.base_url))
+        .query(&page_query(before)?)
+        .header("Authorization", format!("Bearer {}", connection.session.token))
         .send()
         .await?;
-    Ok(response.json::<FetchUserThreadsResponse>().await?)
+    Ok(check_status(response)?.json::<FetchUserThreadsResponse>().await?)
 }
 
-pub async fn fetch_thread(thread_id: Uuid) -> Result<FetchThreadResponse, Box<dyn Error>> {
+pub async fn fetch_thread(
+    connection: &Connection,
+    thread_id: Uuid,
+    before: Option<PageCursor>,
+) -> Result<FetchThreadResponse, Box<dyn Error>> {
     let client = Client::new();
     let response = client
-        .get(format!("{BASE_URL}/chat/{thread_id}"))
-        .header("Authorization", format!("Bearer {USER_ID}"))
+        .get(format!("{}/chat/{thread_id}", connection.base_url))
+        .query(&page_query(before)?)
+        .header("Authorization", format!("Bearer {}", connection.session.token))
         .send()
         .await?;
-    Ok(response.json::<FetchThreadResponse>().await?)
+    Ok(check_status(response)?.json::<FetchThreadResponse>().await?)
 }
 
 pub async fn send_message(
+    connection: &Connection,
     message: &ChatMessage,
     is_new_thread: bool,
 ) -> Result<SendMessageResponse, Box<dyn Error>> {
     let client = Client::new();
     match client
-        .post(format!("{BASE_URL}/chat"))
-        .header("Authorization", format!("Bearer {USER_ID}"))
+        .post(format!("{}/chat", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
         .json(&SendMessageRequest {
-            from_user_id: USER_ID,
+            from_user_id: connection.session.user_id,
             message: message.clone(),
             is_new_thread,
+            utc_offset_minutes: time::UtcOffset::current_local_offset()
+                .ok()
+                .map(|offset| offset.whole_minutes()),
         })
         .send()
         .await
     {
         Ok(res) => {
-            if let Ok(response) = res.json::<SendMessageResponse>().await {
+            if let Ok(response) = check_status(res)?.json::<SendMessageResponse>().await {
                 Ok(response)
             } else {
                 Err("Failed to send message".into())
@@ -54,3 +121,230 @@ pub async fn send_message(
         Err(error) => Err(error.into()),
     }
 }
+
+const ONLY_OWNER: &str = "Only the owner of the thread can do that";
+const ONLY_AUTHOR: &str = "Only the author of the message can do that";
+const NOT_ALLOWED: &str = "You aren't allowed to do that in this thread";
+
+/// Like `check_status`, but passes on the service's explanation of a rejected request,
+/// falling back to `forbidden` when the service refuses without giving a reason.
+async fn check_response(response: Response, forbidden: &str) -> Result<Response, Box<dyn Error>> {
+    let status = response.status();
+    if status == StatusCode::BAD_REQUEST || status == StatusCode::FORBIDDEN {
+        let message = response.text().await?;
+        let message = match serde_json::from_str::<HttpErrorBody>(&message) {
+            Ok(body) => String::from(body.error),
+            Err(_) if status == StatusCode::FORBIDDEN => forbidden.to_string(),
+            Err(_) => message,
+        };
+        return Err(message.into());
+    }
+    check_status(response)
+}
+
+pub async fn edit_message(
+    connection: &Connection,
+    thread_id: Uuid,
+    message_id: Uuid,
+    content: &str,
+) -> Result<EditMessageResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .patch(format!("{}/chat/{thread_id}/message/{message_id}", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .json(&EditMessageRequest {
+            from_user_id: connection.session.user_id,
+            thread_id,
+            message_id,
+            content: content.to_string(),
+        })
+        .send()
+        .await?;
+    Ok(check_response(response, ONLY_AUTHOR).await?.json::<EditMessageResponse>().await?)
+}
+
+pub async fn delete_message(
+    connection: &Connection,
+    thread_id: Uuid,
+    message_id: Uuid,
+) -> Result<DeleteMessageResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .delete(format!("{}/chat/{thread_id}/message/{message_id}", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .send()
+        .await?;
+    Ok(check_response(response, ONLY_AUTHOR).await?.json::<DeleteMessageResponse>().await?)
+}
+
+pub async fn regenerate_reply(
+    connection: &Connection,
+    thread_id: Uuid,
+    message_id: Uuid,
+) -> Result<RegenerateReplyResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{}/chat/{thread_id}/message/{message_id}/regenerate", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .send()
+        .await?;
+    Ok(check_response(response, "Only participants of the thread can do that")
+        .await?
+        .json::<RegenerateReplyResponse>()
+        .await?)
+}
+
+pub async fn fetch_participants(
+    connection: &Connection,
+    thread_id: Uuid,
+) -> Result<FetchParticipantsResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/chat/{thread_id}/participants", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .send()
+        .await?;
+    Ok(check_status(response)?.json::<FetchParticipantsResponse>().await?)
+}
+
+pub async fn invite_participant(
+    connection: &Connection,
+    thread_id: Uuid,
+    user_id: Uuid,
+) -> Result<InviteParticipantResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{}/chat/{thread_id}/participants", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .json(&InviteParticipantRequest {
+            from_user_id: connection.session.user_id,
+            thread_id,
+            user_id,
+        })
+        .send()
+        .await?;
+    Ok(check_response(response, NOT_ALLOWED).await?.json::<InviteParticipantResponse>().await?)
+}
+
+pub async fn remove_participant(
+    connection: &Connection,
+    thread_id: Uuid,
+    user_id: Uuid,
+) -> Result<RemoveParticipantResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .delete(format!("{}/chat/{thread_id}/participant/{user_id}", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .send()
+        .await?;
+    Ok(check_response(response, ONLY_OWNER).await?.json::<RemoveParticipantResponse>().await?)
+}
+
+pub async fn leave_thread(connection: &Connection, thread_id: Uuid) -> Result<LeaveThreadResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{}/chat/{thread_id}/leave", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .send()
+        .await?;
+    Ok(check_response(response, NOT_ALLOWED).await?.json::<LeaveThreadResponse>().await?)
+}
+
+pub async fn transfer_ownership(
+    connection: &Connection,
+    thread_id: Uuid,
+    new_owner_id: Uuid,
+) -> Result<TransferOwnershipResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{}/chat/{thread_id}/owner", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .json(&TransferOwnershipRequest {
+            from_user_id: connection.session.user_id,
+            thread_id,
+            new_owner_id,
+        })
+        .send()
+        .await?;
+    Ok(check_response(response, ONLY_OWNER).await?.json::<TransferOwnershipResponse>().await?)
+}
+
+pub async fn search(connection: &Connection, query: &str) -> Result<SearchResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/search", connection.base_url))
+        .query(&[("q", query)])
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .send()
+        .await?;
+    Ok(check_status(response)?.json::<SearchResponse>().await?)
+}
+
+/// Changes after `since`, or only where they start without it.
+pub async fn sync(connection: &Connection, since: Option<i64>) -> Result<SyncResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/sync", connection.base_url))
+        .query(&[("since", since)])
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .send()
+        .await?;
+    Ok(check_status(response)?.json::<SyncResponse>().await?)
+}
+
+pub async fn subscribe_events(
+    connection: &Connection,
+) -> Result<impl Stream<Item = Result<ChatEvent, Box<dyn Error>>>, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/events", connection.base_url))
+        .header("Authorization", format!("Bearer {}", connection.session.token))
+        .header("Accept", "text/event-stream")
+        .send()
+        .await?;
+    let response = check_status(response)?;
+    Ok(sse::decode(response.bytes_stream().boxed()).map(|event| {
+        let event = event?;
+        Ok(serde_json::from_str::<ChatEvent>(&event.data)?)
+    }))
+}
+
+/// Turns a token handed over by the auth frontend into a full session.
+pub async fn verify(config: &ClientConfig, token: &str) -> Result<Session, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{}/verify", config.auth_url))
+        .json(&VerifyTokenRequest { token: token.into() })
+        .send()
+        .await?;
+    let verified = check_status(response)?.json::<VerifyTokenResponse>().await?;
+    Ok(Session {
+        token: token.into(),
+        user_id: verified.user_id,
+        expires_at: verified.expires_at,
+    })
+}
+
+pub async fn login(config: &ClientConfig, request: &PasswordLoginRequest) -> Result<LoginResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{}/login", config.auth_url))
+        .json(request)
+        .send()
+        .await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return Err("Wrong login or password".into());
+    }
+    Ok(check_status(response)?.json::<LoginResponse>().await?)
+}
+
+pub async fn logout(config: &ClientConfig, token: &str) -> Result<LogoutResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{}/logout", config.auth_url))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await?;
+    check_status(response)?;
+    Ok(())
+}
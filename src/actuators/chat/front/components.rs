@@ -3,11 +3,17 @@ use dioxus::prelude::*;
 mod chat;
 mod chat_message;
 mod layout;
+mod participant_list;
+mod session;
+mod sidebar_search;
 mod sidebar_thread_link;
 
 pub use chat::{Chat, NewChat};
 pub use chat_message::ChatMessage;
 pub use layout::Layout;
+pub use participant_list::ParticipantList;
+pub use session::{Login, Logout, Settings, SignedIn};
+pub use sidebar_search::SidebarSearch;
 pub use sidebar_thread_link::SidebarThreadLink;
 
 #[component]
@@ -16,5 +22,23 @@ pub fn Style() -> Element {
         document::Stylesheet { href: chat::CSS }
         document::Stylesheet { href: chat_message::CSS }
         document::Stylesheet { href: layout::CSS }
+        document::Stylesheet { href: participant_list::CSS }
+        document::Stylesheet { href: session::CSS }
     }
 }
+
+/// How close to the end of a list, in pixels, scrolling loads more of it
+const LOAD_MORE_DISTANCE: f64 = 200.0;
+
+/// Whether `element` is scrolled close to its end, or too short to scroll at all.
+/// The offset is negative in `column-reverse` lists, which end at the top.
+async fn is_near_scroll_end(element: &MountedData) -> bool {
+    let (Ok(offset), Ok(size), Ok(rect)) = (
+        element.get_scroll_offset().await,
+        element.get_scroll_size().await,
+        element.get_client_rect().await,
+    ) else {
+        return false;
+    };
+    size.height - rect.height() - offset.y.abs() < LOAD_MORE_DISTANCE
+}
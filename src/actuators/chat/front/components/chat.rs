@@ -1,10 +1,11 @@
 use dioxus::prelude::*;
+use std::rc::Rc;
 use uuid::Uuid;
 
-use super::ChatMessage;
+use super::{is_near_scroll_end, ChatMessage, ParticipantList};
 use crate::actuators::chat::front::state::{
-    State, SyncState,
-    actions::{FetchThreadAction, SendMessageAction},
+    MessageTree, State, SyncState,
+    actions::{FetchOlderMessagesAction, FetchThreadAction, SendMessageAction},
 };
 
 pub static CSS: Asset = asset!("/src/actuators/chat/front/components/chat.css");
@@ -13,9 +14,13 @@ pub static CSS: Asset = asset!("/src/actuators/chat/front/components/chat.css");
 pub fn Chat(thread_id: Option<Uuid>) -> Element {
     let state = use_context::<State>();
     let mut input = use_signal(|| String::new());
+    // An earlier message the next one answers, starting a new branch
+    let mut branch_from = use_signal(|| None::<Uuid>);
     let navigator = use_navigator();
     let dispatch_send_message = use_coroutine_handle::<SendMessageAction>();
     let dispatch_fetch_thread = use_coroutine_handle::<FetchThreadAction>();
+    let dispatch_fetch_older_messages = use_coroutine_handle::<FetchOlderMessagesAction>();
+    let mut history = use_signal(|| None::<Rc<MountedData>>);
     let is_synced_thread = {
         if let Some(thread_id) = thread_id
             && let Some(thread_sync_state) = state.threads.read().get(&thread_id)
@@ -27,21 +32,89 @@ pub fn Chat(thread_id: Option<Uuid>) -> Element {
         }
     };
 
+    use_effect(use_reactive((&thread_id,), move |_| branch_from.set(None)));
+
     use_effect(use_reactive!(|thread_id, is_synced_thread| {
         if is_synced_thread && let Some(thread_id) = thread_id {
             dispatch_fetch_thread.send(thread_id);
         }
     }));
 
+    // Older messages load while the history is scrolled near its top, or until the
+    // selected message, which a search may have led to, is loaded
+    let load_older_messages = move |thread_id: Uuid| {
+        let Some(Some(before)) = state.thread_message_next_pages.peek().get(&thread_id).copied() else {
+            return;
+        };
+        let is_selected_missing = state
+            .selected_messages
+            .peek()
+            .get(&thread_id)
+            .is_some_and(|message_id| !state.messages.peek().contains_key(message_id));
+        spawn(async move {
+            let is_near_top = match history.peek().clone() {
+                Some(history) => is_near_scroll_end(&history).await,
+                None => false,
+            };
+            if is_selected_missing || is_near_top {
+                dispatch_fetch_older_messages.send(FetchOlderMessagesAction { thread_id, before });
+            }
+        });
+    };
+
+    use_effect(use_reactive!(|thread_id| {
+        // Checks again whenever a page arrives or a message gets selected
+        state.thread_message_next_pages.read();
+        state.selected_messages.read();
+        history.read();
+        if let Some(thread_id) = thread_id {
+            load_older_messages(thread_id);
+        }
+    }));
+
+    let thread_message_ids = match thread_id {
+        Some(thread_id) => state
+            .thread_message_ids
+            .read()
+            .get(&thread_id)
+            .unwrap_or(&vec![])
+            .clone(),
+        None => vec![],
+    };
+
+    let selected = thread_id.and_then(|thread_id| state.selected_messages.read().get(&thread_id).copied());
+    let tree = MessageTree::new(&state.messages.read(), &thread_message_ids);
+    let branch = tree.branch(selected);
+
+    // Only the latest reply can be regenerated, from the human message it answers
+    let regenerate = {
+        let messages = state.messages.read();
+        let is_from_human = |message_id: &Uuid| {
+            messages
+                .get(message_id)
+                .and_then(|message| message.read())
+                .is_some_and(|message| message.user_id.is_some_and(|user_id| !user_id.is_nil()))
+        };
+        match branch.iter().rposition(is_from_human) {
+            Some(position) if position + 1 < branch.len() => branch
+                .last()
+                .map(|reply_id| (*reply_id, branch[position])),
+            _ => None,
+        }
+    };
+
+    let branch_tip = branch.last().copied();
     let mut handle_send = move || {
         let (is_new_thread, thread_id) = match thread_id {
             Some(thread_id) => (false, thread_id),
             None => (true, Uuid::new_v4()),
         };
+        let parent_id = branch_from.take().or(branch_tip);
         dispatch_send_message.send(SendMessageAction {
             content: input.read().clone(),
             thread_id,
             is_new_thread,
+            parent_id,
         });
         input.set(String::new());
         if is_new_thread {
@@ -56,23 +129,40 @@ pub fn Chat(thread_id: Option<Uuid>) -> Element {
         }
     };
 
-    let thread_message_ids = match thread_id {
-        Some(thread_id) => state
-            .thread_message_ids
-            .read()
-            .get(&thread_id)
-            .unwrap_or(&vec![])
-            .clone(),
-        None => vec![],
-    };
-
     rsx! {
         div { class: "chat",
+            if is_synced_thread && let Some(thread_id) = thread_id {
+                ParticipantList { thread_id }
+            }
             div { class: "chat__history",
-                for message_id in thread_message_ids.iter().rev() {
+                onmounted: move |evt| history.set(Some(evt.data())),
+                onscroll: move |_| {
+                    if let Some(thread_id) = thread_id {
+                        load_older_messages(thread_id);
+                    }
+                },
+                for message_id in branch.iter().rev() {
                     ChatMessage {
                         key: "{message_id}",
                         message_id: *message_id,
+                        branch_position: tree.position(*message_id),
+                        on_branch_from: (Some(message_id) != branch.last()).then_some({
+                            let message_id = *message_id;
+                            EventHandler::new(move |_| branch_from.set(Some(message_id)))
+                        }),
+                        regenerate_from: regenerate
+                            .filter(|(reply_id, _)| reply_id == message_id)
+                            .map(|(_, human_message_id)| human_message_id),
+                    }
+                }
+            }
+            if branch_from().is_some() {
+                div { class: "chat__branching",
+                    "Answering an earlier message, in a new branch"
+                    button {
+                        class: "chat__branching-cancel",
+                        onclick: move |_| branch_from.set(None),
+                        "Cancel"
                     }
                 }
             }
@@ -1,13 +1,45 @@
 use dioxus::prelude::*;
+use std::rc::Rc;
 use uuid::Uuid;
 
-use crate::actuators::chat::front::state::State;
+use crate::actuators::chat::front::state::{
+    BranchPosition, State,
+    actions::{
+        DeleteMessageAction, DiscardMessageAction, EditMessageAction, RegenerateReplyAction, RetryMessageAction,
+    },
+};
 
 pub static CSS: Asset = asset!("/src/actuators/chat/front/components/chat_message.css");
+/// With `regenerate_from`, the message is the latest reply and offers to be
+/// replaced by a new reply to that message. With `branch_position`, it offers to
+/// switch to the branches of its siblings, and with `on_branch_from` to start a new one.
 #[component]
-pub fn ChatMessage(message_id: Uuid) -> Element {
+pub fn ChatMessage(
+    message_id: Uuid,
+    regenerate_from: Option<Uuid>,
+    branch_position: Option<BranchPosition>,
+    on_branch_from: Option<EventHandler>,
+) -> Element {
     let b = classnames::classname("chat-message");
-    let state = use_context::<State>();
+    let mut state = use_context::<State>();
+    let mut draft = use_signal(|| None::<String>);
+    let dispatch_edit_message = use_coroutine_handle::<EditMessageAction>();
+    let dispatch_delete_message = use_coroutine_handle::<DeleteMessageAction>();
+    let dispatch_regenerate_reply = use_coroutine_handle::<RegenerateReplyAction>();
+    let dispatch_retry_message = use_coroutine_handle::<RetryMessageAction>();
+    let dispatch_discard_message = use_coroutine_handle::<DiscardMessageAction>();
+    let is_unsent = state.outbox.read().iter().any(|entry| entry.message.id == message_id);
+    let mut element = use_signal(|| None::<Rc<MountedData>>);
+    let is_highlighted = *state.highlighted_message.read() == Some(message_id);
+    use_effect(move || {
+        if *state.highlighted_message.read() == Some(message_id)
+            && let Some(element) = element()
+        {
+            spawn(async move {
+                let _ = element.scroll_to(ScrollBehavior::Smooth).await;
+            });
+        }
+    });
     let messages = state.messages.read();
     let message_state = messages
         .get(&message_id)
@@ -19,17 +51,31 @@ pub fn ChatMessage(message_id: Uuid) -> Element {
     match message_state.read() {
         None => rsx! {},
         Some(message) => {
-            let my_user_id = *use_context::<State>().user_id.read();
+            let my_user_id = use_context::<State>().user_id();
             let b = match message.user_id {
                 None => b.attr("event"),
                 Some(id) => {
                     let message_source = match id {
-                        _ if id == my_user_id => "user-me",
+                        _ if Some(id) == my_user_id => "user-me",
                         _ if id == Uuid::nil() => "user-artilect",
                         _ => "user-other",
                     };
                     b.attr("message").attr(message_source)
                 }
+            }
+            .maybe_attr("highlighted", is_highlighted);
+            // Only worth naming when someone else is talking
+            let author_name = message
+                .user_id
+                .filter(|id| Some(*id) != my_user_id && !id.is_nil())
+                .and_then(|id| state.users.read().get(&id).and_then(|user| user.read().map(|user| user.name.clone())));
+            let is_editable = !is_syncing && message.user_id.is_some() && message.user_id == my_user_id;
+            let thread_id = message.thread_id;
+            let mut handle_save = move || {
+                let Some(content) = draft.take() else {
+                    return;
+                };
+                dispatch_edit_message.send(EditMessageAction { thread_id, message_id, content });
             };
             let rendered_markdown = match markdown::to_html_with_options(&message.content, &markdown::Options::gfm()) {
                 Ok(rendered) => rendered,
@@ -38,9 +84,100 @@ pub fn ChatMessage(message_id: Uuid) -> Element {
             rsx! {
                 div {
                     class: b.to_string(),
-                    div {
-                        class: b.el("text").to_string(),
-                        dangerous_inner_html: rendered_markdown
+                    onmounted: move |event| element.set(Some(event.data())),
+                    if let Some(author_name) = author_name {
+                        p {
+                            class: b.el("author").to_string(),
+                            "{author_name}"
+                        }
+                    }
+                    if let Some(content) = draft() {
+                        div {
+                            class: b.el("editor").to_string(),
+                            textarea {
+                                class: b.el("editor-field").to_string(),
+                                value: "{content}",
+                                oninput: move |evt| draft.set(Some(evt.value())),
+                            }
+                            button {
+                                class: b.el("action").to_string(),
+                                disabled: content.trim().is_empty(),
+                                onclick: move |_| handle_save(),
+                                "Save"
+                            }
+                            button {
+                                class: b.el("action").to_string(),
+                                onclick: move |_| draft.set(None),
+                                "Cancel"
+                            }
+                        }
+                    } else {
+                        div {
+                            class: b.el("text").to_string(),
+                            dangerous_inner_html: rendered_markdown
+                        }
+                    }
+                    if draft.read().is_none() {
+                        div {
+                            class: b.el("actions").to_string(),
+                            if let Some(BranchPosition { index, count, previous, next }) = branch_position {
+                                span {
+                                    class: b.el("branches").to_string(),
+                                    button {
+                                        class: b.el("branch-switch").to_string(),
+                                        disabled: previous.is_none(),
+                                        onclick: move |_| if let Some(previous) = previous {
+                                            state.selected_messages.with_mut(|selected| selected.insert(thread_id, previous));
+                                        },
+                                        "‹"
+                                    }
+                                    "{index + 1}/{count}"
+                                    button {
+                                        class: b.el("branch-switch").to_string(),
+                                        disabled: next.is_none(),
+                                        onclick: move |_| if let Some(next) = next {
+                                            state.selected_messages.with_mut(|selected| selected.insert(thread_id, next));
+                                        },
+                                        "›"
+                                    }
+                                }
+                            }
+                            if message.updated_at.is_some() {
+                                span { class: b.el("edited").to_string(), "edited" }
+                            }
+                            if is_editable {
+                                button {
+                                    class: b.el("action").to_string(),
+                                    onclick: {
+                                        let content = message.content.clone();
+                                        move |_| draft.set(Some(content.clone()))
+                                    },
+                                    "Edit"
+                                }
+                                button {
+                                    class: b.el("action").to_string(),
+                                    onclick: move |_| dispatch_delete_message.send(DeleteMessageAction { thread_id, message_id }),
+                                    "Delete"
+                                }
+                            }
+                            if let Some(on_branch_from) = on_branch_from {
+                                button {
+                                    class: b.el("action").to_string(),
+                                    onclick: move |_| on_branch_from.call(()),
+                                    "Branch from here"
+                                }
+                            }
+                            if let Some(regenerate_from) = regenerate_from {
+                                button {
+                                    class: b.el("action").to_string(),
+                                    onclick: move |_| dispatch_regenerate_reply.send(RegenerateReplyAction {
+                                        thread_id,
+                                        message_id: regenerate_from,
+                                    }),
+                                    "Regenerate"
+                                }
+                            }
+                        }
                     }
                     if is_syncing {
                         p {
@@ -52,6 +189,19 @@ pub fn ChatMessage(message_id: Uuid) -> Element {
                         p {
                             class: b.el("error").to_string(),
                             "Error: {error}"
+                            // Failed sends wait in the outbox, to be retried or given up on
+                            if is_unsent {
+                                button {
+                                    class: b.el("action").to_string(),
+                                    onclick: move |_| dispatch_retry_message.send(RetryMessageAction(message_id)),
+                                    "Retry"
+                                }
+                                button {
+                                    class: b.el("action").to_string(),
+                                    onclick: move |_| dispatch_discard_message.send(DiscardMessageAction(message_id)),
+                                    "Discard"
+                                }
+                            }
                         }
                     }
                 }
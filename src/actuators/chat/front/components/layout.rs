@@ -1,9 +1,13 @@
 use dioxus::prelude::*;
+use std::rc::Rc;
 use uuid::Uuid;
 // use tokio::time::{sleep, Duration};
 
-use super::SidebarThreadLink;
-use crate::actuators::chat::front::{state::State, Route};
+use super::{is_near_scroll_end, SidebarSearch, SidebarThreadLink};
+use crate::actuators::chat::front::{
+    state::{actions::FetchMoreThreadsAction, SearchResults, State},
+    Route,
+};
 
 pub static CSS: Asset = asset!("/src/actuators/chat/front/components/layout.css");
 
@@ -11,7 +15,39 @@ pub static CSS: Asset = asset!("/src/actuators/chat/front/components/layout.css"
 pub fn Layout() -> Element {
     let b = classnames::classname("app");
     let state = use_context::<State>();
+    let navigator = use_navigator();
+    let dispatch_fetch_more_threads = use_coroutine_handle::<FetchMoreThreadsAction>();
+    let mut thread_list = use_signal(|| None::<Rc<MountedData>>);
+    use_effect(move || {
+        if state.session.read().is_none() {
+            navigator.replace(Route::Login {});
+        }
+    });
+
+    // Older threads load while the list is scrolled near its end
+    let load_more_threads = move || {
+        let Some(before) = *state.thread_list_next_page.peek() else {
+            return;
+        };
+        spawn(async move {
+            if let Some(thread_list) = thread_list.peek().clone()
+                && is_near_scroll_end(&thread_list).await
+            {
+                dispatch_fetch_more_threads.send(FetchMoreThreadsAction(before));
+            }
+        });
+    };
+    use_effect(move || {
+        // Checks again whenever a page arrives, as it may not fill the list
+        state.thread_list_next_page.read();
+        thread_list.read();
+        load_more_threads();
+    });
+    if state.session.read().is_none() {
+        return rsx! {};
+    }
     let thread_ids: Vec<Uuid> = state.thread_list.read().clone();
+    let is_searching = !matches!(*state.search_results.read(), SearchResults::Idle);
     // let mut has_recently_scrolled = use_signal(|| false);
     // let toggle_recently_scrolled = use_coroutine(move |mut rx: UnboundedReceiver<()>| async move {
     //     // let mut disable_recently_scrolled = std::future::pending();
@@ -38,17 +74,28 @@ pub fn Layout() -> Element {
                     to: Route::NewChat {},
                     "New Chat"
                 }
-                div { class: b.el("thread-list").maybe_attr("recently-scrolled", false /* *has_recently_scrolled.read() */).to_string(),
-                    // onscroll: move |evt| {
-                    //     has_recently_scrolled.set(true);
-                    // },
-                    for thread_id in thread_ids {
-                        SidebarThreadLink {
-                            key: "{thread_id}",
-                            thread_id: thread_id,
+                SidebarSearch {}
+                if !is_searching {
+                    div { class: b.el("thread-list").maybe_attr("recently-scrolled", false /* *has_recently_scrolled.read() */).to_string(),
+                        onmounted: move |evt| thread_list.set(Some(evt.data())),
+                        onscroll: move |_| load_more_threads(),
+                        // onscroll: move |evt| {
+                        //     has_recently_scrolled.set(true);
+                        // },
+                        for thread_id in thread_ids {
+                            SidebarThreadLink {
+                                key: "{thread_id}",
+                                thread_id: thread_id,
+                            }
                         }
                     }
                 }
+                div { class: b.el("session").to_string(),
+                    if cfg!(feature = "desktop") {
+                        Link { to: Route::Settings {}, "Servers" }
+                    }
+                    Link { to: Route::Logout {}, "Sign out" }
+                }
             }
             Outlet::<Route> {}
         }
@@ -0,0 +1,140 @@
+use dioxus::prelude::*;
+use uuid::Uuid;
+
+use crate::actuators::chat::front::{
+    Route,
+    state::{
+        State,
+        actions::{
+            FetchParticipantsAction, InviteParticipantAction, LeaveThreadAction, RemoveParticipantAction,
+            TransferOwnershipAction,
+        },
+    },
+};
+
+pub static CSS: Asset = asset!("/src/actuators/chat/front/components/participant_list.css");
+
+/// Who is in the thread, with controls to invite people, hand the thread
+/// over or leave it.
+#[component]
+pub fn ParticipantList(thread_id: Uuid) -> Element {
+    let b = classnames::classname("participants");
+    let state = use_context::<State>();
+    let navigator = use_navigator();
+    let dispatch_fetch = use_coroutine_handle::<FetchParticipantsAction>();
+    let dispatch_invite = use_coroutine_handle::<InviteParticipantAction>();
+    let dispatch_remove = use_coroutine_handle::<RemoveParticipantAction>();
+    let dispatch_leave = use_coroutine_handle::<LeaveThreadAction>();
+    let dispatch_transfer = use_coroutine_handle::<TransferOwnershipAction>();
+    let mut invitee = use_signal(String::new);
+    let mut invite_error = use_signal(|| None::<String>);
+
+    use_effect(use_reactive!(|thread_id| {
+        dispatch_fetch.send(FetchParticipantsAction(thread_id));
+    }));
+
+    let my_user_id = state.user_id();
+    let owner_id = state
+        .threads
+        .read()
+        .get(&thread_id)
+        .and_then(|thread| thread.read().map(|thread| thread.owner_id));
+    let is_owner = owner_id.is_some() && owner_id == my_user_id;
+    let participants = state
+        .thread_participant_ids
+        .read()
+        .get(&thread_id)
+        .map(|ids| {
+            let users = state.users.read();
+            ids.iter()
+                .filter_map(|id| users.get(id).and_then(|user| user.read().cloned()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let error = invite_error().or_else(|| state.participant_errors.read().get(&thread_id).cloned());
+
+    let handle_invite = move |evt: FormEvent| {
+        evt.prevent_default();
+        let parsed = Uuid::parse_str(invitee.read().trim());
+        match parsed {
+            Ok(user_id) => {
+                invite_error.set(None);
+                invitee.set(String::new());
+                dispatch_invite.send(InviteParticipantAction { thread_id, user_id });
+            }
+            Err(_) => invite_error.set(Some("That doesn't look like a user ID".into())),
+        }
+    };
+
+    rsx! {
+        div { class: b.to_string(),
+            ul { class: b.el("list").to_string(),
+                for user in participants {
+                    li {
+                        key: "{user.id}",
+                        class: b.el("item").to_string(),
+                        title: "{user.id}",
+                        span { class: b.el("name").to_string(), "{user.name}" }
+                        if Some(user.id) == owner_id {
+                            span { class: b.el("badge").to_string(), "owner" }
+                        }
+                        if Some(user.id) == my_user_id {
+                            span { class: b.el("badge").to_string(), "you" }
+                        } else if is_owner {
+                            if !user.id.is_nil() {
+                                button {
+                                    class: b.el("action").to_string(),
+                                    title: "Make {user.name} the owner",
+                                    onclick: move |_| {
+                                        dispatch_transfer.send(TransferOwnershipAction { thread_id, new_owner_id: user.id });
+                                    },
+                                    "♛"
+                                }
+                            }
+                            button {
+                                class: b.el("action").to_string(),
+                                title: "Remove {user.name}",
+                                onclick: move |_| {
+                                    dispatch_remove.send(RemoveParticipantAction { thread_id, user_id: user.id });
+                                },
+                                "×"
+                            }
+                        }
+                    }
+                }
+            }
+            form { class: b.el("invite").to_string(), onsubmit: handle_invite,
+                input {
+                    class: b.el("input").to_string(),
+                    r#type: "text",
+                    placeholder: "User ID to invite",
+                    value: "{invitee}",
+                    oninput: move |evt| invitee.set(evt.value()),
+                }
+                button {
+                    class: b.el("button").to_string(),
+                    r#type: "submit",
+                    disabled: invitee.read().trim().is_empty(),
+                    "Invite"
+                }
+                if !is_owner {
+                    button {
+                        class: b.el("button").to_string(),
+                        r#type: "button",
+                        onclick: move |_| {
+                            dispatch_leave.send(LeaveThreadAction(thread_id));
+                            navigator.push(Route::NewChat {});
+                        },
+                        "Leave"
+                    }
+                }
+            }
+            if let Some(error) = error {
+                p { class: b.el("error").to_string(), "{error}" }
+            }
+            if let Some(my_user_id) = my_user_id {
+                p { class: b.el("hint").to_string(), "Others can invite you by your ID: {my_user_id}" }
+            }
+        }
+    }
+}
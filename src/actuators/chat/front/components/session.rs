@@ -0,0 +1,269 @@
+use dioxus::logger::tracing::error;
+use dioxus::prelude::*;
+
+use crate::actuators::chat::front::{
+    Route, api,
+    config::{ClientConfig, origin},
+    state::State,
+};
+use crate::auth::dto::PasswordLoginRequest;
+
+pub static CSS: Asset = asset!("/src/actuators/chat/front/components/session.css");
+
+#[component]
+fn FormError(error: Signal<Option<String>>) -> Element {
+    let b = classnames::classname("session-form");
+    rsx! {
+        if let Some(error) = error() {
+            p { class: b.el("error").to_string(), "{error}" }
+        }
+    }
+}
+
+/// On the web, signing in happens on the auth frontend, which comes back to
+/// `SignedIn` with a token. Desktop windows can't take part in redirects, so
+/// they sign in with a password right here.
+#[component]
+pub fn Login() -> Element {
+    let b = classnames::classname("session-form");
+    let state = use_context::<State>();
+    let navigator = use_navigator();
+    let mut login = use_signal(String::new);
+    let mut password = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+
+    use_effect(move || {
+        if state.session.read().is_some() {
+            navigator.replace(Route::NewChat {});
+        }
+    });
+
+    let handle_redirect = move |_| {
+        let auth_front_url = state.config.read().auth_front_url.clone();
+        spawn(async move {
+            let Some(origin) = origin().await else {
+                error.set(Some("Can't tell where to come back to after signing in".into()));
+                return;
+            };
+            let url = reqwest::Url::parse_with_params(
+                &format!("{auth_front_url}/"),
+                &[("redirect_to", format!("{origin}/signed-in"))],
+            );
+            match url {
+                Ok(url) => {
+                    navigator.push(NavigationTarget::<Route>::External(url.into()));
+                }
+                Err(err) => error.set(Some(format!("Invalid sign-in page URL: {err}"))),
+            }
+        });
+    };
+
+    let handle_submit = move |evt: FormEvent| {
+        evt.prevent_default();
+        let config = state.config.read().clone();
+        let request = PasswordLoginRequest {
+            login: login.read().as_str().into(),
+            password: password.read().as_str().into(),
+        };
+        spawn(async move {
+            match api::login(&config, &request).await {
+                Ok(session) => {
+                    error.set(None);
+                    password.set(String::new());
+                    state.set_session(Some(session));
+                }
+                Err(err) => error.set(Some(err.to_string())),
+            }
+        });
+    };
+
+    rsx! {
+        form { class: b.to_string(), onsubmit: handle_submit,
+            h1 { class: b.el("title").to_string(), "Sign in to Artilect" }
+            if cfg!(feature = "desktop") {
+                input {
+                    class: b.el("input").to_string(),
+                    r#type: "text",
+                    placeholder: "Login",
+                    autocomplete: "username",
+                    value: "{login}",
+                    oninput: move |evt| login.set(evt.value()),
+                }
+                input {
+                    class: b.el("input").to_string(),
+                    r#type: "password",
+                    placeholder: "Password",
+                    autocomplete: "current-password",
+                    value: "{password}",
+                    oninput: move |evt| password.set(evt.value()),
+                }
+                FormError { error }
+                button { class: b.el("submit").to_string(), r#type: "submit", "Sign in" }
+            } else {
+                FormError { error }
+                button {
+                    class: b.el("submit").to_string(),
+                    r#type: "button",
+                    onclick: handle_redirect,
+                    "Sign in"
+                }
+            }
+            div { class: b.el("links").to_string(),
+                Link { to: Route::Settings {}, "Server settings" }
+            }
+        }
+    }
+}
+
+/// Where the auth frontend sends the user back to, with the token in the fragment.
+#[component]
+pub fn SignedIn(fragment: String) -> Element {
+    let b = classnames::classname("session-form");
+    let state = use_context::<State>();
+    let navigator = use_navigator();
+    let mut error = use_signal(|| None::<String>);
+
+    use_hook(move || {
+        let token = fragment.strip_prefix("token=").unwrap_or_default().to_string();
+        let config = state.config.read().clone();
+        spawn(async move {
+            match api::verify(&config, &token).await {
+                Ok(session) => {
+                    state.set_session(Some(session));
+                    navigator.replace(Route::NewChat {});
+                }
+                Err(err) => error.set(Some(err.to_string())),
+            }
+        })
+    });
+
+    rsx! {
+        div { class: b.to_string(),
+            h1 { class: b.el("title").to_string(), "Signing in…" }
+            if let Some(error) = error() {
+                p { class: b.el("error").to_string(), "{error}" }
+                Link { to: Route::Login {}, "Back to sign in" }
+            }
+        }
+    }
+}
+
+/// Revokes the session. On the web, the auth frontend is signed out too, or
+/// it would sign the user straight back in.
+#[component]
+pub fn Logout() -> Element {
+    let b = classnames::classname("session-form");
+    let state = use_context::<State>();
+    let navigator = use_navigator();
+
+    use_hook(move || {
+        let config = state.config.read().clone();
+        let token = state.session.read().as_ref().map(|session| session.token.clone());
+        spawn(async move {
+            if let Some(token) = token
+                && let Err(err) = api::logout(&config, &token).await
+            {
+                // Forgetting the token is what matters here; revoking it is best-effort
+                error!("Failed to revoke the session: {}", err);
+            }
+            state.set_session(None);
+            let auth_front_logout = match cfg!(feature = "desktop") {
+                true => None,
+                false => origin().await.and_then(|origin| {
+                    reqwest::Url::parse_with_params(
+                        &format!("{}/logout", config.auth_front_url),
+                        &[("redirect_to", format!("{origin}/login"))],
+                    )
+                    .ok()
+                }),
+            };
+            match auth_front_logout {
+                Some(url) => navigator.replace(NavigationTarget::<Route>::External(url.into())),
+                None => navigator.replace(Route::Login {}),
+            };
+        })
+    });
+
+    rsx! {
+        div { class: b.to_string(),
+            h1 { class: b.el("title").to_string(), "Signing out…" }
+        }
+    }
+}
+
+/// Picks the chat and auth servers. Switching servers signs the user out.
+#[component]
+pub fn Settings() -> Element {
+    let b = classnames::classname("session-form");
+    let state = use_context::<State>();
+    let navigator = use_navigator();
+    let current = state.config.read().clone();
+    let mut server_url = use_signal(|| current.server_url.clone());
+    let mut auth_url = use_signal(|| current.auth_url.clone());
+    let mut auth_front_url = use_signal(|| current.auth_front_url.clone());
+    let mut error = use_signal(|| None::<String>);
+
+    let handle_submit = move |evt: FormEvent| {
+        evt.prevent_default();
+        let config = ClientConfig {
+            server_url: server_url(),
+            auth_url: auth_url(),
+            auth_front_url: auth_front_url(),
+        };
+        match config.normalize() {
+            Ok(config) => {
+                state.set_config(config);
+                match state.session.read().is_some() {
+                    true => navigator.push(Route::NewChat {}),
+                    false => navigator.push(Route::Login {}),
+                };
+            }
+            Err(err) => error.set(Some(err)),
+        }
+    };
+
+    rsx! {
+        form { class: b.to_string(), onsubmit: handle_submit,
+            h1 { class: b.el("title").to_string(), "Servers" }
+            label { class: b.el("label").to_string(),
+                "Chat server"
+                input {
+                    class: b.el("input").to_string(),
+                    r#type: "url",
+                    value: "{server_url}",
+                    oninput: move |evt| server_url.set(evt.value()),
+                }
+            }
+            label { class: b.el("label").to_string(),
+                "Auth server"
+                input {
+                    class: b.el("input").to_string(),
+                    r#type: "url",
+                    value: "{auth_url}",
+                    oninput: move |evt| auth_url.set(evt.value()),
+                }
+            }
+            if !cfg!(feature = "desktop") {
+                label { class: b.el("label").to_string(),
+                    "Sign-in page"
+                    input {
+                        class: b.el("input").to_string(),
+                        r#type: "url",
+                        value: "{auth_front_url}",
+                        oninput: move |evt| auth_front_url.set(evt.value()),
+                    }
+                }
+            }
+            FormError { error }
+            button { class: b.el("submit").to_string(), r#type: "submit", "Save" }
+            div { class: b.el("links").to_string(),
+                button {
+                    class: b.el("link").to_string(),
+                    r#type: "button",
+                    onclick: move |_| navigator.go_back(),
+                    "Cancel"
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use dioxus::prelude::*;
+
+use crate::actuators::chat::front::{
+    state::{actions::SearchAction, SearchResults, State},
+    Route,
+};
+
+/// Searches on submit, and lists what it found in place of the threads until cleared.
+#[component]
+pub fn SidebarSearch() -> Element {
+    let b = classnames::classname("app");
+    let mut state = use_context::<State>();
+    let navigator = use_navigator();
+    let dispatch_search = use_coroutine_handle::<SearchAction>();
+    let results = state.search_results.read().clone();
+
+    let handle_search = move |evt: FormEvent| {
+        evt.prevent_default();
+        dispatch_search.send(SearchAction(state.search_query.read().clone()));
+    };
+
+    rsx! {
+        form { class: b.el("search").to_string(), onsubmit: handle_search,
+            input {
+                class: b.el("search-field").to_string(),
+                r#type: "search",
+                placeholder: "Search chats",
+                value: "{state.search_query}",
+                oninput: move |evt| {
+                    let query = evt.value();
+                    if query.trim().is_empty() {
+                        state.search_results.set(SearchResults::Idle);
+                    }
+                    state.search_query.set(query);
+                },
+            }
+        }
+        match results {
+            SearchResults::Idle => rsx! {},
+            SearchResults::Searching => rsx! {
+                p { class: b.el("search-status").to_string(), "Searching…" }
+            },
+            SearchResults::Failed(error) => rsx! {
+                p { class: b.el("search-status").to_string(), "Search failed: {error}" }
+            },
+            SearchResults::Found(hits) if hits.is_empty() => rsx! {
+                p { class: b.el("search-status").to_string(), "Nothing found" }
+            },
+            SearchResults::Found(hits) => rsx! {
+                div { class: b.el("thread-list").to_string(),
+                    for hit in hits {
+                        button {
+                            key: "{hit.thread_id}-{hit.message_id:?}",
+                            class: b.el("search-hit").to_string(),
+                            onclick: move |_| {
+                                // Shows the branch the message is on, then scrolls to it
+                                if let Some(message_id) = hit.message_id {
+                                    state.selected_messages.write().insert(hit.thread_id, message_id);
+                                }
+                                state.highlighted_message.set(hit.message_id);
+                                navigator.push(Route::Chat { thread_id: hit.thread_id });
+                            },
+                            if hit.message_id.is_some() {
+                                span { class: b.el("search-hit-thread").to_string(),
+                                    {hit.thread_name.as_deref().unwrap_or("Untitled Chat")}
+                                }
+                            }
+                            span { class: b.el("search-hit-snippet").to_string(),
+                                for part in hit.snippet.iter() {
+                                    if part.highlighted {
+                                        mark { "{part.text}" }
+                                    } else {
+                                        "{part.text}"
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+        }
+    }
+}
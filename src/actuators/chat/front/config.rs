@@ -0,0 +1,71 @@
+use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::storage;
+
+/// Which chat and auth services to talk to, chosen at runtime.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientConfig {
+    /// Chat backend
+    pub server_url: String,
+    /// Auth backend, which verifies and revokes session tokens
+    pub auth_url: String,
+    /// Auth frontend, which the web build sends users to for signing in
+    pub auth_front_url: String,
+}
+
+impl Default for ClientConfig {
+    /// Where `dx serve` and the backends run during local development
+    fn default() -> Self {
+        Self {
+            server_url: "http://localhost:3001".into(),
+            auth_url: "http://localhost:3002".into(),
+            auth_front_url: "http://localhost:8081".into(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// The configuration saved on this device, else the one a web deployment
+    /// serves at `/config.json`, else the local development defaults.
+    pub async fn load() -> Self {
+        if let Some(config) = storage::load_config().await {
+            return config;
+        }
+        if !cfg!(feature = "desktop")
+            && let Some(config) = fetch_deployed().await
+        {
+            return config;
+        }
+        Self::default()
+    }
+
+    /// Checks that every URL is absolute and drops trailing slashes, so that
+    /// paths can be appended.
+    pub fn normalize(self) -> Result<Self, String> {
+        let normalize_url = |name: &str, url: String| {
+            let url = url.trim().trim_end_matches('/').to_string();
+            match reqwest::Url::parse(&url) {
+                Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(url),
+                _ => Err(format!("{name} must be an http:// or https:// URL")),
+            }
+        };
+        Ok(Self {
+            server_url: normalize_url("Chat server", self.server_url)?,
+            auth_url: normalize_url("Auth server", self.auth_url)?,
+            auth_front_url: normalize_url("Sign-in page", self.auth_front_url)?,
+        })
+    }
+}
+
+async fn fetch_deployed() -> Option<ClientConfig> {
+    let url = format!("{}/config.json", origin().await?);
+    let response = reqwest::get(url).await.ok()?.error_for_status().ok()?;
+    response.json::<ClientConfig>().await.ok()?.normalize().ok()
+}
+
+/// Scheme, host and port this app is served from.
+pub async fn origin() -> Option<String> {
+    let origin = document::eval("return window.location.origin;").await.ok()?;
+    origin.as_str().map(Into::into)
+}
@@ -1,32 +1,232 @@
 use dioxus::prelude::*;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
 pub mod actions;
-use crate::actuators::chat::dto::{ChatMessage, SyncUpdate, Thread};
+use super::api::Connection;
+use super::config::ClientConfig;
+use super::storage;
+use crate::actuators::chat::dto::{
+    ChatMessage, OneToManyChild, OneToManyUpdate, PageCursor, SearchHit, SyncUpdate, Thread, User,
+};
+use crate::auth::dto::Session;
 use crate::Identifiable;
 
-static USER_ID_STR: &str = dotenvy_macro::dotenv!("CHAT_USER_ID");
-
 #[derive(Debug, Clone, Copy)]
 pub struct State {
-    pub user_id: Signal<Uuid>,
+    pub config: Signal<ClientConfig>,
+    /// Whoever is signed in, if anyone
+    pub session: Signal<Option<Session>>,
     pub messages: Signal<HashMap<Uuid, SyncState<ChatMessage>>>,
     pub threads: Signal<HashMap<Uuid, SyncState<Thread>>>,
     pub thread_list: Signal<Vec<Uuid>>,
+    /// Where the next page of the thread list starts, while there is one
+    pub thread_list_next_page: Signal<Option<PageCursor>>,
     pub thread_message_ids: Signal<HashMap<Uuid, Vec<Uuid>>>,
+    /// Where the next page of older messages starts for each fetched thread, while there is one
+    pub thread_message_next_pages: Signal<HashMap<Uuid, Option<PageCursor>>>,
+    /// Where the next sync continues, once the events stream has connected
+    pub sync_cursor: Signal<Option<i64>>,
+    /// The message whose branch is shown for each thread, if not the latest one
+    pub selected_messages: Signal<HashMap<Uuid, Uuid>>,
+    /// The message the last picked search result led to
+    pub highlighted_message: Signal<Option<Uuid>>,
+    pub search_query: Signal<String>,
+    pub search_results: Signal<SearchResults>,
+    pub users: Signal<HashMap<Uuid, SyncState<User>>>,
+    pub thread_participant_ids: Signal<HashMap<Uuid, Vec<Uuid>>>,
+    /// Why the last change to a thread's participants failed
+    pub participant_errors: Signal<HashMap<Uuid, String>>,
+    /// Messages waiting to be sent, oldest first, from whoever wrote them on this device
+    pub outbox: Signal<Vec<OutboxEntry>>,
 }
 
 pub fn use_app_state() -> State {
     use_context_provider::<State>(|| State {
-        user_id: Signal::new(Uuid::parse_str(USER_ID_STR).expect("Failed to parse CHAT_USER_ID")),
+        config: Signal::new(ClientConfig::default()),
+        session: Signal::new(None),
         messages: Signal::new(HashMap::new()),
         threads: Signal::new(HashMap::new()),
         thread_list: Signal::new(Vec::new()),
+        thread_list_next_page: Signal::new(None),
         thread_message_ids: Signal::new(HashMap::new()),
+        thread_message_next_pages: Signal::new(HashMap::new()),
+        sync_cursor: Signal::new(None),
+        selected_messages: Signal::new(HashMap::new()),
+        highlighted_message: Signal::new(None),
+        search_query: Signal::new(String::new()),
+        search_results: Signal::new(SearchResults::Idle),
+        users: Signal::new(HashMap::new()),
+        thread_participant_ids: Signal::new(HashMap::new()),
+        participant_errors: Signal::new(HashMap::new()),
+        outbox: Signal::new(Vec::new()),
     })
 }
 
+impl State {
+    pub fn user_id(&self) -> Option<Uuid> {
+        self.session.read().as_ref().map(|session| session.user_id)
+    }
+
+    pub fn connection(&self) -> Option<Connection> {
+        let session = self.session.read().clone()?;
+        Some(Connection {
+            base_url: self.config.read().server_url.clone(),
+            session,
+        })
+    }
+
+    /// Remembers the session across restarts. Chats loaded for someone else
+    /// are dropped.
+    pub fn set_session(mut self, session: Option<Session>) {
+        storage::save_session(session.as_ref());
+        if self.user_id() != session.as_ref().map(|session| session.user_id) {
+            self.clear_chats();
+        }
+        self.session.set(session);
+    }
+
+    /// Switches to other servers, which signs the user out unless nothing changed.
+    pub fn set_config(mut self, config: ClientConfig) {
+        if *self.config.read() == config {
+            return;
+        }
+        storage::save_config(&config);
+        self.set_session(None);
+        self.clear_chats();
+        self.config.set(config);
+    }
+
+    fn clear_chats(mut self) {
+        self.messages.write().clear();
+        self.threads.write().clear();
+        self.thread_list.write().clear();
+        self.thread_list_next_page.set(None);
+        self.thread_message_ids.write().clear();
+        self.thread_message_next_pages.write().clear();
+        self.sync_cursor.set(None);
+        self.selected_messages.write().clear();
+        self.highlighted_message.set(None);
+        self.search_query.write().clear();
+        self.search_results.set(SearchResults::Idle);
+        self.users.write().clear();
+        self.thread_participant_ids.write().clear();
+        self.participant_errors.write().clear();
+    }
+}
+
+/// A message sent while offline, or whose sending failed, kept until the server has it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    pub message: ChatMessage,
+    pub is_new_thread: bool,
+    /// Why the server refused it. Such a message waits for the user to retry or discard it.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Takes the message out of the outbox. A later message to the same thread
+/// creates it in its place, if this one was to.
+pub fn discard_from_outbox(outbox: &mut Vec<OutboxEntry>, message_id: Uuid) -> Option<OutboxEntry> {
+    let index = outbox.iter().position(|entry| entry.message.id == message_id)?;
+    let discarded = outbox.remove(index);
+    if discarded.is_new_thread
+        && let Some(next) = outbox.iter_mut().find(|entry| entry.message.thread_id == discarded.message.thread_id)
+    {
+        next.is_new_thread = true;
+    }
+    Some(discarded)
+}
+
+/// What the sidebar search found for the current query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchResults {
+    Idle,
+    Searching,
+    Found(Vec<SearchHit>),
+    Failed(String),
+}
+
+/// Where a message stands among the answers to the same message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BranchPosition {
+    pub index: usize,
+    pub count: usize,
+    pub previous: Option<Uuid>,
+    pub next: Option<Uuid>,
+}
+
+/// The messages of a thread as a tree, with the answers to each message oldest first.
+pub struct MessageTree {
+    parents: HashMap<Uuid, Option<Uuid>>,
+    children: HashMap<Option<Uuid>, Vec<Uuid>>,
+    newest: Option<Uuid>,
+}
+
+impl MessageTree {
+    /// Messages answering one that isn't loaded count as answers to nothing.
+    pub fn new(messages: &HashMap<Uuid, SyncState<ChatMessage>>, message_ids: &[Uuid]) -> Self {
+        let loaded = message_ids
+            .iter()
+            .filter_map(|id| messages.get(id).and_then(SyncState::read))
+            .collect::<Vec<_>>();
+        let mut tree = Self {
+            parents: HashMap::with_capacity(loaded.len()),
+            children: HashMap::new(),
+            newest: loaded.last().map(|message| message.id),
+        };
+        for message in &loaded {
+            tree.parents.insert(message.id, None);
+        }
+        for message in loaded {
+            let parent_id = message.parent_id.filter(|parent_id| tree.parents.contains_key(parent_id));
+            tree.parents.insert(message.id, parent_id);
+            tree.children.entry(parent_id).or_default().push(message.id);
+        }
+        tree
+    }
+
+    /// The branch through `selected`, or through the newest message without one:
+    /// the message's ancestors, then its newest answers all the way down.
+    pub fn branch(&self, selected: Option<Uuid>) -> Vec<Uuid> {
+        let Some(selected) = selected.filter(|id| self.parents.contains_key(id)).or(self.newest) else {
+            return Vec::new();
+        };
+        let mut branch = vec![selected];
+        while let Some(Some(parent_id)) = self.parents.get(branch.last().unwrap())
+            && branch.len() <= self.parents.len()
+        {
+            branch.push(*parent_id);
+        }
+        branch.reverse();
+        while let Some(newest_answer) = self
+            .children
+            .get(&branch.last().copied())
+            .and_then(|answers| answers.last())
+            && branch.len() <= self.parents.len()
+        {
+            branch.push(*newest_answer);
+        }
+        branch
+    }
+
+    /// Where the message stands among its siblings, if it has any.
+    pub fn position(&self, message_id: Uuid) -> Option<BranchPosition> {
+        let siblings = self.children.get(self.parents.get(&message_id)?)?;
+        if siblings.len() < 2 {
+            return None;
+        }
+        let index = siblings.iter().position(|id| *id == message_id)?;
+        Some(BranchPosition {
+            index,
+            count: siblings.len(),
+            previous: index.checked_sub(1).map(|index| siblings[index]),
+            next: siblings.get(index + 1).copied(),
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct SyncError {
     message: String,
@@ -126,3 +326,203 @@ pub fn consume_sync_update_batch<T: Identifiable>(
         }
     }
 }
+
+/// Stores the inlined children and replaces each owner's child ID list.
+pub fn consume_one_to_many_update_batch<T: Identifiable>(
+    map: &mut HashMap<Uuid, SyncState<T>>,
+    child_ids: &mut HashMap<Uuid, Vec<Uuid>>,
+    updates: Vec<OneToManyUpdate<T>>,
+) {
+    for update in updates {
+        let mut owner_child_ids = Vec::with_capacity(update.children.len());
+        for child in update.children {
+            let id = match child {
+                OneToManyChild::Id(id) => id,
+                OneToManyChild::Value(child) => {
+                    let id = child.get_id();
+                    map.insert(id, SyncState::Synced(child));
+                    id
+                }
+            };
+            owner_child_ids.push(id);
+        }
+        child_ids.insert(update.owner_id, owner_child_ids);
+    }
+}
+
+/// Keeps a thread's message IDs oldest first. IDs of messages that aren't loaded
+/// go first, as only older pages are missing.
+fn sort_message_ids(messages: &HashMap<Uuid, SyncState<ChatMessage>>, message_ids: &mut [Uuid]) {
+    message_ids.sort_by_key(|id| {
+        messages
+            .get(id)
+            .and_then(SyncState::read)
+            .map(|message| (message.created_at, message.id))
+    });
+}
+
+/// Stores a page of messages and adds their IDs to each thread's list.
+pub fn consume_thread_messages_page(
+    messages: &mut HashMap<Uuid, SyncState<ChatMessage>>,
+    thread_message_ids: &mut HashMap<Uuid, Vec<Uuid>>,
+    updates: Vec<OneToManyUpdate<ChatMessage>>,
+) {
+    for update in updates {
+        let message_ids = thread_message_ids.entry(update.owner_id).or_default();
+        for child in update.children {
+            let id = match child {
+                OneToManyChild::Id(id) => id,
+                OneToManyChild::Value(message) => {
+                    let id = message.id;
+                    messages.insert(id, SyncState::Synced(message));
+                    id
+                }
+            };
+            if !message_ids.contains(&id) {
+                message_ids.push(id);
+            }
+        }
+        sort_message_ids(messages, message_ids);
+    }
+}
+
+/// Stores new and edited messages, adding the new ones to their thread's list, and
+/// drops deleted ones from every list.
+pub fn consume_message_updates(
+    messages: &mut HashMap<Uuid, SyncState<ChatMessage>>,
+    thread_message_ids: &mut HashMap<Uuid, Vec<Uuid>>,
+    updates: Vec<SyncUpdate<ChatMessage>>,
+) {
+    let mut changed_thread_ids = Vec::new();
+    for update in updates {
+        match update {
+            SyncUpdate::Updated(message) => {
+                let message_ids = thread_message_ids.entry(message.thread_id).or_default();
+                if !message_ids.contains(&message.id) {
+                    message_ids.push(message.id);
+                    changed_thread_ids.push(message.thread_id);
+                }
+                messages.insert(message.id, SyncState::Synced(message));
+            }
+            SyncUpdate::Deleted(id) => {
+                for message_ids in thread_message_ids.values_mut() {
+                    message_ids.retain(|message_id| *message_id != id);
+                }
+                messages.insert(id, SyncState::Deleted);
+            }
+        }
+    }
+    for thread_id in changed_thread_ids {
+        if let Some(message_ids) = thread_message_ids.get_mut(&thread_id) {
+            sort_message_ids(messages, message_ids);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u128, parent_id: Option<u128>) -> ChatMessage {
+        ChatMessage {
+            id: Uuid::from_u128(id),
+            thread_id: Uuid::nil(),
+            user_id: None,
+            parent_id: parent_id.map(Uuid::from_u128),
+            content: String::new(),
+            created_at: time::OffsetDateTime::UNIX_EPOCH,
+            updated_at: None,
+        }
+    }
+
+    /// 1 ─ 2 ─ 3
+    ///      └─ 4 ─ 5
+    fn tree() -> MessageTree {
+        let messages = [
+            message(1, None),
+            message(2, Some(1)),
+            message(3, Some(2)),
+            message(4, Some(2)),
+            message(5, Some(4)),
+        ];
+        let ids = messages.iter().map(|message| message.id).collect::<Vec<_>>();
+        let messages = messages.into_iter().map(|message| (message.id, SyncState::Synced(message))).collect();
+        MessageTree::new(&messages, &ids)
+    }
+
+    fn ids(ids: &[u128]) -> Vec<Uuid> {
+        ids.iter().copied().map(Uuid::from_u128).collect()
+    }
+
+    #[test]
+    fn follows_the_newest_branch() {
+        assert_eq!(tree().branch(None), ids(&[1, 2, 4, 5]));
+        assert_eq!(tree().branch(Some(Uuid::from_u128(2))), ids(&[1, 2, 4, 5]));
+    }
+
+    #[test]
+    fn follows_the_selected_branch() {
+        assert_eq!(tree().branch(Some(Uuid::from_u128(3))), ids(&[1, 2, 3]));
+        assert_eq!(tree().branch(Some(Uuid::from_u128(42))), ids(&[1, 2, 4, 5]));
+    }
+
+    #[test]
+    fn merges_pages_in_order() {
+        let page = |ids: &[u128]| {
+            vec![OneToManyUpdate {
+                owner_id: Uuid::nil(),
+                children: ids.iter().map(|id| OneToManyChild::Value(message(*id, None))).collect(),
+            }]
+        };
+        let mut messages = HashMap::new();
+        let mut thread_message_ids = HashMap::new();
+        consume_thread_messages_page(&mut messages, &mut thread_message_ids, page(&[3, 4]));
+        consume_thread_messages_page(&mut messages, &mut thread_message_ids, page(&[1, 2]));
+        // Fetching the latest page again, with a new message
+        consume_thread_messages_page(&mut messages, &mut thread_message_ids, page(&[4, 5]));
+        assert_eq!(thread_message_ids[&Uuid::nil()], ids(&[1, 2, 3, 4, 5]));
+    }
+
+    #[test]
+    fn adds_and_drops_updated_messages() {
+        let mut messages = HashMap::new();
+        let mut thread_message_ids = HashMap::new();
+        let updates = [2, 1, 3].map(|id| SyncUpdate::Updated(message(id, None)));
+        consume_message_updates(&mut messages, &mut thread_message_ids, updates.into());
+        consume_message_updates(
+            &mut messages,
+            &mut thread_message_ids,
+            vec![SyncUpdate::Deleted(Uuid::from_u128(2)), SyncUpdate::Updated(message(3, Some(1)))],
+        );
+        assert_eq!(thread_message_ids[&Uuid::nil()], ids(&[1, 3]));
+        assert!(matches!(messages[&Uuid::from_u128(2)], SyncState::Deleted));
+    }
+
+    #[test]
+    fn hands_thread_creation_on_when_discarding() {
+        let entry = |id: u128, thread_id: u128, is_new_thread: bool| OutboxEntry {
+            message: ChatMessage { thread_id: Uuid::from_u128(thread_id), ..message(id, None) },
+            is_new_thread,
+            error: None,
+        };
+        let mut outbox = vec![entry(1, 10, true), entry(2, 20, false), entry(3, 10, false)];
+        assert_eq!(discard_from_outbox(&mut outbox, Uuid::from_u128(1)), Some(entry(1, 10, true)));
+        assert_eq!(outbox, vec![entry(2, 20, false), entry(3, 10, true)]);
+        assert_eq!(discard_from_outbox(&mut outbox, Uuid::from_u128(1)), None);
+    }
+
+    #[test]
+    fn locates_siblings() {
+        let tree = tree();
+        assert_eq!(tree.position(Uuid::from_u128(2)), None);
+        assert_eq!(
+            tree.position(Uuid::from_u128(4)),
+            Some(BranchPosition {
+                index: 1,
+                count: 2,
+                previous: Some(Uuid::from_u128(3)),
+                next: None,
+            }),
+        );
+    }
+}
@@ -1,12 +1,29 @@
-use dioxus::logger::tracing::error;
+use dioxus::logger::tracing::{error, info};
 use dioxus::prelude::*;
-use futures_util::{Future, StreamExt};
+use futures_util::{
+    future::{select, Either},
+    Future, StreamExt,
+};
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::Duration;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
-use super::{consume_sync_update_batch, State, SyncState};
-use crate::actuators::chat::front::api;
-use crate::actuators::chat::dto::{ChatMessage, OneToManyChild, OneToManyUpdate, SyncUpdate, Thread};
+use super::{
+    consume_message_updates, consume_one_to_many_update_batch, consume_sync_update_batch,
+    consume_thread_messages_page, discard_from_outbox, OutboxEntry, SearchResults, State, SyncError, SyncState,
+};
+use crate::actuators::chat::front::{api, storage};
+use crate::actuators::chat::dto::{
+    ChatEvent, ChatMessage, FetchParticipantsResponse, FetchThreadResponse, FetchUserThreadsResponse, OneToManyChild,
+    OneToManyUpdate, PageCursor, SendMessageResponse, SyncUpdate, Thread,
+};
+
+const EVENTS_RECONNECT_DELAY: Duration = Duration::from_secs(3);
+/// Failed sends are retried after this, doubling up to `OUTBOX_MAX_RETRY_DELAY`
+const OUTBOX_RETRY_DELAY: Duration = Duration::from_secs(2);
+const OUTBOX_MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
 
 fn use_action<T, F>(handler: &'static impl Fn(State, T) -> F) -> Coroutine<T>
 where
@@ -22,82 +39,177 @@ where
 
 pub fn use_app_actions() {
     use_action::<FetchUserThreadsAction, _>(&handle_fetch_user_threads);
+    use_action::<FetchMoreThreadsAction, _>(&handle_fetch_more_threads);
     use_action::<FetchThreadAction, _>(&handle_fetch_thread);
+    use_action::<FetchOlderMessagesAction, _>(&handle_fetch_older_messages);
     use_action::<SendMessageAction, _>(&handle_send_message);
+    use_outbox();
+    use_action::<RetryMessageAction, _>(&handle_retry_message);
+    use_action::<DiscardMessageAction, _>(&handle_discard_message);
+    use_action::<EditMessageAction, _>(&handle_edit_message);
+    use_action::<DeleteMessageAction, _>(&handle_delete_message);
+    use_action::<RegenerateReplyAction, _>(&handle_regenerate_reply);
+    use_action::<SubscribeEventsAction, _>(&handle_subscribe_events);
+    use_action::<SearchAction, _>(&handle_search);
+    use_action::<FetchParticipantsAction, _>(&handle_fetch_participants);
+    use_action::<InviteParticipantAction, _>(&handle_invite_participant);
+    use_action::<RemoveParticipantAction, _>(&handle_remove_participant);
+    use_action::<LeaveThreadAction, _>(&handle_leave_thread);
+    use_action::<TransferOwnershipAction, _>(&handle_transfer_ownership);
 }
 
-pub type FetchUserThreadsAction = ();
-async fn handle_fetch_user_threads(mut state: State, _: FetchUserThreadsAction) {
-    match api::fetch_user_threads().await {
-        Ok(response) => {
-            let mut thread_updates = Vec::new();
-            state.thread_list.with_mut(|thread_list| {
-                thread_list.clear();
-                for OneToManyUpdate { children, .. } in response.user_threads {
-                    for child in children {
-                        match child {
-                            OneToManyChild::Value(thread) => {
-                                thread_list.push(thread.id);
-                                thread_updates.push(SyncUpdate::Updated(thread));
-                            }
-                            _ => {}
-                        }
+/// Logs the error, signing the user out if it was their session that failed.
+fn report_error(state: State, context: &str, error: Box<dyn Error>) {
+    error!("{context}: {error}");
+    if error.is::<api::SessionExpired>() {
+        state.set_session(None);
+    }
+}
+
+/// Adds a page of threads to the end of the sidebar list.
+fn apply_user_threads_page(mut state: State, response: FetchUserThreadsResponse) {
+    let mut thread_updates = Vec::new();
+    state.thread_list.with_mut(|thread_list| {
+        for OneToManyUpdate { children, .. } in response.user_threads {
+            for child in children {
+                if let OneToManyChild::Value(thread) = child {
+                    // Threads created since the first page came as events, and are listed already
+                    if !thread_list.contains(&thread.id) {
+                        thread_list.push(thread.id);
                     }
+                    thread_updates.push(SyncUpdate::Updated(thread));
                 }
-            });
-            state.threads.with_mut(|threads_state| {
-                consume_sync_update_batch(threads_state, Some(thread_updates));
-            });
+            }
         }
-        Err(error) => {
-            error!("Error fetching user threads: {}", error);
+    });
+    state.threads.with_mut(|threads_state| {
+        consume_sync_update_batch(threads_state, Some(thread_updates));
+    });
+    state.thread_list_next_page.set(response.next_page);
+}
+
+// Actions are told apart by type, so each one needs its own
+pub struct FetchUserThreadsAction;
+async fn handle_fetch_user_threads(mut state: State, _: FetchUserThreadsAction) {
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    match api::fetch_user_threads(&connection, None).await {
+        Ok(response) => {
+            state.thread_list.write().clear();
+            apply_user_threads_page(state, response);
         }
+        Err(error) => report_error(state, "Error fetching user threads", error),
     }
+    // Whatever didn't get sent last time is tried again
+    show_outbox(state);
+    wake_outbox();
 }
 
+/// Holds the page it continues after, so that repeated requests for it only load it once.
+pub struct FetchMoreThreadsAction(pub PageCursor);
+async fn handle_fetch_more_threads(state: State, FetchMoreThreadsAction(before): FetchMoreThreadsAction) {
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    if *state.thread_list_next_page.peek() != Some(before) {
+        return;
+    }
+    match api::fetch_user_threads(&connection, Some(before)).await {
+        Ok(response) => apply_user_threads_page(state, response),
+        Err(error) => report_error(state, "Error fetching more user threads", error),
+    }
+}
+
+/// Merges a page of messages into what's loaded of the thread.
+fn apply_thread_page(mut state: State, response: FetchThreadResponse) {
+    state.threads.with_mut(|t| {
+        consume_sync_update_batch(t, Some(response.threads));
+    });
+    state.messages.with_mut(|messages| {
+        state.thread_message_ids.with_mut(|thread_message_ids| {
+            consume_thread_messages_page(messages, thread_message_ids, response.thread_messages);
+        });
+    });
+}
+
+/// Fetches the latest messages, keeping the older ones already loaded.
 pub type FetchThreadAction = Uuid;
 async fn handle_fetch_thread(mut state: State, thread_id: FetchThreadAction) {
-    match api::fetch_thread(thread_id).await {
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    match api::fetch_thread(&connection, thread_id, None).await {
         Ok(response) => {
-            state.threads.with_mut(|t| {
-                consume_sync_update_batch(t, Some(response.threads));
-            });
-            // Make consume_one_to_many_update_batch fn!
-            state.messages.with_mut(|messages| {
-                state.thread_message_ids.with_mut(|thread_message_ids| {
-                    for update in response.thread_messages {
-                        let mut cur_thread_message_ids = vec![]; //thread_message_ids.entry(update.owner_id).or_insert(vec![]);
-                        for child in update.children {
-                            let id = match child {
-                                OneToManyChild::Id(id) => id,
-                                OneToManyChild::Value(child) => {
-                                    let id = child.id;
-                                    messages.insert(id, SyncState::Synced(child));
-                                    id
-                                }
-                            };
-                            cur_thread_message_ids.push(id);
-                        }
-                        thread_message_ids.insert(update.owner_id, cur_thread_message_ids);
-                    }
-                });
+            let next_page = response.next_page;
+            apply_thread_page(state, response);
+            state.thread_message_next_pages.with_mut(|next_pages| {
+                next_pages.entry(thread_id).or_insert(next_page);
             });
         }
-        Err(error) => {
-            error!("Error fetching thread {thread_id}: {}", error);
+        Err(error) => report_error(state, &format!("Error fetching thread {thread_id}"), error),
+    }
+}
+
+/// Holds the page it continues after, so that repeated requests for it only load it once.
+pub struct FetchOlderMessagesAction {
+    pub thread_id: Uuid,
+    pub before: PageCursor,
+}
+async fn handle_fetch_older_messages(mut state: State, action: FetchOlderMessagesAction) {
+    let FetchOlderMessagesAction { thread_id, before } = action;
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    if state.thread_message_next_pages.peek().get(&thread_id) != Some(&Some(before)) {
+        return;
+    }
+    match api::fetch_thread(&connection, thread_id, Some(before)).await {
+        Ok(response) => {
+            let next_page = response.next_page;
+            apply_thread_page(state, response);
+            state.thread_message_next_pages.with_mut(|next_pages| next_pages.insert(thread_id, next_page));
         }
+        Err(error) => report_error(state, &format!("Error fetching older messages of thread {thread_id}"), error),
     }
 }
 
 pub struct SendMessageAction {
     pub thread_id: Uuid,
     pub is_new_thread: bool,
+    /// The message to answer, which starts a new branch unless it's the last one
+    pub parent_id: Option<Uuid>,
     pub content: String,
 }
 async fn handle_send_message(mut state: State, action: SendMessageAction) {
-    let SendMessageAction { thread_id, is_new_thread, content } = action;
-    if is_new_thread {
-        let now = OffsetDateTime::now_utc();
+    let SendMessageAction { thread_id, is_new_thread, parent_id, content } = action;
+    let Some(user_id) = state.user_id() else {
+        return;
+    };
+    let message = ChatMessage {
+        id: Uuid::new_v4(),
+        thread_id,
+        user_id: Some(user_id),
+        parent_id,
+        content,
+        created_at: OffsetDateTime::now_utc(),
+        updated_at: None,
+    };
+    // Stay on the branch of the new message
+    state.selected_messages.with_mut(|selected| selected.insert(thread_id, message.id));
+    let entry = OutboxEntry { message, is_new_thread, error: None };
+    show_pending_message(state, user_id, entry.clone());
+    state.outbox.with_mut(|outbox| {
+        outbox.push(entry);
+        storage::save_outbox(outbox);
+    });
+    wake_outbox();
+}
+
+/// Shows an unsent message as being saved, along with the thread it starts.
+fn show_pending_message(mut state: State, user_id: Uuid, OutboxEntry { message, is_new_thread, error }: OutboxEntry) {
+    let thread_id = message.thread_id;
+    if is_new_thread && !state.threads.peek().contains_key(&thread_id) {
         state.threads.with_mut(|t| {
             t.insert(
                 thread_id,
@@ -106,60 +218,493 @@ async fn handle_send_message(mut state: State, action: SendMessageAction) {
                     Thread {
                         id: thread_id,
                         name: None,
-                        owner_id: *state.user_id.read(),
-                        created_at: now,
-                        updated_at: now,
+                        owner_id: user_id,
+                        created_at: message.created_at,
+                        updated_at: message.created_at,
                     },
                 ),
             )
         });
         state.thread_list.with_mut(|thread_list| {
-            thread_list.insert(0, thread_id);
+            if !thread_list.contains(&thread_id) {
+                thread_list.insert(0, thread_id);
+            }
         });
+    }
+    state.thread_message_ids.with_mut(|ids| {
+        let message_ids = ids.entry(thread_id).or_default();
+        if !message_ids.contains(&message.id) {
+            message_ids.push(message.id);
+        }
+    });
+    // A failed send keeps its error
+    state.messages.with_mut(|m| {
+        m.entry(message.id).or_insert(SyncState::Saving(error.map(SyncError::new), message));
+    });
+}
+
+/// Shows the signed-in user's unsent messages, which other chats don't list.
+fn show_outbox(state: State) {
+    let Some(user_id) = state.user_id() else {
+        return;
     };
-    let now = OffsetDateTime::now_utc();
-    let message = ChatMessage {
-        id: Uuid::new_v4(),
-        thread_id,
-        user_id: Some(*state.user_id.read()),
-        content,
-        created_at: now,
-        updated_at: None,
+    let entries = state
+        .outbox
+        .peek()
+        .iter()
+        .filter(|entry| entry.message.user_id == Some(user_id))
+        .cloned()
+        .collect::<Vec<_>>();
+    for entry in entries {
+        show_pending_message(state, user_id, entry);
+    }
+}
+
+/// Has the outbox send right away, rather than after its retry delay.
+fn wake_outbox() {
+    consume_context::<Coroutine<DeliverOutboxAction>>().send(DeliverOutboxAction);
+}
+
+/// Sends the signed-in user's unsent messages in order, and tells whether any should be
+/// tried again later. Messages the server refused wait for the user instead, and after
+/// any failure the later messages to that thread wait too, as they may answer it.
+async fn deliver_outbox(mut state: State) -> bool {
+    let Some(connection) = state.connection() else {
+        return true;
     };
-    state.messages.with_mut(|m| {
-        m.insert(message.id, SyncState::Saving(None, message.clone()));
+    let user_id = connection.session.user_id;
+    let message_ids = state
+        .outbox
+        .peek()
+        .iter()
+        .filter(|entry| entry.message.user_id == Some(user_id))
+        .map(|entry| entry.message.id)
+        .collect::<Vec<_>>();
+    let mut failed_thread_ids = HashSet::new();
+    let mut should_retry = false;
+    for message_id in message_ids {
+        if state.connection().as_ref() != Some(&connection) {
+            break;
+        }
+        // Discarding changes the entries, or takes them out
+        let Some(OutboxEntry { message, is_new_thread, error }) =
+            state.outbox.peek().iter().find(|entry| entry.message.id == message_id).cloned()
+        else {
+            continue;
+        };
+        if failed_thread_ids.contains(&message.thread_id) {
+            continue;
+        }
+        if error.is_some() {
+            failed_thread_ids.insert(message.thread_id);
+            continue;
+        }
+        set_message_sync_state(state, message_id, SyncState::Saving, None, |_| {});
+        match api::send_message(&connection, &message, is_new_thread).await {
+            Ok(response) => {
+                state.outbox.with_mut(|outbox| {
+                    outbox.retain(|entry| entry.message.id != message_id);
+                    storage::save_outbox(outbox);
+                });
+                apply_sent_message(state, response);
+            }
+            Err(error) => {
+                failed_thread_ids.insert(message.thread_id);
+                if api::is_transient(error.as_ref()) {
+                    should_retry = true;
+                } else {
+                    set_outbox_error(state, message_id, Some(error.to_string()));
+                }
+                report_message_error(state, message_id, SyncState::Saving, error);
+            }
+        }
+    }
+    !should_retry
+}
+
+fn set_outbox_error(state: State, message_id: Uuid, error: Option<String>) {
+    let mut outbox = state.outbox;
+    outbox.with_mut(|outbox| {
+        if let Some(entry) = outbox.iter_mut().find(|entry| entry.message.id == message_id) {
+            entry.error = error;
+            storage::save_outbox(outbox);
+        }
     });
-    state
-        .thread_message_ids
-        .with_mut(|ids| ids.entry(thread_id).or_insert(vec![]).push(message.id));
-    match api::send_message(&message, is_new_thread).await {
-        Ok(response) => {
-            state.threads.with_mut(|t| {
-                consume_sync_update_batch(t, Some(response.threads));
+}
+
+fn apply_sent_message(mut state: State, response: SendMessageResponse) {
+    state.threads.with_mut(|t| {
+        consume_sync_update_batch(t, Some(response.threads));
+    });
+    state.messages.with_mut(|messages| {
+        state.thread_message_ids.with_mut(|thread_message_ids| {
+            consume_thread_messages_page(messages, thread_message_ids, response.thread_messages);
+        });
+    });
+}
+
+/// Wakes the outbox, which otherwise retries failed sends after a growing delay.
+pub struct DeliverOutboxAction;
+fn use_outbox() -> Coroutine<DeliverOutboxAction> {
+    let state = use_context::<State>();
+    use_coroutine(move |mut rx: UnboundedReceiver<DeliverOutboxAction>| async move {
+        let mut retry_delay = OUTBOX_RETRY_DELAY;
+        loop {
+            if deliver_outbox(state).await {
+                retry_delay = OUTBOX_RETRY_DELAY;
+                if rx.next().await.is_none() {
+                    return;
+                }
+            } else {
+                let retry = futures_timer::Delay::new(retry_delay);
+                retry_delay = (retry_delay * 2).min(OUTBOX_MAX_RETRY_DELAY);
+                if let Either::Left((None, _)) = select(rx.next(), retry).await {
+                    return;
+                }
+            }
+        }
+    })
+}
+
+/// Sends a message the server refused once more, along with those waiting behind it.
+pub struct RetryMessageAction(pub Uuid);
+async fn handle_retry_message(state: State, RetryMessageAction(message_id): RetryMessageAction) {
+    set_outbox_error(state, message_id, None);
+    wake_outbox();
+}
+
+/// Gives up on sending a message, and on the new thread it started if nothing else
+/// waits to be sent to it.
+pub struct DiscardMessageAction(pub Uuid);
+async fn handle_discard_message(state: State, DiscardMessageAction(message_id): DiscardMessageAction) {
+    let mut outbox = state.outbox;
+    let Some(discarded) = outbox.with_mut(|outbox| {
+        let discarded = discard_from_outbox(outbox, message_id);
+        storage::save_outbox(outbox);
+        discarded
+    }) else {
+        return;
+    };
+    apply_chat_event(state, ChatEvent::Messages(vec![SyncUpdate::Deleted(message_id)]));
+    let thread_id = discarded.message.thread_id;
+    if discarded.is_new_thread && !outbox.peek().iter().any(|entry| entry.message.thread_id == thread_id) {
+        apply_chat_event(state, ChatEvent::Threads(vec![SyncUpdate::Deleted(thread_id)]));
+    }
+}
+
+/// Puts the message into `sync_state`, e.g. `SyncState::Saving`, until the server confirms.
+fn set_message_sync_state(
+    mut state: State,
+    message_id: Uuid,
+    sync_state: fn(Option<SyncError>, ChatMessage) -> SyncState<ChatMessage>,
+    error: Option<SyncError>,
+    edit: impl FnOnce(&mut ChatMessage),
+) {
+    state.messages.with_mut(|messages| {
+        if let Some(mut message) = messages.get(&message_id).and_then(SyncState::read).cloned() {
+            edit(&mut message);
+            messages.insert(message_id, sync_state(error, message));
+        }
+    });
+}
+
+/// Keeps the error next to the message instead of only logging it.
+fn report_message_error(
+    state: State,
+    message_id: Uuid,
+    sync_state: fn(Option<SyncError>, ChatMessage) -> SyncState<ChatMessage>,
+    error: Box<dyn Error>,
+) {
+    let sync_error = SyncError::new(error.to_string());
+    set_message_sync_state(state, message_id, sync_state, Some(sync_error), |_| {});
+    report_error(state, &format!("Error updating message {message_id}"), error);
+}
+
+pub struct EditMessageAction {
+    pub thread_id: Uuid,
+    pub message_id: Uuid,
+    pub content: String,
+}
+async fn handle_edit_message(state: State, action: EditMessageAction) {
+    let EditMessageAction { thread_id, message_id, content } = action;
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    set_message_sync_state(state, message_id, SyncState::Saving, None, |message| {
+        message.content = content.clone();
+    });
+    match api::edit_message(&connection, thread_id, message_id, &content).await {
+        Ok(response) => apply_chat_event(state, ChatEvent::Messages(response.messages)),
+        Err(error) => report_message_error(state, message_id, SyncState::Saving, error),
+    }
+}
+
+pub struct DeleteMessageAction {
+    pub thread_id: Uuid,
+    pub message_id: Uuid,
+}
+async fn handle_delete_message(state: State, action: DeleteMessageAction) {
+    let DeleteMessageAction { thread_id, message_id } = action;
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    set_message_sync_state(state, message_id, SyncState::Deleting, None, |_| {});
+    match api::delete_message(&connection, thread_id, message_id).await {
+        Ok(response) => apply_chat_event(state, ChatEvent::Messages(response.messages)),
+        Err(error) => report_message_error(state, message_id, SyncState::Deleting, error),
+    }
+}
+
+/// Asks for a new reply to `message_id`, the latest message from a human.
+pub struct RegenerateReplyAction {
+    pub thread_id: Uuid,
+    pub message_id: Uuid,
+}
+async fn handle_regenerate_reply(state: State, action: RegenerateReplyAction) {
+    let RegenerateReplyAction { thread_id, message_id } = action;
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    // The new reply streams in through the events
+    match api::regenerate_reply(&connection, thread_id, message_id).await {
+        Ok(response) => apply_chat_event(state, ChatEvent::Messages(response.messages)),
+        Err(error) => report_message_error(state, message_id, SyncState::Reloading, error),
+    }
+}
+
+pub struct SearchAction(pub String);
+async fn handle_search(mut state: State, SearchAction(query): SearchAction) {
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    let query = query.trim().to_string();
+    if query.is_empty() {
+        state.search_results.set(SearchResults::Idle);
+        return;
+    }
+    state.search_results.set(SearchResults::Searching);
+    let results = match api::search(&connection, &query).await {
+        Ok(response) => SearchResults::Found(response.hits),
+        Err(error) => {
+            let text = error.to_string();
+            report_error(state, &format!("Error searching for {query:?}"), error);
+            SearchResults::Failed(text)
+        }
+    };
+    // The query may have changed while waiting for the server
+    if state.search_query.read().trim() == query {
+        state.search_results.set(results);
+    }
+}
+
+pub struct SubscribeEventsAction;
+async fn handle_subscribe_events(state: State, _: SubscribeEventsAction) {
+    loop {
+        // Waits for a sign-in, then reconnects as whoever is signed in
+        let Some(connection) = state.connection() else {
+            futures_timer::Delay::new(EVENTS_RECONNECT_DELAY).await;
+            continue;
+        };
+        match api::subscribe_events(&connection).await {
+            Ok(events) => {
+                info!("Subscribed to chat events");
+                // Whatever changed while disconnected didn't come as events
+                sync_changes(state, &connection).await;
+                let mut events = std::pin::pin!(events);
+                while let Some(event) = events.next().await {
+                    if state.connection().as_ref() != Some(&connection) {
+                        info!("Session changed, resubscribing to chat events");
+                        break;
+                    }
+                    match event {
+                        Ok(event) => apply_chat_event(state, event),
+                        Err(error) => {
+                            error!("Error reading chat events: {}", error);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(error) => report_error(state, "Error subscribing to chat events", error),
+        }
+        futures_timer::Delay::new(EVENTS_RECONNECT_DELAY).await;
+    }
+}
+
+/// Applies the changes since the last sync, or only learns where they start on the first one.
+async fn sync_changes(mut state: State, connection: &api::Connection) {
+    loop {
+        let since = *state.sync_cursor.peek();
+        match api::sync(connection, since).await {
+            Ok(response) => {
+                apply_chat_event(state, ChatEvent::Threads(response.threads));
+                apply_chat_event(state, ChatEvent::Messages(response.messages));
+                state.sync_cursor.set(Some(response.cursor));
+                if !response.has_more {
+                    return;
+                }
+            }
+            Err(error) => return report_error(state, "Error syncing chat changes", error),
+        }
+    }
+}
+
+fn apply_chat_event(mut state: State, event: ChatEvent) {
+    match event {
+        ChatEvent::Threads(updates) => {
+            state.thread_list.with_mut(|thread_list| {
+                for update in &updates {
+                    match update {
+                        SyncUpdate::Updated(thread) if !thread_list.contains(&thread.id) => {
+                            thread_list.insert(0, thread.id);
+                        }
+                        SyncUpdate::Updated(_) => {}
+                        SyncUpdate::Deleted(id) => thread_list.retain(|thread_id| thread_id != id),
+                    }
+                }
+            });
+            state.threads.with_mut(|threads| {
+                consume_sync_update_batch(threads, Some(updates));
             });
-            // Make consume_one_to_many_update_batch fn!
+        }
+        ChatEvent::Messages(updates) => {
             state.messages.with_mut(|messages| {
                 state.thread_message_ids.with_mut(|thread_message_ids| {
-                    for update in response.thread_messages {
-                        let mut cur_thread_message_ids = vec![]; //thread_message_ids.entry(update.owner_id).or_insert(vec![]);
-                        for child in update.children {
-                            let id = match child {
-                                OneToManyChild::Id(id) => id,
-                                OneToManyChild::Value(child) => {
-                                    let id = child.id;
-                                    messages.insert(id, SyncState::Synced(child));
-                                    id
-                                }
-                            };
-                            cur_thread_message_ids.push(id);
-                        }
-                        thread_message_ids.insert(update.owner_id, cur_thread_message_ids);
-                    }
+                    consume_message_updates(messages, thread_message_ids, updates);
                 });
             });
         }
-        Err(error) => {
-            error!("Error sending message: {}", error);
+        ChatEvent::ThreadParticipants(updates) => {
+            state.users.with_mut(|users| {
+                state.thread_participant_ids.with_mut(|thread_participant_ids| {
+                    consume_one_to_many_update_batch(users, thread_participant_ids, updates);
+                });
+            });
+        }
+        ChatEvent::MessageDelta { thread_id, message_id, parent_id, content } => {
+            let mut is_new = false;
+            state.messages.with_mut(|messages| match messages.get_mut(&message_id) {
+                Some(SyncState::Reloading(_, message)) => message.content.push_str(&content),
+                // The complete message has already arrived
+                Some(_) => {}
+                None => {
+                    is_new = true;
+                    messages.insert(
+                        message_id,
+                        SyncState::Reloading(
+                            None,
+                            ChatMessage {
+                                id: message_id,
+                                thread_id,
+                                user_id: Some(Uuid::nil()),
+                                parent_id: Some(parent_id),
+                                content,
+                                created_at: OffsetDateTime::now_utc(),
+                                updated_at: None,
+                            },
+                        ),
+                    );
+                }
+            });
+            if is_new {
+                state
+                    .thread_message_ids
+                    .with_mut(|ids| ids.entry(thread_id).or_insert(vec![]).push(message_id));
+            }
+        }
+    }
+}
+
+fn apply_participants_response(state: State, response: FetchParticipantsResponse) {
+    apply_chat_event(state, ChatEvent::Threads(response.threads));
+    apply_chat_event(state, ChatEvent::ThreadParticipants(response.thread_participants));
+}
+
+/// Keeps the error next to the participant list instead of only logging it.
+fn report_participant_error(mut state: State, thread_id: Uuid, error: Box<dyn Error>) {
+    state.participant_errors.with_mut(|errors| errors.insert(thread_id, error.to_string()));
+    report_error(state, &format!("Error updating participants of thread {thread_id}"), error);
+}
+
+fn clear_participant_error(mut state: State, thread_id: Uuid) {
+    state.participant_errors.with_mut(|errors| errors.remove(&thread_id));
+}
+
+pub struct FetchParticipantsAction(pub Uuid);
+async fn handle_fetch_participants(state: State, FetchParticipantsAction(thread_id): FetchParticipantsAction) {
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    match api::fetch_participants(&connection, thread_id).await {
+        Ok(response) => apply_participants_response(state, response),
+        Err(error) => report_error(state, &format!("Error fetching participants of thread {thread_id}"), error),
+    }
+}
+
+pub struct InviteParticipantAction {
+    pub thread_id: Uuid,
+    pub user_id: Uuid,
+}
+async fn handle_invite_participant(state: State, action: InviteParticipantAction) {
+    let InviteParticipantAction { thread_id, user_id } = action;
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    match api::invite_participant(&connection, thread_id, user_id).await {
+        Ok(response) => {
+            clear_participant_error(state, thread_id);
+            apply_participants_response(state, response);
+        }
+        Err(error) => report_participant_error(state, thread_id, error),
+    }
+}
+
+pub struct RemoveParticipantAction {
+    pub thread_id: Uuid,
+    pub user_id: Uuid,
+}
+async fn handle_remove_participant(state: State, action: RemoveParticipantAction) {
+    let RemoveParticipantAction { thread_id, user_id } = action;
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    match api::remove_participant(&connection, thread_id, user_id).await {
+        Ok(response) => {
+            clear_participant_error(state, thread_id);
+            apply_participants_response(state, response);
+        }
+        Err(error) => report_participant_error(state, thread_id, error),
+    }
+}
+
+pub struct LeaveThreadAction(pub Uuid);
+async fn handle_leave_thread(state: State, LeaveThreadAction(thread_id): LeaveThreadAction) {
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    match api::leave_thread(&connection, thread_id).await {
+        Ok(response) => {
+            clear_participant_error(state, thread_id);
+            apply_participants_response(state, response);
+        }
+        Err(error) => report_participant_error(state, thread_id, error),
+    }
+}
+
+pub struct TransferOwnershipAction {
+    pub thread_id: Uuid,
+    pub new_owner_id: Uuid,
+}
+async fn handle_transfer_ownership(state: State, action: TransferOwnershipAction) {
+    let TransferOwnershipAction { thread_id, new_owner_id } = action;
+    let Some(connection) = state.connection() else {
+        return;
+    };
+    match api::transfer_ownership(&connection, thread_id, new_owner_id).await {
+        Ok(response) => {
+            clear_participant_error(state, thread_id);
+            apply_participants_response(state, response);
         }
+        Err(error) => report_participant_error(state, thread_id, error),
     }
 }
@@ -0,0 +1,43 @@
+//! Remembers the configuration, session and unsent messages across restarts:
+//! in localStorage on the web, in a file in the user's config directory on desktop.
+
+use super::{config::ClientConfig, state::OutboxEntry};
+use crate::auth::dto::Session;
+
+#[cfg(feature = "desktop")]
+mod file;
+#[cfg(feature = "desktop")]
+use file as backend;
+
+#[cfg(not(feature = "desktop"))]
+mod browser;
+#[cfg(not(feature = "desktop"))]
+use browser as backend;
+
+const CONFIG_KEY: &str = "config";
+const SESSION_KEY: &str = "session";
+const OUTBOX_KEY: &str = "outbox";
+
+pub async fn load_config() -> Option<ClientConfig> {
+    backend::load(CONFIG_KEY).await
+}
+
+pub fn save_config(config: &ClientConfig) {
+    backend::save(CONFIG_KEY, Some(config));
+}
+
+pub async fn load_session() -> Option<Session> {
+    backend::load(SESSION_KEY).await
+}
+
+pub fn save_session(session: Option<&Session>) {
+    backend::save(SESSION_KEY, session);
+}
+
+pub async fn load_outbox() -> Vec<OutboxEntry> {
+    backend::load(OUTBOX_KEY).await.unwrap_or_default()
+}
+
+pub fn save_outbox(outbox: &[OutboxEntry]) {
+    backend::save(OUTBOX_KEY, (!outbox.is_empty()).then_some(&outbox));
+}
@@ -0,0 +1,26 @@
+use dioxus::prelude::*;
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Reads a JSON value saved with `save`, if there is one and it still parses.
+pub async fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let script = format!("return localStorage.getItem({});", serde_json::to_string(key).ok()?);
+    let item = document::eval(&script).await.ok()?;
+    serde_json::from_str(item.as_str()?).ok()
+}
+
+/// Stores the value under `key`, or removes the key when there is none.
+pub fn save<T: Serialize>(key: &str, value: Option<&T>) {
+    let Ok(key) = serde_json::to_string(key) else {
+        return;
+    };
+    let Some(value) = value else {
+        document::eval(&format!("localStorage.removeItem({key});"));
+        return;
+    };
+    let Ok(value) = serde_json::to_string(value) else {
+        return;
+    };
+    // The stored item is itself a JSON string, so it gets encoded twice
+    let value = serde_json::to_string(&value).unwrap();
+    document::eval(&format!("localStorage.setItem({key}, {value});"));
+}
@@ -0,0 +1,54 @@
+use dioxus::logger::tracing::error;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{Map, Value};
+use std::{fs, io::Write, path::PathBuf};
+
+/// All values live in one JSON object, keyed like localStorage on the web.
+fn path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("artilect").join("chat-front.json"))
+}
+
+fn read_values() -> Map<String, Value> {
+    path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Reads a value saved with `save`, if there is one and it still parses.
+pub async fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    serde_json::from_value(read_values().remove(key)?).ok()
+}
+
+/// Stores the value under `key`, or removes the key when there is none.
+pub fn save<T: Serialize>(key: &str, value: Option<&T>) {
+    let Some(path) = path() else {
+        error!("No config directory to save {key} in");
+        return;
+    };
+    let mut values = read_values();
+    match value.map(serde_json::to_value) {
+        Some(Ok(value)) => values.insert(key.into(), value),
+        Some(Err(err)) => {
+            error!("Failed to serialize {key}: {err}");
+            return;
+        }
+        None => values.remove(key),
+    };
+    let contents = serde_json::to_string_pretty(&values).unwrap();
+    if let Err(err) = write_private(&path, contents.as_bytes()) {
+        error!("Failed to save {}: {}", path.display(), err);
+    }
+}
+
+/// The file holds a session token, so only its owner may read it.
+fn write_private(path: &PathBuf, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)
+}
@@ -3,5 +3,8 @@ pub mod dto;
 #[cfg(feature = "auth-in")]
 pub mod back;
 
+#[cfg(feature = "auth-out")]
+pub mod client;
+
 #[cfg(feature = "auth-front")]
 pub mod front;
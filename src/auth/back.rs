@@ -1,23 +1,72 @@
-#![feature(let_chains)]
+use actix::Actor;
+use sqlx::PgPool;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use time::Duration;
 
-use axum::{
-    http::{HeaderValue, Method},
-    routing::{get, post},
-    Router,
-};
-use tower_http::cors::CorsLayer;
+mod accounts;
+mod actor;
+mod handlers;
+mod oidc;
+mod passwords;
+mod sessions;
+
+use crate::auth::dto::AuthProvider;
+use actor::AuthService;
+use oidc::{OidcConfig, OidcProvider};
+use sessions::SessionKeys;
 
 pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
     // Load configuration
     dotenvy::dotenv().ok();
     let database_url = std::env::var("CHAT_DATABASE_URL").expect("DATABASE_URL must be set");
     let port = std::env::var("PORT").unwrap_or_else(|_| "80".to_string());
+    let secret = std::env::var("AUTH_SECRET").expect("AUTH_SECRET must be set");
+    if secret.len() < 32 {
+        panic!("AUTH_SECRET must be at least 32 bytes long");
+    }
+    let mut keys = SessionKeys::new(secret.as_bytes());
+    if let Ok(hours) = std::env::var("SESSION_TTL_HOURS") {
+        keys = keys.with_ttl(Duration::hours(hours.parse()?));
+    }
+
+    let public_url = std::env::var("AUTH_PUBLIC_URL").expect("AUTH_PUBLIC_URL must be set");
+    let allowed_redirects = std::env::var("AUTH_ALLOWED_REDIRECTS")
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|prefix| !prefix.is_empty())
+                .map(Into::into)
+                .collect()
+        })
+        .unwrap_or_default();
+    let allow_registration = match std::env::var("AUTH_ALLOW_REGISTRATION") {
+        Ok(value) => value.parse::<bool>()?,
+        Err(_) => true,
+    };
+
+    // Discover configured login providers
+    let mut providers = Vec::new();
+    for &provider in AuthProvider::ALL.iter().filter(|provider| provider.uses_oidc()) {
+        if let Some(config) = OidcConfig::from_env(provider, &public_url) {
+            providers.push(OidcProvider::discover(config).await?);
+            tracing::info!("Login with {:?} enabled", provider);
+        }
+    }
+
+    // Create database connection pool
+    let pool = PgPool::connect(&database_url).await?;
+
+    // Create shared state
+    let actor = AuthService::new(pool, keys, providers, public_url.into(), allowed_redirects, allow_registration).start();
+    let router = handlers::build_router(Arc::new(actor));
+
+    // Start server
+    let addr = SocketAddr::from(([0, 0, 0, 0], port.parse::<u16>()?));
+    tracing::info!("Starting server on {}", addr);
 
-    // Configure CORS
-    let cors = CorsLayer::new()
-        .allow_origin("*".parse::<HeaderValue>().unwrap())
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([http::header::AUTHORIZATION, http::header::CONTENT_TYPE]);
-    
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router.into_make_service()).await?;
     Ok(())
 }
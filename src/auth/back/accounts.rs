@@ -0,0 +1,304 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::dto::{AuthProvider, FetchAccountsResponse, LinkedAccount},
+    service::{self, CoercibleResult},
+};
+
+pub struct Account {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+
+pub async fn find_account(
+    pool: &PgPool,
+    provider: AuthProvider,
+    login: &str,
+) -> service::Result<Option<Account>> {
+    sqlx::query_as!(
+        Account,
+        r#"--sql
+        SELECT id, user_id
+        FROM accounts
+        WHERE provider = $1 AND login = $2
+        "#,
+        provider as _,
+        login,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()
+}
+
+/// Looks up the account, signing up a new user for it on first login.
+pub async fn find_or_create_account(
+    pool: &PgPool,
+    provider: AuthProvider,
+    login: &str,
+    name: &str,
+) -> service::Result<Account> {
+    if let Some(account) = find_account(pool, provider, login).await? {
+        return Ok(account);
+    }
+
+    let mut tx = pool.begin().await.into_service_result()?;
+    let user_id = sqlx::query_scalar!(
+        r#"--sql
+        INSERT INTO users (name)
+        VALUES ($1)
+        RETURNING id
+        "#,
+        name,
+    )
+        .fetch_one(&mut *tx)
+        .await
+        .into_service_result()?;
+    let created = sqlx::query_as!(
+        Account,
+        r#"--sql
+        INSERT INTO accounts (provider, login, user_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (provider, login) DO NOTHING
+        RETURNING id, user_id
+        "#,
+        provider as _,
+        login,
+        user_id,
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .into_service_result()?;
+
+    match created {
+        Some(account) => {
+            tx.commit().await.into_service_result()?;
+            tracing::info!("Signed up user {} with {:?}", account.user_id, provider);
+            Ok(account)
+        }
+        None => {
+            // A concurrent login created it first; drop our user
+            tx.rollback().await.into_service_result()?;
+            find_account(pool, provider, login).await?.ok_or(service::Error::Unauthorized)
+        }
+    }
+}
+
+/// Moves an account to a new login, for logins whose format changed. Returns it if it existed.
+pub async fn rename_login(
+    pool: &PgPool,
+    provider: AuthProvider,
+    old_login: &str,
+    new_login: &str,
+) -> service::Result<Option<Account>> {
+    sqlx::query_as!(
+        Account,
+        r#"--sql
+        UPDATE accounts SET login = $3
+        WHERE provider = $1 AND login = $2
+            AND NOT EXISTS (SELECT 1 FROM accounts WHERE provider = $1 AND login = $3)
+        RETURNING id, user_id
+        "#,
+        provider as _,
+        old_login,
+        new_login,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()
+}
+
+/// Adds the provider's account to an existing user. Linking an account that
+/// already belongs to the user is a no-op.
+pub async fn link_account(
+    pool: &PgPool,
+    provider: AuthProvider,
+    login: &str,
+    user_id: Uuid,
+    password_hash: Option<&str>,
+) -> service::Result<Account> {
+    let linked = sqlx::query_as!(
+        Account,
+        r#"--sql
+        INSERT INTO accounts (provider, login, user_id, password_hash)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (provider, login) DO NOTHING
+        RETURNING id, user_id
+        "#,
+        provider as _,
+        login,
+        user_id,
+        password_hash,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()?;
+    if let Some(account) = linked {
+        tracing::info!("Linked {:?} account to user {}", provider, user_id);
+        return Ok(account);
+    }
+    match find_account(pool, provider, login).await? {
+        Some(account) if account.user_id == user_id => Ok(account),
+        _ => Err(service::Error::BadRequest(
+            "This account already belongs to another user".into(),
+        )),
+    }
+}
+
+pub async fn fetch_linked_accounts(pool: &PgPool, user_id: Uuid) -> service::Result<FetchAccountsResponse> {
+    let name = sqlx::query_scalar!(
+        r#"--sql
+        SELECT name
+        FROM users
+        WHERE id = $1
+        "#,
+        user_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()?
+        .ok_or(service::Error::NotFound)?;
+    let accounts = sqlx::query_as!(
+        LinkedAccount,
+        r#"--sql
+        SELECT id, provider AS "provider: AuthProvider", login, created_at
+        FROM accounts
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        user_id,
+    )
+        .fetch_all(pool)
+        .await
+        .into_service_result()?;
+    Ok(FetchAccountsResponse { user_id, name, accounts })
+}
+
+/// Removes one of the user's accounts, along with the sessions signed in
+/// through it. The last account can't be removed.
+pub async fn unlink_account(pool: &PgPool, user_id: Uuid, account_id: Uuid) -> service::Result<()> {
+    let deleted = sqlx::query!(
+        r#"--sql
+        DELETE FROM accounts
+        WHERE id = $1 AND user_id = $2
+            AND EXISTS (SELECT 1 FROM accounts WHERE user_id = $2 AND id <> $1)
+        "#,
+        account_id,
+        user_id,
+    )
+        .execute(pool)
+        .await
+        .into_service_result()?;
+    if deleted.rows_affected() == 0 {
+        let is_owned = sqlx::query_scalar!(
+            r#"--sql
+            SELECT EXISTS (SELECT 1 FROM accounts WHERE id = $1 AND user_id = $2) AS "exists!"
+            "#,
+            account_id,
+            user_id,
+        )
+            .fetch_one(pool)
+            .await
+            .into_service_result()?;
+        return Err(match is_owned {
+            true => service::Error::BadRequest("Can't remove the only way to sign in".into()),
+            false => service::Error::NotFound,
+        });
+    }
+    Ok(())
+}
+
+pub struct LocalAccount {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub password_hash: Option<String>,
+}
+
+pub async fn find_local_account(pool: &PgPool, login: &str) -> service::Result<Option<LocalAccount>> {
+    sqlx::query_as!(
+        LocalAccount,
+        r#"--sql
+        SELECT id, user_id, password_hash
+        FROM accounts
+        WHERE provider = 'Local' AND login = $1
+        "#,
+        login,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()
+}
+
+pub async fn find_local_account_of_user(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> service::Result<Option<LocalAccount>> {
+    sqlx::query_as!(
+        LocalAccount,
+        r#"--sql
+        SELECT id, user_id, password_hash
+        FROM accounts
+        WHERE provider = 'Local' AND user_id = $1
+        "#,
+        user_id,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()
+}
+
+/// Signs up a new user with a password. Fails if the login is taken.
+pub async fn create_local_account(
+    pool: &PgPool,
+    login: &str,
+    name: &str,
+    password_hash: &str,
+) -> service::Result<Account> {
+    let mut tx = pool.begin().await.into_service_result()?;
+    let user_id = sqlx::query_scalar!(
+        r#"--sql
+        INSERT INTO users (name)
+        VALUES ($1)
+        RETURNING id
+        "#,
+        name,
+    )
+        .fetch_one(&mut *tx)
+        .await
+        .into_service_result()?;
+    let account = sqlx::query_as!(
+        Account,
+        r#"--sql
+        INSERT INTO accounts (provider, login, user_id, password_hash)
+        VALUES ('Local', $1, $2, $3)
+        ON CONFLICT (provider, login) DO NOTHING
+        RETURNING id, user_id
+        "#,
+        login,
+        user_id,
+        password_hash,
+    )
+        .fetch_optional(&mut *tx)
+        .await
+        .into_service_result()?
+        .ok_or_else(|| service::Error::BadRequest("Login is already taken".into()))?;
+    tx.commit().await.into_service_result()?;
+    tracing::info!("Signed up user {} with a password", account.user_id);
+    Ok(account)
+}
+
+pub async fn set_password_hash(pool: &PgPool, account_id: Uuid, password_hash: &str) -> service::Result<()> {
+    sqlx::query!(
+        r#"--sql
+        UPDATE accounts
+        SET password_hash = $2
+        WHERE id = $1
+        "#,
+        account_id,
+        password_hash,
+    )
+        .execute(pool)
+        .await
+        .into_service_result()?;
+    Ok(())
+}
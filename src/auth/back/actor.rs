@@ -0,0 +1,435 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use actix::prelude::*;
+use artilect_macro::message_handler;
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+
+use super::{
+    accounts,
+    oidc::{LinkingSession, OidcProvider, PendingLogin, random_string},
+    passwords,
+    sessions::{self, SessionKeys},
+};
+use crate::{
+    auth::dto::{
+        AuthConfig, AuthProvider, ChangePasswordRequest, ChangePasswordResponse,
+        FetchAccountsRequest, FetchAccountsResponse, FetchAuthConfigRequest, FinishLinkResponse,
+        LinkLocalAccountRequest, LinkLocalAccountResponse, LoginRequest, LoginResponse,
+        LogoutRequest, LogoutResponse, PasswordLoginRequest, RegisterRequest, RegisterResponse,
+        Session, StartLinkResponse, UnlinkAccountRequest, UnlinkAccountResponse, VerifyTokenRequest,
+        VerifyTokenResponse, is_allowed_redirect,
+    },
+    service::{self, CoercibleResult},
+};
+
+/// How long the user has to come back from the provider's login page
+pub(super) const PENDING_LOGIN_TTL: Duration = Duration::minutes(10);
+
+pub struct State {
+    pub pool: PgPool,
+    pub keys: SessionKeys,
+    pub providers: HashMap<AuthProvider, OidcProvider>,
+    /// Where users reach this service
+    pub public_url: Box<str>,
+    /// Origins, with an optional path, a finished login may redirect to with its token
+    pub allowed_redirects: Vec<Box<str>>,
+    /// Whether anyone may sign up with a password
+    pub allow_registration: bool,
+    /// Keyed by the OAuth `state` parameter
+    pub pending_logins: Mutex<HashMap<Box<str>, PendingLogin>>,
+    /// Keyed by the ticket or code the link was handed out with
+    pub pending_links: Mutex<HashMap<Box<str>, PendingLink>>,
+}
+
+/// A link waiting on the next step: first the ticket that starts the provider's login
+/// in the user's browser, then, once the provider vouched for `login`, the code that
+/// finishes it.
+pub struct PendingLink {
+    provider: AuthProvider,
+    session: LinkingSession,
+    redirect_to: Box<str>,
+    login: Option<Box<str>>,
+    created_at: OffsetDateTime,
+}
+
+/// Hands out a new key for the link.
+fn insert_pending_link(state: &State, link: PendingLink) -> Box<str> {
+    let key = random_string(32);
+    let mut pending_links = state.pending_links.lock().unwrap();
+    let expired_before = OffsetDateTime::now_utc() - PENDING_LOGIN_TTL;
+    pending_links.retain(|_, link| link.created_at > expired_before);
+    pending_links.insert(key.clone(), link);
+    key
+}
+
+/// Each key can be used once.
+fn take_pending_link(state: &State, key: &str) -> Option<PendingLink> {
+    state
+        .pending_links
+        .lock()
+        .unwrap()
+        .remove(key)
+        .filter(|link| link.created_at > OffsetDateTime::now_utc() - PENDING_LOGIN_TTL)
+}
+
+pub struct AuthService {
+    pub(super) state: Arc<State>,
+}
+
+impl AuthService {
+    pub fn new(
+        pool: PgPool,
+        keys: SessionKeys,
+        providers: Vec<OidcProvider>,
+        public_url: Box<str>,
+        allowed_redirects: Vec<Box<str>>,
+        allow_registration: bool,
+    ) -> Self {
+        Self {
+            state: Arc::new(State {
+                pool,
+                keys,
+                providers: providers.into_iter().map(|p| (p.provider(), p)).collect(),
+                public_url,
+                allowed_redirects,
+                allow_registration,
+                pending_logins: Mutex::default(),
+                pending_links: Mutex::default(),
+            }),
+        }
+    }
+}
+
+impl Actor for AuthService {
+    type Context = actix::Context<Self>;
+}
+
+#[message_handler(AuthService)]
+async fn fetch_auth_config(
+    state: &State,
+    _: FetchAuthConfigRequest,
+) -> service::Result<AuthConfig> {
+    let mut providers = state.providers.keys().copied().collect::<Vec<_>>();
+    providers.push(AuthProvider::Local);
+    providers.sort_by_key(|provider| AuthProvider::ALL.iter().position(|p| p == provider));
+    Ok(AuthConfig {
+        providers,
+        allow_registration: state.allow_registration,
+        allowed_redirects: state.allowed_redirects.clone(),
+    })
+}
+
+#[message_handler(AuthService)]
+async fn login(
+    state: &State,
+    LoginRequest { provider, login }: LoginRequest,
+) -> service::Result<LoginResponse> {
+    if !provider.uses_oidc() {
+        // Local accounts must go through `PasswordLoginRequest`
+        return Err(service::Error::Unauthorized);
+    }
+    let account = accounts::find_account(&state.pool, provider, &login)
+        .await?
+        .ok_or(service::Error::Unauthorized)?;
+    sessions::issue(&state.pool, &state.keys, account.id, account.user_id).await
+}
+
+#[message_handler(AuthService)]
+async fn register(
+    state: &State,
+    RegisterRequest { login, name, password }: RegisterRequest,
+) -> service::Result<RegisterResponse> {
+    if !state.allow_registration {
+        return Err(service::Error::Forbidden);
+    }
+    let login = passwords::normalize_login(&login)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(service::Error::BadRequest("Name cannot be empty".into()));
+    }
+    passwords::validate_password(&password)?;
+
+    let password_hash = passwords::hash(password).await?;
+    let account = accounts::create_local_account(&state.pool, &login, name, &password_hash).await?;
+    sessions::issue(&state.pool, &state.keys, account.id, account.user_id).await
+}
+
+#[message_handler(AuthService)]
+async fn password_login(
+    state: &State,
+    PasswordLoginRequest { login, password }: PasswordLoginRequest,
+) -> service::Result<LoginResponse> {
+    let login = passwords::normalize_login(&login).map_err(|_| service::Error::Unauthorized)?;
+    let account = accounts::find_local_account(&state.pool, &login).await?;
+    let password_hash = account.as_ref().and_then(|account| account.password_hash.as_deref());
+    if !passwords::verify(password, password_hash.map(Into::into)).await? {
+        return Err(service::Error::Unauthorized);
+    }
+    let account = account.ok_or(service::Error::Unauthorized)?;
+    sessions::issue(&state.pool, &state.keys, account.id, account.user_id).await
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "service::Result<ChangePasswordResponse>")]
+pub struct AuthorizedChangePasswordRequest {
+    pub token: Box<str>,
+    pub request: ChangePasswordRequest,
+}
+
+/// Also signs the user out of every other session.
+#[message_handler(AuthService)]
+async fn change_password(
+    state: &State,
+    AuthorizedChangePasswordRequest { token, request }: AuthorizedChangePasswordRequest,
+) -> service::Result<ChangePasswordResponse> {
+    let session = sessions::verify(&state.pool, &state.keys, &token).await?;
+    let account = accounts::find_local_account_of_user(&state.pool, session.user_id)
+        .await?
+        .ok_or(service::Error::NotFound)?;
+    let ChangePasswordRequest { current_password, new_password } = request;
+    if !passwords::verify(current_password, account.password_hash.map(Into::into)).await? {
+        return Err(service::Error::Forbidden);
+    }
+    passwords::validate_password(&new_password)?;
+
+    let password_hash = passwords::hash(new_password).await?;
+    accounts::set_password_hash(&state.pool, account.id, &password_hash).await?;
+    sessions::revoke_other_sessions(&state.pool, session.user_id, session.session_id).await
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "service::Result<StartedOidcLogin>")]
+pub struct StartOidcLoginRequest {
+    pub provider: AuthProvider,
+    pub redirect_to: Option<Box<str>>,
+    /// Handed out by `AuthorizedStartLinkRequest`, to add the account to its user
+    pub link_ticket: Option<Box<str>>,
+}
+
+pub struct StartedOidcLogin {
+    pub authorization_url: reqwest::Url,
+    /// For the browser to keep until the callback, see `PendingLogin::browser_nonce`
+    pub browser_nonce: Box<str>,
+}
+
+#[message_handler(AuthService)]
+async fn start_oidc_login(
+    state: &State,
+    StartOidcLoginRequest { provider, redirect_to, link_ticket }: StartOidcLoginRequest,
+) -> service::Result<StartedOidcLogin> {
+    let oidc = state.providers.get(&provider).ok_or(service::Error::NotFound)?;
+    let (redirect_to, linking) = match link_ticket {
+        Some(ticket) => match take_pending_link(state, &ticket) {
+            Some(PendingLink { provider: link_provider, session, redirect_to, login: None, .. })
+                if link_provider == provider =>
+            {
+                (Some(redirect_to), Some(session))
+            }
+            _ => return Err(service::Error::Unauthorized),
+        },
+        None => {
+            if let Some(redirect_to) = &redirect_to
+                && !is_allowed_redirect(&state.allowed_redirects, redirect_to)
+            {
+                return Err(service::Error::BadRequest("redirect_to is not allowed".into()));
+            }
+            (redirect_to, None)
+        }
+    };
+
+    let (authorization_url, oauth_state, mut pending) = oidc.authorize(redirect_to);
+    pending.linking = linking;
+    let browser_nonce = pending.browser_nonce.clone();
+    let mut pending_logins = state.pending_logins.lock().unwrap();
+    let expired_before = OffsetDateTime::now_utc() - PENDING_LOGIN_TTL;
+    pending_logins.retain(|_, pending| pending.created_at > expired_before);
+    pending_logins.insert(oauth_state, pending);
+    Ok(StartedOidcLogin { authorization_url, browser_nonce })
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "service::Result<StartLinkResponse>")]
+pub struct AuthorizedStartLinkRequest {
+    pub provider: AuthProvider,
+    pub session: LinkingSession,
+    pub redirect_to: Box<str>,
+}
+
+/// Returns where to send the browser to start the login, which has to happen in a
+/// navigation so that the browser is handed its cookie.
+#[message_handler(AuthService)]
+async fn start_link(
+    state: &State,
+    AuthorizedStartLinkRequest { provider, session, redirect_to }: AuthorizedStartLinkRequest,
+) -> service::Result<StartLinkResponse> {
+    if !state.providers.contains_key(&provider) {
+        return Err(service::Error::NotFound);
+    }
+    if !is_allowed_redirect(&state.allowed_redirects, &redirect_to) {
+        return Err(service::Error::BadRequest("redirect_to is not allowed".into()));
+    }
+    let ticket = insert_pending_link(
+        state,
+        PendingLink {
+            provider,
+            session,
+            redirect_to,
+            login: None,
+            created_at: OffsetDateTime::now_utc(),
+        },
+    );
+    let url = reqwest::Url::parse_with_params(
+        &format!("{}/login/{}", state.public_url.trim_end_matches('/'), provider.slug()),
+        &[("link", &*ticket)],
+    )
+        .into_service_result()?;
+    Ok(StartLinkResponse { authorization_url: url.as_str().into() })
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "service::Result<FinishOidcLoginResponse>")]
+pub struct FinishOidcLoginRequest {
+    pub provider: AuthProvider,
+    pub code: Box<str>,
+    pub state: Box<str>,
+    /// From the cookie set when the login started
+    pub browser_nonce: Option<Box<str>>,
+}
+
+pub enum FinishOidcLoginResponse {
+    SignedIn {
+        session: Session,
+        redirect_to: Option<Box<str>>,
+    },
+    /// The provider vouched for the account, which the session that asked for the link
+    /// adds by passing on the code
+    LinkVerified {
+        code: Box<str>,
+        redirect_to: Box<str>,
+    },
+}
+
+#[message_handler(AuthService)]
+async fn finish_oidc_login(
+    state: &State,
+    FinishOidcLoginRequest { provider, code, state: oauth_state, browser_nonce }: FinishOidcLoginRequest,
+) -> service::Result<FinishOidcLoginResponse> {
+    let pending = state
+        .pending_logins
+        .lock()
+        .unwrap()
+        .remove(&oauth_state)
+        .filter(|pending| {
+            pending.is_finished_by(provider, browser_nonce.as_deref())
+                && pending.created_at > OffsetDateTime::now_utc() - PENDING_LOGIN_TTL
+        })
+        .ok_or(service::Error::Unauthorized)?;
+    let oidc = state.providers.get(&provider).ok_or(service::Error::NotFound)?;
+
+    let identity = oidc.finish(&code, &pending).await?;
+    // Accounts used to be found by email, which still finds them once verified
+    if let Some(email) = &identity.email {
+        accounts::rename_login(&state.pool, provider, email, &identity.login).await?;
+    }
+    if let Some(session) = pending.linking {
+        // Links always start with somewhere to come back to
+        let redirect_to = pending.redirect_to.ok_or(service::Error::Unauthorized)?;
+        let code = insert_pending_link(
+            state,
+            PendingLink {
+                provider,
+                session,
+                redirect_to: redirect_to.clone(),
+                login: Some(identity.login),
+                created_at: OffsetDateTime::now_utc(),
+            },
+        );
+        return Ok(FinishOidcLoginResponse::LinkVerified { code, redirect_to });
+    }
+    let account =
+        accounts::find_or_create_account(&state.pool, provider, &identity.login, &identity.name).await?;
+    let session = sessions::issue(&state.pool, &state.keys, account.id, account.user_id).await?;
+    Ok(FinishOidcLoginResponse::SignedIn {
+        session,
+        redirect_to: pending.redirect_to,
+    })
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "service::Result<FinishLinkResponse>")]
+pub struct AuthorizedFinishLinkRequest {
+    pub session: LinkingSession,
+    pub code: Box<str>,
+}
+
+/// Adds the account the provider vouched for, if it is the session that asked for it.
+/// Anyone else who got the code to come back to them only used it up.
+#[message_handler(AuthService)]
+async fn finish_link(
+    state: &State,
+    AuthorizedFinishLinkRequest { session, code }: AuthorizedFinishLinkRequest,
+) -> service::Result<FinishLinkResponse> {
+    let Some(PendingLink { provider, session: linking, login: Some(login), .. }) = take_pending_link(state, &code)
+    else {
+        return Err(service::Error::Unauthorized);
+    };
+    if linking != session {
+        return Err(service::Error::Forbidden);
+    }
+    accounts::link_account(&state.pool, provider, &login, session.user_id, None).await?;
+    accounts::fetch_linked_accounts(&state.pool, session.user_id).await
+}
+
+#[message_handler(AuthService)]
+async fn fetch_accounts(
+    state: &State,
+    FetchAccountsRequest { from_user_id }: FetchAccountsRequest,
+) -> service::Result<FetchAccountsResponse> {
+    accounts::fetch_linked_accounts(&state.pool, from_user_id).await
+}
+
+#[message_handler(AuthService)]
+async fn link_local_account(
+    state: &State,
+    LinkLocalAccountRequest { from_user_id, login, password }: LinkLocalAccountRequest,
+) -> service::Result<LinkLocalAccountResponse> {
+    if accounts::find_local_account_of_user(&state.pool, from_user_id).await?.is_some() {
+        return Err(service::Error::BadRequest("A password is already set".into()));
+    }
+    let login = passwords::normalize_login(&login)?;
+    passwords::validate_password(&password)?;
+
+    let password_hash = passwords::hash(password).await?;
+    accounts::link_account(&state.pool, AuthProvider::Local, &login, from_user_id, Some(&password_hash))
+        .await?;
+    accounts::fetch_linked_accounts(&state.pool, from_user_id).await
+}
+
+#[message_handler(AuthService)]
+async fn unlink_account(
+    state: &State,
+    UnlinkAccountRequest { from_user_id, account_id }: UnlinkAccountRequest,
+) -> service::Result<UnlinkAccountResponse> {
+    accounts::unlink_account(&state.pool, from_user_id, account_id).await?;
+    accounts::fetch_linked_accounts(&state.pool, from_user_id).await
+}
+
+#[message_handler(AuthService)]
+async fn verify_token(
+    state: &State,
+    VerifyTokenRequest { token }: VerifyTokenRequest,
+) -> service::Result<VerifyTokenResponse> {
+    sessions::verify(&state.pool, &state.keys, &token).await
+}
+
+#[message_handler(AuthService)]
+async fn logout(
+    state: &State,
+    LogoutRequest { token }: LogoutRequest,
+) -> service::Result<LogoutResponse> {
+    sessions::revoke(&state.pool, &state.keys, &token).await
+}
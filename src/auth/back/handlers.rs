@@ -0,0 +1,279 @@
+use actix::prelude::*;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderValue, Method, header::SET_COOKIE},
+    response::{IntoResponse, Redirect, Response},
+    routing::{delete, get, post},
+};
+use axum_extra::TypedHeader;
+use headers::{
+    Cookie,
+    authorization::{Authorization, Bearer},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tower_http::cors::CorsLayer;
+
+use uuid::Uuid;
+
+use crate::auth::dto::{
+    AuthConfig, AuthProvider, ChangePasswordRequest, ChangePasswordResponse, FetchAccountsRequest,
+    FetchAccountsResponse, FetchAuthConfigRequest, FinishLinkRequest, FinishLinkResponse, LinkLocalAccountRequest,
+    LinkLocalAccountResponse, LoginResponse, LogoutRequest, LogoutResponse, PasswordLoginRequest,
+    RegisterRequest, RegisterResponse, StartLinkRequest, StartLinkResponse, UnlinkAccountRequest,
+    UnlinkAccountResponse, VerifyTokenRequest, VerifyTokenResponse,
+};
+use crate::service;
+
+use super::{
+    actor::{
+        AuthService, AuthorizedChangePasswordRequest, AuthorizedFinishLinkRequest, AuthorizedStartLinkRequest,
+        FinishOidcLoginRequest, FinishOidcLoginResponse, PENDING_LOGIN_TTL, StartOidcLoginRequest, StartedOidcLogin,
+    },
+    oidc::LinkingSession,
+};
+
+/// Holds `PendingLogin::browser_nonce` from the start of a login until its callback
+const LOGIN_COOKIE: &str = "artilect_login";
+
+pub fn build_router(state: Arc<Addr<AuthService>>) -> Router {
+    // Configure CORS
+    let cors = CorsLayer::new()
+        .allow_origin("*".parse::<HeaderValue>().unwrap())
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers([http::header::AUTHORIZATION, http::header::CONTENT_TYPE]);
+
+    // Build router
+    Router::new()
+        .route("/config", get(fetch_auth_config_handler))
+        .route("/register", post(register_handler))
+        .route("/login", post(password_login_handler))
+        .route("/password", post(change_password_handler))
+        .route("/login/{provider}", get(start_oidc_login_handler))
+        .route("/callback/{provider}", get(finish_oidc_login_handler))
+        .route("/verify", post(verify_token_handler))
+        .route("/logout", post(logout_handler))
+        .route("/accounts", get(fetch_accounts_handler))
+        .route("/accounts/local", post(link_local_account_handler))
+        .route("/accounts/link", post(finish_link_handler))
+        .route("/accounts/link/{provider}", post(start_link_handler))
+        .route("/account/{account_id}", delete(unlink_account_handler))
+        .layer(cors)
+        .with_state(state)
+}
+
+fn map_service_response<T>(
+    actix_response: Result<service::Result<T>, MailboxError>,
+) -> service::Result<Json<T>> {
+    match actix_response {
+        Ok(Ok(response)) => Ok(Json(response)),
+        // Rejected tokens are routine, not worth an error log
+        Ok(Err(service::Error::Unauthorized)) => Err(service::Error::Unauthorized),
+        Ok(Err(error)) => {
+            tracing::error!("Service error: {:?}", error);
+            Err(error)
+        }
+        Err(err) => {
+            tracing::error!("Mailbox error: {:?}", err);
+            Err(service::Error::ServiceUnavailable)
+        }
+    }
+}
+
+/// Resolves the bearer token to the session it belongs to.
+async fn authenticate(
+    service: &Addr<AuthService>,
+    auth_header: &TypedHeader<Authorization<Bearer>>,
+) -> service::Result<VerifyTokenResponse> {
+    let token = auth_header.token().into();
+    map_service_response(service.send(VerifyTokenRequest { token }).await).map(|Json(session)| session)
+}
+
+pub async fn fetch_auth_config_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+) -> service::Result<Json<AuthConfig>> {
+    map_service_response(service.send(FetchAuthConfigRequest).await)
+}
+
+pub async fn register_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    Json(request): Json<RegisterRequest>,
+) -> service::Result<Json<RegisterResponse>> {
+    map_service_response(service.send(request).await)
+}
+
+pub async fn password_login_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    Json(request): Json<PasswordLoginRequest>,
+) -> service::Result<Json<LoginResponse>> {
+    map_service_response(service.send(request).await)
+}
+
+pub async fn change_password_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    auth_header: TypedHeader<Authorization<Bearer>>,
+    Json(request): Json<ChangePasswordRequest>,
+) -> service::Result<Json<ChangePasswordResponse>> {
+    let token = auth_header.token().into();
+    map_service_response(service.send(AuthorizedChangePasswordRequest { token, request }).await)
+}
+
+fn parse_provider(slug: &str) -> service::Result<AuthProvider> {
+    AuthProvider::from_slug(slug).ok_or(service::Error::NotFound)
+}
+
+/// Lax, so that the browser still sends it along when the provider redirects back.
+fn login_cookie(value: &str, max_age: time::Duration) -> String {
+    format!(
+        "{LOGIN_COOKIE}={value}; Max-Age={}; Path=/; HttpOnly; SameSite=Lax",
+        max_age.whole_seconds()
+    )
+}
+
+#[derive(Deserialize)]
+pub struct StartLoginQuery {
+    redirect_to: Option<Box<str>>,
+    /// From `/accounts/link/{provider}`, to add the account instead of signing in
+    link: Option<Box<str>>,
+}
+
+/// Sends the user to the provider's login page, marking the browser as the one
+/// that has to come back.
+pub async fn start_oidc_login_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    Path(provider): Path<Box<str>>,
+    Query(StartLoginQuery { redirect_to, link }): Query<StartLoginQuery>,
+) -> service::Result<Response> {
+    let provider = parse_provider(&provider)?;
+    let Json(StartedOidcLogin { authorization_url, browser_nonce }) = map_service_response(
+        service
+            .send(StartOidcLoginRequest { provider, redirect_to, link_ticket: link })
+            .await,
+    )?;
+    Ok((
+        [(SET_COOKIE, login_cookie(&browser_nonce, PENDING_LOGIN_TTL))],
+        Redirect::to(authorization_url.as_str()),
+    )
+        .into_response())
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    code: Option<Box<str>>,
+    state: Box<str>,
+    error: Option<Box<str>>,
+}
+
+/// Where the provider sends the user back to. Redirects on to `redirect_to` with the
+/// token in the fragment, or the code to finish a link with, or responds with the
+/// session itself.
+pub async fn finish_oidc_login_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    Path(provider): Path<Box<str>>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Query(query): Query<CallbackQuery>,
+) -> service::Result<Response> {
+    let provider = parse_provider(&provider)?;
+    let code = match (query.code, query.error) {
+        (Some(code), None) => code,
+        (_, error) => {
+            tracing::info!("{:?} login was not completed: {:?}", provider, error);
+            return Err(service::Error::Unauthorized);
+        }
+    };
+    let browser_nonce = cookie.as_ref().and_then(|cookie| cookie.get(LOGIN_COOKIE)).map(Into::into);
+    let Json(response) = map_service_response(
+        service
+            .send(FinishOidcLoginRequest { provider, code, state: query.state, browser_nonce })
+            .await,
+    )?;
+    let clear_cookie = [(SET_COOKIE, login_cookie("", time::Duration::ZERO))];
+    Ok(match response {
+        FinishOidcLoginResponse::SignedIn { session, redirect_to: Some(redirect_to) } => {
+            (clear_cookie, Redirect::to(&format!("{redirect_to}#token={}", session.token))).into_response()
+        }
+        FinishOidcLoginResponse::SignedIn { session, redirect_to: None } => {
+            (clear_cookie, Json(session)).into_response()
+        }
+        FinishOidcLoginResponse::LinkVerified { code, redirect_to } => {
+            (clear_cookie, Redirect::to(&format!("{redirect_to}#link={code}"))).into_response()
+        }
+    })
+}
+
+pub async fn verify_token_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    Json(request): Json<VerifyTokenRequest>,
+) -> service::Result<Json<VerifyTokenResponse>> {
+    map_service_response(service.send(request).await)
+}
+
+pub async fn logout_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    auth_header: TypedHeader<Authorization<Bearer>>,
+) -> service::Result<Json<LogoutResponse>> {
+    let token = auth_header.token().into();
+    map_service_response(service.send(LogoutRequest { token }).await)
+}
+
+pub async fn fetch_accounts_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    auth_header: TypedHeader<Authorization<Bearer>>,
+) -> service::Result<Json<FetchAccountsResponse>> {
+    let from_user_id = authenticate(&service, &auth_header).await?.user_id;
+    map_service_response(service.send(FetchAccountsRequest { from_user_id }).await)
+}
+
+pub async fn link_local_account_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    auth_header: TypedHeader<Authorization<Bearer>>,
+    Json(request): Json<LinkLocalAccountRequest>,
+) -> service::Result<Json<LinkLocalAccountResponse>> {
+    let from_user_id = authenticate(&service, &auth_header).await?.user_id;
+    if from_user_id != request.from_user_id {
+        Err(service::Error::Unauthorized)
+    } else {
+        map_service_response(service.send(request).await)
+    }
+}
+
+/// Returns a `/login/{provider}` URL to go to, since a plain navigation couldn't
+/// carry the session token.
+pub async fn start_link_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    auth_header: TypedHeader<Authorization<Bearer>>,
+    Path(provider): Path<Box<str>>,
+    Json(StartLinkRequest { from_user_id, redirect_to }): Json<StartLinkRequest>,
+) -> service::Result<Json<StartLinkResponse>> {
+    let provider = parse_provider(&provider)?;
+    let VerifyTokenResponse { user_id, session_id, .. } = authenticate(&service, &auth_header).await?;
+    if user_id != from_user_id {
+        return Err(service::Error::Unauthorized);
+    }
+    let session = LinkingSession { user_id, session_id };
+    map_service_response(service.send(AuthorizedStartLinkRequest { provider, session, redirect_to }).await)
+}
+
+/// Takes the code a link came back with, from the session that started it.
+pub async fn finish_link_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    auth_header: TypedHeader<Authorization<Bearer>>,
+    Json(FinishLinkRequest { from_user_id, code }): Json<FinishLinkRequest>,
+) -> service::Result<Json<FinishLinkResponse>> {
+    let VerifyTokenResponse { user_id, session_id, .. } = authenticate(&service, &auth_header).await?;
+    if user_id != from_user_id {
+        return Err(service::Error::Unauthorized);
+    }
+    let session = LinkingSession { user_id, session_id };
+    map_service_response(service.send(AuthorizedFinishLinkRequest { session, code }).await)
+}
+
+pub async fn unlink_account_handler(
+    State(service): State<Arc<Addr<AuthService>>>,
+    auth_header: TypedHeader<Authorization<Bearer>>,
+    Path(account_id): Path<Uuid>,
+) -> service::Result<Json<UnlinkAccountResponse>> {
+    let from_user_id = authenticate(&service, &auth_header).await?.user_id;
+    map_service_response(service.send(UnlinkAccountRequest { from_user_id, account_id }).await)
+}
@@ -0,0 +1,516 @@
+use std::sync::RwLock;
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, jwk::JwkSet};
+use rand::{Rng, distributions::Alphanumeric};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{auth::dto::AuthProvider, service};
+
+const DEFAULT_SCOPES: &str = "openid email profile";
+
+/// Algorithms an ID token may be signed with. Symmetric ones would let anyone
+/// holding the client secret mint tokens, so they are left out.
+const ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+#[derive(Error, Debug)]
+pub enum OidcError {
+    #[error("Request to the issuer failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("Invalid discovery document: {0}")]
+    Discovery(Box<str>),
+
+    #[error("Token exchange failed: {0}")]
+    TokenExchange(Box<str>),
+
+    #[error("Invalid ID token: {0}")]
+    InvalidIdToken(Box<str>),
+
+    #[error("The provider did not vouch for the email address")]
+    UnverifiedEmail,
+}
+
+impl From<jsonwebtoken::errors::Error> for OidcError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        Self::InvalidIdToken(error.to_string().into())
+    }
+}
+
+impl From<OidcError> for service::Error {
+    fn from(error: OidcError) -> Self {
+        tracing::warn!("OIDC login failed: {}", error);
+        match error {
+            OidcError::Http(_) | OidcError::Discovery(_) => service::Error::ServiceUnavailable,
+            _ => service::Error::Unauthorized,
+        }
+    }
+}
+
+fn default_issuer(provider: AuthProvider) -> Option<&'static str> {
+    match provider {
+        AuthProvider::Google => Some("https://accounts.google.com"),
+        AuthProvider::Local => None,
+    }
+}
+
+/// Client registration with one OpenID Connect issuer.
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    pub provider: AuthProvider,
+    pub issuer: Box<str>,
+    pub client_id: Box<str>,
+    pub client_secret: Option<Box<str>>,
+    /// Where the issuer sends the user back to, i.e. our `/callback/{provider}`
+    pub redirect_url: Box<str>,
+    pub scopes: Box<str>,
+}
+
+impl OidcConfig {
+    /// Reads `{PROVIDER}_CLIENT_ID`, `{PROVIDER}_CLIENT_SECRET`, `{PROVIDER}_ISSUER`
+    /// and `{PROVIDER}_SCOPES`. Providers without a client id are not configured.
+    pub fn from_env(provider: AuthProvider, public_url: &str) -> Option<Self> {
+        let prefix = provider.slug().to_uppercase();
+        let var = |name: &str| std::env::var(format!("{prefix}_{name}")).ok().map(Box::from);
+
+        let client_id = var("CLIENT_ID")?;
+        let issuer = var("ISSUER")
+            .or_else(|| default_issuer(provider).map(Box::from))
+            .unwrap_or_else(|| panic!("{prefix}_ISSUER must be set"));
+        Some(Self {
+            provider,
+            issuer,
+            client_id,
+            client_secret: var("CLIENT_SECRET"),
+            redirect_url: format!("{}/callback/{}", public_url.trim_end_matches('/'), provider.slug()).into(),
+            scopes: var("SCOPES").unwrap_or_else(|| DEFAULT_SCOPES.into()),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: Box<str>,
+    authorization_endpoint: Box<str>,
+    token_endpoint: Box<str>,
+    jwks_uri: Box<str>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Box<str>,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: Box<str>,
+    email: Option<Box<str>>,
+    email_verified: Option<bool>,
+    name: Option<Box<str>>,
+    nonce: Option<Box<str>>,
+}
+
+/// A login started by `OidcProvider::authorize`, waiting for the issuer's callback.
+#[derive(Debug)]
+pub struct PendingLogin {
+    pub provider: AuthProvider,
+    pub nonce: Box<str>,
+    pub code_verifier: Box<str>,
+    /// Kept in a cookie by the browser that started the login, which has to be the one
+    /// to finish it, so that no one can have someone else sign in through their link
+    pub browser_nonce: Box<str>,
+    pub redirect_to: Option<Box<str>>,
+    /// Set when adding the account to a signed-in user rather than signing in
+    pub linking: Option<LinkingSession>,
+    pub created_at: OffsetDateTime,
+}
+
+impl PendingLogin {
+    /// Whether the callback came to the browser that started the login, with the provider it started with.
+    pub fn is_finished_by(&self, provider: AuthProvider, browser_nonce: Option<&str>) -> bool {
+        self.provider == provider && browser_nonce == Some(&self.browser_nonce)
+    }
+}
+
+/// The session adding an account to its user, which has to be the one to finish it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkingSession {
+    pub user_id: Uuid,
+    pub session_id: Uuid,
+}
+
+/// Who the issuer says the user is. The login is the issuer's own ID for them, as
+/// email addresses can change hands.
+#[derive(Debug)]
+pub struct Identity {
+    pub login: Box<str>,
+    /// Only set once the issuer has verified it
+    pub email: Option<Box<str>>,
+    pub name: Box<str>,
+}
+
+pub struct OidcProvider {
+    config: OidcConfig,
+    metadata: ProviderMetadata,
+    http: reqwest::Client,
+    jwks: RwLock<JwkSet>,
+}
+
+impl OidcProvider {
+    /// Fetches the issuer's discovery document and signing keys.
+    pub async fn discover(config: OidcConfig) -> Result<Self, OidcError> {
+        let http = reqwest::Client::new();
+        let metadata = http
+            .get(format!("{}/.well-known/openid-configuration", config.issuer.trim_end_matches('/')))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ProviderMetadata>()
+            .await?;
+        if metadata.issuer.trim_end_matches('/') != config.issuer.trim_end_matches('/') {
+            return Err(OidcError::Discovery(format!("issuer is {}", metadata.issuer).into()));
+        }
+        if let Err(error) = reqwest::Url::parse(&metadata.authorization_endpoint) {
+            return Err(OidcError::Discovery(format!("authorization_endpoint: {error}").into()));
+        }
+        let jwks = fetch_jwks(&http, &metadata.jwks_uri).await?;
+        Ok(Self {
+            config,
+            metadata,
+            http,
+            jwks: RwLock::new(jwks),
+        })
+    }
+
+    pub fn provider(&self) -> AuthProvider {
+        self.config.provider
+    }
+
+    /// Builds the URL to send the user to, returning it with the `state` value
+    /// the callback will carry and what to check the result against.
+    pub fn authorize(&self, redirect_to: Option<Box<str>>) -> (reqwest::Url, Box<str>, PendingLogin) {
+        let state = random_string(32);
+        let nonce = random_string(32);
+        let code_verifier = random_string(64);
+        let code_challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()));
+
+        let url = reqwest::Url::parse_with_params(
+            &self.metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.config.redirect_url),
+                ("scope", &self.config.scopes),
+                ("state", &state),
+                ("nonce", &nonce),
+                ("code_challenge", &code_challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+            .expect("authorization_endpoint was validated during discovery");
+        let pending = PendingLogin {
+            provider: self.config.provider,
+            nonce,
+            code_verifier,
+            browser_nonce: random_string(32),
+            redirect_to,
+            linking: None,
+            created_at: OffsetDateTime::now_utc(),
+        };
+        (url, state, pending)
+    }
+
+    /// Redeems the authorization code and checks the ID token it yields.
+    pub async fn finish(&self, code: &str, pending: &PendingLogin) -> Result<Identity, OidcError> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.config.redirect_url),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &pending.code_verifier),
+        ];
+        if let Some(client_secret) = &self.config.client_secret {
+            form.push(("client_secret", client_secret));
+        }
+        let response = self
+            .http
+            .post(&*self.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(OidcError::TokenExchange(format!("{status}: {body}").into()));
+        }
+        let TokenResponse { id_token } = response.json().await?;
+
+        let claims = self.validate_id_token(&id_token).await?;
+        if claims.nonce.as_deref() != Some(&pending.nonce) {
+            return Err(OidcError::InvalidIdToken("nonce mismatch".into()));
+        }
+        if claims.email.is_some() && claims.email_verified != Some(true) {
+            return Err(OidcError::UnverifiedEmail);
+        }
+        let name = claims
+            .name
+            .or_else(|| Some(claims.email.as_deref()?.split('@').next()?.into()))
+            .unwrap_or_else(|| "User".into());
+        Ok(Identity {
+            login: format!("{}#{}", self.metadata.issuer, claims.sub).into(),
+            email: claims.email,
+            name,
+        })
+    }
+
+    async fn validate_id_token(&self, id_token: &str) -> Result<IdTokenClaims, OidcError> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        if !ALLOWED_ALGORITHMS.contains(&header.alg) {
+            return Err(OidcError::InvalidIdToken(format!("{:?} is not allowed", header.alg).into()));
+        }
+        let key = match self.decoding_key(header.kid.as_deref())? {
+            Some(key) => key,
+            None => {
+                // The issuer may have rotated its keys since we last looked
+                let jwks = fetch_jwks(&self.http, &self.metadata.jwks_uri).await?;
+                *self.jwks.write().unwrap() = jwks;
+                self.decoding_key(header.kid.as_deref())?
+                    .ok_or_else(|| OidcError::InvalidIdToken("unknown signing key".into()))?
+            }
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&*self.metadata.issuer]);
+        validation.set_audience(&[&*self.config.client_id]);
+        Ok(jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)?.claims)
+    }
+
+    fn decoding_key(&self, kid: Option<&str>) -> Result<Option<DecodingKey>, OidcError> {
+        let jwks = self.jwks.read().unwrap();
+        let jwk = match kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        };
+        Ok(jwk.map(DecodingKey::from_jwk).transpose()?)
+    }
+}
+
+async fn fetch_jwks(http: &reqwest::Client, jwks_uri: &str) -> Result<JwkSet, OidcError> {
+    Ok(http.get(jwks_uri).send().await?.error_for_status()?.json().await?)
+}
+
+pub fn random_string(len: usize) -> Box<str> {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect::<String>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{Form, Json, Router, extract::State, routing::{get, post}};
+    use jsonwebtoken::{EncodingKey, Header};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const CLIENT_ID: &str = "artilect-test";
+    const TEST_KEY: &str = include_str!("testdata/oidc_test_key.pem");
+    const TEST_KEY_MODULUS: &str = "poTayMjHWK2AkBxCFMtMPx6oATY-6ApyQAkYuAh0fi2jpUJ2o5uqXVtf7Ng7pzoEXNjrcpk8Qv5yOlj_wHdyQNncB4znDLA5JoFEC6IPgclOw4XlOe1JZD0ABh-9TfvPr4Jwv9VJ62uecedAft3N95xBt-b2yzB_LTCOXUXwfPxSZlijkdVwnU4oJGUkGJF6tqSVn-CUxappgGhSZHwRpvcDnnnceXDyzBO7oUte8cZLkF6kffmGkyyaUIuPWDVx6fZWsM2VPsLzuL_yZM_KrOP91jYGhp77mt9lIOLBcKhaVOuffoXAlaK3mm09qmAHJbsged0pAF4T4yUmFfh8Vw";
+
+    #[derive(Default)]
+    struct IssuerState {
+        url: String,
+        /// Claims signed into the next ID token, on top of `iss`, `aud` and `exp`
+        claims: Value,
+        token_requests: Vec<Vec<(String, String)>>,
+    }
+
+    type SharedIssuer = Arc<Mutex<IssuerState>>;
+
+    /// Serves discovery, JWKS and token endpoints the way a real issuer would.
+    async fn mock_issuer() -> SharedIssuer {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = Arc::new(Mutex::new(IssuerState {
+            url: format!("http://{}", listener.local_addr().unwrap()),
+            ..Default::default()
+        }));
+        let router = Router::new()
+            .route("/.well-known/openid-configuration", get(discovery))
+            .route("/jwks", get(jwks))
+            .route("/token", post(token))
+            .with_state(issuer.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        issuer
+    }
+
+    async fn discovery(State(issuer): State<SharedIssuer>) -> Json<Value> {
+        let url = issuer.lock().unwrap().url.clone();
+        Json(json!({
+            "issuer": url,
+            "authorization_endpoint": format!("{url}/authorize"),
+            "token_endpoint": format!("{url}/token"),
+            "jwks_uri": format!("{url}/jwks"),
+        }))
+    }
+
+    async fn jwks() -> Json<Value> {
+        Json(json!({
+            "keys": [{ "kty": "RSA", "kid": "test", "alg": "RS256", "use": "sig", "n": TEST_KEY_MODULUS, "e": "AQAB" }],
+        }))
+    }
+
+    async fn token(
+        State(issuer): State<SharedIssuer>,
+        Form(form): Form<Vec<(String, String)>>,
+    ) -> Json<Value> {
+        let mut issuer = issuer.lock().unwrap();
+        issuer.token_requests.push(form);
+        let mut claims = json!({
+            "iss": issuer.url,
+            "aud": CLIENT_ID,
+            "sub": "1234",
+            "exp": OffsetDateTime::now_utc().unix_timestamp() + 600,
+        });
+        claims.as_object_mut().unwrap().extend(issuer.claims.as_object().unwrap().clone());
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test".into());
+        let key = EncodingKey::from_rsa_pem(TEST_KEY.as_bytes()).unwrap();
+        Json(json!({ "id_token": jsonwebtoken::encode(&header, &claims, &key).unwrap() }))
+    }
+
+    async fn provider(issuer: &SharedIssuer) -> OidcProvider {
+        let url = issuer.lock().unwrap().url.clone();
+        OidcProvider::discover(OidcConfig {
+            provider: AuthProvider::Google,
+            issuer: url.into(),
+            client_id: CLIENT_ID.into(),
+            client_secret: Some("secret".into()),
+            redirect_url: "http://localhost/callback/google".into(),
+            scopes: DEFAULT_SCOPES.into(),
+        })
+            .await
+            .unwrap()
+    }
+
+    fn query_param(url: &reqwest::Url, name: &str) -> String {
+        url.query_pairs().find(|(key, _)| key == name).unwrap().1.into_owned()
+    }
+
+    fn form_field<'a>(form: &'a [(String, String)], name: &str) -> &'a str {
+        &form.iter().find(|(key, _)| key == name).unwrap().1
+    }
+
+    #[tokio::test]
+    async fn completes_authorization_code_flow_with_pkce() {
+        let issuer = mock_issuer().await;
+        let provider = provider(&issuer).await;
+        let (url, _state, pending) = provider.authorize(None);
+        assert_eq!(query_param(&url, "client_id"), CLIENT_ID);
+        assert_eq!(query_param(&url, "code_challenge_method"), "S256");
+        issuer.lock().unwrap().claims = json!({
+            "email": "ada@example.com",
+            "email_verified": true,
+            "name": "Ada",
+            "nonce": query_param(&url, "nonce"),
+        });
+
+        let identity = provider.finish("the-code", &pending).await.unwrap();
+        assert_eq!(&*identity.login, format!("{}#1234", issuer.lock().unwrap().url));
+        assert_eq!(identity.email.as_deref(), Some("ada@example.com"));
+        assert_eq!(&*identity.name, "Ada");
+
+        let issuer = issuer.lock().unwrap();
+        let form = &issuer.token_requests[0];
+        assert_eq!(form_field(form, "code"), "the-code");
+        assert_eq!(form_field(form, "client_secret"), "secret");
+        let verifier = form_field(form, "code_verifier");
+        assert_eq!(
+            URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())),
+            query_param(&url, "code_challenge"),
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_id_token_for_another_login() {
+        let issuer = mock_issuer().await;
+        let provider = provider(&issuer).await;
+        let (_, _, pending) = provider.authorize(None);
+        issuer.lock().unwrap().claims = json!({ "email": "ada@example.com", "nonce": "replayed" });
+
+        let result = provider.finish("the-code", &pending).await;
+        assert!(matches!(result, Err(OidcError::InvalidIdToken(_))), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn rejects_unverified_email() {
+        let issuer = mock_issuer().await;
+        let provider = provider(&issuer).await;
+        let (url, _, pending) = provider.authorize(None);
+        issuer.lock().unwrap().claims = json!({
+            "email": "ada@example.com",
+            "email_verified": false,
+            "nonce": query_param(&url, "nonce"),
+        });
+
+        let result = provider.finish("the-code", &pending).await;
+        assert!(matches!(result, Err(OidcError::UnverifiedEmail)), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn rejects_email_without_verification() {
+        let issuer = mock_issuer().await;
+        let provider = provider(&issuer).await;
+        let (url, _, pending) = provider.authorize(None);
+        issuer.lock().unwrap().claims = json!({
+            "email": "ada@example.com",
+            "nonce": query_param(&url, "nonce"),
+        });
+
+        let result = provider.finish("the-code", &pending).await;
+        assert!(matches!(result, Err(OidcError::UnverifiedEmail)), "{result:?}");
+    }
+
+    #[tokio::test]
+    async fn only_finishes_in_the_browser_that_started() {
+        let issuer = mock_issuer().await;
+        let provider = provider(&issuer).await;
+        let (_, _, pending) = provider.authorize(None);
+        let browser_nonce = pending.browser_nonce.clone();
+
+        assert!(pending.is_finished_by(AuthProvider::Google, Some(&browser_nonce)));
+        assert!(!pending.is_finished_by(AuthProvider::Google, None));
+        assert!(!pending.is_finished_by(AuthProvider::Google, Some("another-browser")));
+        assert!(!pending.is_finished_by(AuthProvider::Local, Some(&browser_nonce)));
+    }
+
+    #[tokio::test]
+    async fn signs_in_without_email() {
+        let issuer = mock_issuer().await;
+        let provider = provider(&issuer).await;
+        let (url, _, pending) = provider.authorize(None);
+        issuer.lock().unwrap().claims = json!({ "nonce": query_param(&url, "nonce") });
+
+        let identity = provider.finish("the-code", &pending).await.unwrap();
+        assert!(identity.login.ends_with("#1234"));
+        assert_eq!(identity.email, None);
+    }
+}
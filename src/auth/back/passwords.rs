@@ -0,0 +1,99 @@
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+};
+use once_cell::sync::Lazy;
+
+use crate::service::{self, CoercibleResult};
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+/// Argon2 hashes any length, but there is no point in letting requests burn CPU on megabytes
+const MAX_PASSWORD_LENGTH: usize = 1024;
+const MAX_LOGIN_LENGTH: usize = 255;
+
+/// Verified against when the login doesn't exist, so that a wrong login takes
+/// as long to reject as a wrong password.
+static DUMMY_HASH: Lazy<Box<str>> = Lazy::new(|| hash_blocking("not a password").unwrap());
+
+/// Local logins are case-insensitive usernames or email addresses.
+pub fn normalize_login(login: &str) -> service::Result<Box<str>> {
+    let login = login.trim().to_lowercase();
+    if login.is_empty() || login.len() > MAX_LOGIN_LENGTH || login.contains(char::is_whitespace) {
+        return Err(service::Error::BadRequest("Invalid login".into()));
+    }
+    Ok(login.into())
+}
+
+pub fn validate_password(password: &str) -> service::Result<()> {
+    match password.chars().count() {
+        length if length < MIN_PASSWORD_LENGTH => Err(service::Error::BadRequest(
+            format!("Password must be at least {MIN_PASSWORD_LENGTH} characters long").into(),
+        )),
+        length if length > MAX_PASSWORD_LENGTH => {
+            Err(service::Error::BadRequest("Password is too long".into()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn hash_blocking(password: &str) -> service::Result<Box<str>> {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string().into())
+        .map_err(|error| anyhow::anyhow!("Failed to hash password: {error}").into())
+}
+
+fn verify_blocking(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .and_then(|hash| Argon2::default().verify_password(password.as_bytes(), &hash))
+        .is_ok()
+}
+
+/// Hashing is deliberately slow, so it runs off the async workers.
+pub async fn hash(password: Box<str>) -> service::Result<Box<str>> {
+    tokio::task::spawn_blocking(move || hash_blocking(&password))
+        .await
+        .into_service_result()?
+}
+
+/// Checks the password against the stored hash, or against a dummy one when
+/// there is no account, taking the same time either way.
+pub async fn verify(password: Box<str>, hash: Option<Box<str>>) -> service::Result<bool> {
+    tokio::task::spawn_blocking(move || match hash {
+        Some(hash) => verify_blocking(&password, &hash),
+        None => {
+            verify_blocking(&password, &DUMMY_HASH);
+            false
+        }
+    })
+        .await
+        .into_service_result()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn verifies_only_the_hashed_password() {
+        let hash = hash("correct horse".into()).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify("correct horse".into(), Some(hash.clone())).await.unwrap());
+        assert!(!verify("battery staple".into(), Some(hash)).await.unwrap());
+        assert!(!verify("correct horse".into(), None).await.unwrap());
+    }
+
+    #[test]
+    fn normalizes_logins() {
+        assert_eq!(&*normalize_login("  Ada@Example.com ").unwrap(), "ada@example.com");
+        assert!(normalize_login("   ").is_err());
+        assert!(normalize_login("ada lovelace").is_err());
+    }
+
+    #[test]
+    fn rejects_short_passwords() {
+        assert!(validate_password("short").is_err());
+        assert!(validate_password("long enough").is_ok());
+    }
+}
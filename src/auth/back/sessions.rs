@@ -0,0 +1,194 @@
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    auth::dto::{Session, VerifyTokenResponse},
+    service::{self, CoercibleResult},
+};
+
+const DEFAULT_SESSION_TTL: Duration = Duration::days(30);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    /// User id
+    sub: Uuid,
+    /// Session id, so that a session can be revoked before it expires
+    sid: Uuid,
+    iat: i64,
+    exp: i64,
+}
+
+/// Signs and checks HS256 session tokens.
+pub struct SessionKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    ttl: Duration,
+}
+
+impl SessionKeys {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            ttl: DEFAULT_SESSION_TTL,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    fn sign(&self, claims: &Claims) -> service::Result<Box<str>> {
+        jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &self.encoding)
+            .map(Into::into)
+            .into_service_result()
+    }
+
+    /// Checks the signature and expiry, not whether the session was revoked.
+    fn decode(&self, token: &str) -> service::Result<Claims> {
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+        jsonwebtoken::decode::<Claims>(token, &self.decoding, &validation)
+            .map(|data| data.claims)
+            .map_err(|_| service::Error::Unauthorized)
+    }
+}
+
+pub async fn issue(
+    pool: &PgPool,
+    keys: &SessionKeys,
+    account_id: Uuid,
+    user_id: Uuid,
+) -> service::Result<Session> {
+    let now = OffsetDateTime::now_utc().replace_nanosecond(0).unwrap();
+    let expires_at = now + keys.ttl;
+    let session_id = sqlx::query_scalar!(
+        r#"--sql
+        INSERT INTO sessions (account_id, user_id, created_at, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+        account_id,
+        user_id,
+        now,
+        expires_at,
+    )
+        .fetch_one(pool)
+        .await
+        .into_service_result()?;
+
+    let token = keys.sign(&Claims {
+        sub: user_id,
+        sid: session_id,
+        iat: now.unix_timestamp(),
+        exp: expires_at.unix_timestamp(),
+    })?;
+    Ok(Session { token, user_id, expires_at })
+}
+
+pub async fn verify(pool: &PgPool, keys: &SessionKeys, token: &str) -> service::Result<VerifyTokenResponse> {
+    let claims = keys.decode(token)?;
+    let session = sqlx::query!(
+        r#"--sql
+        SELECT user_id, expires_at
+        FROM sessions
+        WHERE id = $1 AND revoked_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        "#,
+        claims.sid,
+    )
+        .fetch_optional(pool)
+        .await
+        .into_service_result()?
+        .ok_or(service::Error::Unauthorized)?;
+
+    if session.user_id != claims.sub {
+        return Err(service::Error::Unauthorized);
+    }
+    Ok(VerifyTokenResponse {
+        user_id: session.user_id,
+        session_id: claims.sid,
+        expires_at: session.expires_at,
+    })
+}
+
+pub async fn revoke(pool: &PgPool, keys: &SessionKeys, token: &str) -> service::Result<()> {
+    let claims = keys.decode(token)?;
+    sqlx::query!(
+        r#"--sql
+        UPDATE sessions
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND revoked_at IS NULL
+        "#,
+        claims.sid,
+    )
+        .execute(pool)
+        .await
+        .into_service_result()?;
+    Ok(())
+}
+
+/// Signs out everywhere else, e.g. after a password change.
+pub async fn revoke_other_sessions(pool: &PgPool, user_id: Uuid, keep_session_id: Uuid) -> service::Result<()> {
+    sqlx::query!(
+        r#"--sql
+        UPDATE sessions
+        SET revoked_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1 AND id <> $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        keep_session_id,
+    )
+        .execute(pool)
+        .await
+        .into_service_result()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(exp: OffsetDateTime) -> Claims {
+        Claims {
+            sub: Uuid::new_v4(),
+            sid: Uuid::new_v4(),
+            iat: OffsetDateTime::now_utc().unix_timestamp(),
+            exp: exp.unix_timestamp(),
+        }
+    }
+
+    #[test]
+    fn decodes_signed_claims() {
+        let keys = SessionKeys::new(b"secret");
+        let claims = claims(OffsetDateTime::now_utc() + Duration::hours(1));
+        let decoded = keys.decode(&keys.sign(&claims).unwrap()).unwrap();
+        assert_eq!(decoded.sub, claims.sub);
+        assert_eq!(decoded.sid, claims.sid);
+    }
+
+    #[test]
+    fn rejects_tokens_signed_with_another_secret() {
+        let token = SessionKeys::new(b"other")
+            .sign(&claims(OffsetDateTime::now_utc() + Duration::hours(1)))
+            .unwrap();
+        assert!(matches!(
+            SessionKeys::new(b"secret").decode(&token),
+            Err(service::Error::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn rejects_expired_and_malformed_tokens() {
+        let keys = SessionKeys::new(b"secret");
+        let expired = keys.sign(&claims(OffsetDateTime::now_utc() - Duration::seconds(1))).unwrap();
+        assert!(matches!(keys.decode(&expired), Err(service::Error::Unauthorized)));
+        assert!(matches!(
+            keys.decode("00000000-0000-0000-0000-000000000000"),
+            Err(service::Error::Unauthorized)
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use reqwest::StatusCode;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use super::dto::{VerifyTokenRequest, VerifyTokenResponse};
+use crate::service;
+
+/// How long a verified token is trusted without asking the auth service again,
+/// which bounds how late a logout takes effect.
+const VERIFIED_TOKEN_TTL: Duration = Duration::seconds(30);
+const MAX_CACHED_TOKENS: usize = 4096;
+
+/// User ids of recently verified tokens, with when to ask again
+type VerifiedTokens = HashMap<Box<str>, (Uuid, OffsetDateTime)>;
+
+/// Verifies session tokens against the auth service.
+#[derive(Clone)]
+pub struct AuthClient {
+    base_url: Arc<str>,
+    http: reqwest::Client,
+    verified: Arc<Mutex<VerifiedTokens>>,
+}
+
+impl AuthClient {
+    pub fn new(base_url: impl Into<Arc<str>>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            http: reqwest::Client::new(),
+            verified: Default::default(),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("AUTH_BASE_URL").expect("AUTH_BASE_URL must be set"))
+    }
+
+    /// Returns the id of the user the token was issued to.
+    pub async fn verify(&self, token: &str) -> service::Result<Uuid> {
+        let now = OffsetDateTime::now_utc();
+        if let Some(&(user_id, valid_until)) = self.verified.lock().unwrap().get(token)
+            && valid_until > now
+        {
+            return Ok(user_id);
+        }
+
+        let response = self
+            .http
+            .post(format!("{}/verify", self.base_url))
+            .json(&VerifyTokenRequest { token: token.into() })
+            .send()
+            .await
+            .map_err(|error| {
+                tracing::error!("Auth service request failed: {:?}", error);
+                service::Error::ServiceUnavailable
+            })?;
+        let verified = match response.status() {
+            StatusCode::OK => response
+                .json::<VerifyTokenResponse>()
+                .await
+                .map_err(|_| service::Error::InvalidResponse)?,
+            StatusCode::UNAUTHORIZED => return Err(service::Error::Unauthorized),
+            status => {
+                tracing::error!("Auth service responded with {status}");
+                return Err(service::Error::ServiceUnavailable);
+            }
+        };
+
+        let mut cache = self.verified.lock().unwrap();
+        if cache.len() >= MAX_CACHED_TOKENS {
+            cache.retain(|_, (_, valid_until)| *valid_until > now);
+        }
+        if cache.len() < MAX_CACHED_TOKENS {
+            let valid_until = verified.expires_at.min(now + VERIFIED_TOKEN_TTL);
+            cache.insert(token.into(), (verified.user_id, valid_until));
+        }
+        Ok(verified.user_id)
+    }
+}
+
+/// The user behind the request's bearer token, as verified by the auth service.
+#[cfg(feature = "server-http2")]
+pub struct SessionUser(pub Uuid);
+
+#[cfg(feature = "server-http2")]
+impl<S> axum::extract::FromRequestParts<S> for SessionUser
+where
+    AuthClient: axum::extract::FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = service::Error;
+
+    async fn from_request_parts(
+        parts: &mut http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        use axum::extract::FromRef;
+        use axum_extra::TypedHeader;
+        use headers::authorization::{Authorization, Bearer};
+
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| service::Error::Unauthorized)?;
+        AuthClient::from_ref(state).verify(bearer.token()).await.map(Self)
+    }
+}
@@ -1,8 +1,265 @@
-// pub use crate::service;
-// use uuid::Uuid;
-// pub enum Identity {
-//     User(Uuid),
-//     Service(service::Identity),
-// }
-
-// pub struct Authenticated<T> (pub Identity, pub T);
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[cfg(feature = "backend")]
+#[allow(unused_imports)]
+use actix::Message;
+
+#[cfg(feature = "backend")]
+#[allow(unused_imports)]
+use crate::service;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "auth-in", derive(sqlx::Type))]
+#[cfg_attr(feature = "auth-in", sqlx(type_name = "auth_provider"))]
+pub enum AuthProvider {
+    Google,
+    /// Login and password kept by the auth service itself
+    Local,
+}
+
+impl AuthProvider {
+    pub const ALL: &[AuthProvider] = &[AuthProvider::Google, AuthProvider::Local];
+
+    /// Used in URLs and environment variable names
+    pub fn slug(self) -> &'static str {
+        match self {
+            AuthProvider::Google => "google",
+            AuthProvider::Local => "local",
+        }
+    }
+
+    /// Whether logging in goes through an OpenID Connect issuer
+    pub fn uses_oidc(self) -> bool {
+        !matches!(self, AuthProvider::Local)
+    }
+
+    pub fn from_slug(slug: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|provider| provider.slug() == slug)
+    }
+}
+
+/// A signed session token and who it was issued to.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(any(feature = "auth-in", feature = "frontend"), derive(Serialize))]
+#[cfg_attr(feature = "auth-out", derive(Deserialize))]
+pub struct Session {
+    pub token: Box<str>,
+    pub user_id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+/// Issues a session for an account whose identity the caller has already
+/// checked with its provider. Never routed directly.
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<LoginResponse>"))]
+pub struct LoginRequest {
+    pub provider: AuthProvider,
+    pub login: Box<str>,
+}
+
+pub type LoginResponse = Session;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message, Deserialize))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<RegisterResponse>"))]
+#[cfg_attr(feature = "auth-out", derive(Serialize))]
+pub struct RegisterRequest {
+    pub login: Box<str>,
+    pub name: Box<str>,
+    pub password: Box<str>,
+}
+
+pub type RegisterResponse = Session;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message, Deserialize))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<LoginResponse>"))]
+#[cfg_attr(feature = "auth-out", derive(Serialize))]
+pub struct PasswordLoginRequest {
+    pub login: Box<str>,
+    pub password: Box<str>,
+}
+
+/// Sent with the session token of the user whose password changes.
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Deserialize))]
+#[cfg_attr(feature = "auth-out", derive(Serialize))]
+pub struct ChangePasswordRequest {
+    pub current_password: Box<str>,
+    pub new_password: Box<str>,
+}
+
+pub type ChangePasswordResponse = ();
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message, Deserialize))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<VerifyTokenResponse>"))]
+#[cfg_attr(feature = "auth-out", derive(Serialize))]
+pub struct VerifyTokenRequest {
+    pub token: Box<str>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "auth-in", derive(Serialize))]
+#[cfg_attr(feature = "auth-out", derive(Deserialize))]
+pub struct VerifyTokenResponse {
+    pub user_id: Uuid,
+    pub session_id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub expires_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<LogoutResponse>"))]
+pub struct LogoutRequest {
+    pub token: Box<str>,
+}
+
+pub type LogoutResponse = ();
+
+/// What the auth frontend needs to know to offer the right options
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "auth-in", derive(Serialize))]
+#[cfg_attr(feature = "auth-out", derive(Deserialize))]
+pub struct AuthConfig {
+    pub providers: Vec<AuthProvider>,
+    pub allow_registration: bool,
+    /// Origins, with an optional path, a login may send its token back to
+    pub allowed_redirects: Vec<Box<str>>,
+}
+
+#[cfg(any(feature = "auth-in", feature = "auth-out"))]
+impl AuthConfig {
+    pub fn is_allowed_redirect(&self, url: &str) -> bool {
+        is_allowed_redirect(&self.allowed_redirects, url)
+    }
+}
+
+/// Whether `url` has the scheme, host and port of one of the allowed redirects, and lies
+/// under its path. Comparing strings would let `https://chat.example.com.evil.net` pass
+/// for `https://chat.example.com`, or `/apple` for `/app`.
+#[cfg(any(feature = "auth-in", feature = "auth-out"))]
+pub fn is_allowed_redirect(allowed_redirects: &[Box<str>], url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    allowed_redirects
+        .iter()
+        .filter_map(|allowed| reqwest::Url::parse(allowed).ok())
+        .any(|allowed| {
+            let path = allowed.path().trim_end_matches('/');
+            url.scheme() == allowed.scheme()
+                && url.host_str() == allowed.host_str()
+                && url.port_or_known_default() == allowed.port_or_known_default()
+                && (url.path() == path || url.path().starts_with(&format!("{path}/")))
+        })
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<AuthConfig>"))]
+pub struct FetchAuthConfigRequest;
+
+/// One of the ways a user can sign in.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "auth-in", derive(Serialize, sqlx::FromRow))]
+#[cfg_attr(feature = "auth-out", derive(Deserialize))]
+pub struct LinkedAccount {
+    pub id: Uuid,
+    pub provider: AuthProvider,
+    pub login: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<FetchAccountsResponse>"))]
+pub struct FetchAccountsRequest {
+    pub from_user_id: Uuid,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "auth-in", derive(Serialize))]
+#[cfg_attr(feature = "auth-out", derive(Deserialize))]
+pub struct FetchAccountsResponse {
+    pub user_id: Uuid,
+    pub name: String,
+    pub accounts: Vec<LinkedAccount>,
+}
+
+/// Starts an OpenID Connect login that adds the account to the user instead of
+/// signing in as whoever owns it. The login comes back to `redirect_to` with a code
+/// in the fragment, for the same session to finish with `FinishLinkRequest`.
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Deserialize))]
+#[cfg_attr(feature = "auth-out", derive(Serialize))]
+pub struct StartLinkRequest {
+    pub from_user_id: Uuid,
+    pub redirect_to: Box<str>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Serialize))]
+#[cfg_attr(feature = "auth-out", derive(Deserialize))]
+pub struct StartLinkResponse {
+    pub authorization_url: Box<str>,
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Deserialize))]
+#[cfg_attr(feature = "auth-out", derive(Serialize))]
+pub struct FinishLinkRequest {
+    pub from_user_id: Uuid,
+    pub code: Box<str>,
+}
+
+pub type FinishLinkResponse = FetchAccountsResponse;
+
+/// Adds a login and password to a user who signed up with another provider.
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message, Deserialize))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<LinkLocalAccountResponse>"))]
+#[cfg_attr(feature = "auth-out", derive(Serialize))]
+pub struct LinkLocalAccountRequest {
+    pub from_user_id: Uuid,
+    pub login: Box<str>,
+    pub password: Box<str>,
+}
+
+pub type LinkLocalAccountResponse = FetchAccountsResponse;
+
+#[derive(Debug)]
+#[cfg_attr(feature = "auth-in", derive(Message))]
+#[cfg_attr(feature = "auth-in", rtype(result = "service::Result<UnlinkAccountResponse>"))]
+pub struct UnlinkAccountRequest {
+    pub from_user_id: Uuid,
+    pub account_id: Uuid,
+}
+
+pub type UnlinkAccountResponse = FetchAccountsResponse;
+
+#[cfg(all(test, any(feature = "auth-in", feature = "auth-out")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_redirects_to_the_same_origin_and_path() {
+        let allowed = ["https://chat.example.com".into(), "http://localhost:8080/app".into()];
+        assert!(is_allowed_redirect(&allowed, "https://chat.example.com/login#token"));
+        assert!(is_allowed_redirect(&allowed, "https://chat.example.com:443"));
+        assert!(is_allowed_redirect(&allowed, "http://localhost:8080/app/login"));
+        assert!(is_allowed_redirect(&allowed, "http://localhost:8080/app"));
+        assert!(!is_allowed_redirect(&allowed, "https://chat.example.com.evil.net/login"));
+        assert!(!is_allowed_redirect(&allowed, "https://chat.example.com@evil.net/login"));
+        assert!(!is_allowed_redirect(&allowed, "http://chat.example.com/login"));
+        assert!(!is_allowed_redirect(&allowed, "https://chat.example.com:8443/login"));
+        assert!(!is_allowed_redirect(&allowed, "http://localhost:8080/apple"));
+        assert!(!is_allowed_redirect(&allowed, "not a url"));
+    }
+}
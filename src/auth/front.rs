@@ -1,95 +1,93 @@
+use dioxus::logger::tracing::error;
 use dioxus::prelude::*;
+use time::OffsetDateTime;
+
+use crate::auth::dto::{AuthConfig, Session};
+
+mod api;
+mod browser;
+mod components;
+use components::{Account, ChangePassword, Login, Logout, Register, SignedIn, Style};
 
 #[derive(Debug, Clone, Routable, PartialEq)]
 #[rustfmt::skip]
 enum Route {
-    #[layout(Navbar)]
-    #[route("/")]
-    Home {},
-    #[route("/blog/:id")]
-    Blog { id: i32 },
+    #[route("/?:redirect_to")]
+    Login { redirect_to: String },
+    #[route("/register?:redirect_to")]
+    Register { redirect_to: String },
+    #[route("/signed-in#:fragment")]
+    SignedIn { fragment: String },
+    #[route("/account")]
+    Account {},
+    #[route("/password")]
+    ChangePassword {},
+    #[route("/logout?:redirect_to")]
+    Logout { redirect_to: String },
 }
 
-const FAVICON: Asset = asset!("/assets/favicon.ico");
-const MAIN_CSS: Asset = asset!("/assets/main.css");
-const HEADER_SVG: Asset = asset!("/assets/header.svg");
+const FAVICON: Asset = asset!("/src/auth/front/assets/favicon.ico");
+
+const SESSION_KEY: &str = "session";
+/// Where to send the token once an OpenID Connect login comes back
+const REDIRECT_KEY: &str = "redirect_to";
+
+/// The session of whoever signed in through this app, if anyone
+pub type CurrentSession = Signal<Option<Session>>;
+pub type CurrentConfig = Signal<Option<AuthConfig>>;
 
 #[component]
 pub fn App() -> Element {
+    let mut session = use_context_provider::<CurrentSession>(|| Signal::new(None));
+    let mut config = use_context_provider::<CurrentConfig>(|| Signal::new(None));
+    let mut is_loaded = use_signal(|| false);
+    use_hook(|| {
+        spawn(async move {
+            if let Some(stored) = browser::load::<Session>(SESSION_KEY).await
+                && stored.expires_at > OffsetDateTime::now_utc()
+            {
+                session.set(Some(stored));
+            }
+            match api::fetch_config().await {
+                Ok(fetched) => config.set(Some(fetched)),
+                Err(err) => error!("Failed to fetch auth config: {}", err),
+            }
+            is_loaded.set(true);
+        })
+    });
     rsx! {
         document::Link { rel: "icon", href: FAVICON }
-        document::Link { rel: "stylesheet", href: MAIN_CSS }
-        Router::<Route> {}
-    }
-}
-
-#[component]
-fn Hero() -> Element {
-    rsx! {
-        div {
-            id: "hero",
-            img { src: HEADER_SVG, id: "header" }
-            div { id: "links",
-                a { href: "https://dioxuslabs.com/learn/0.6/", "📚 Learn Dioxus" }
-                a { href: "https://dioxuslabs.com/awesome", "🚀 Awesome Dioxus" }
-                a { href: "https://github.com/dioxus-community/", "📡 Community Libraries" }
-                a { href: "https://github.com/DioxusLabs/sdk", "⚙️ Dioxus Development Kit" }
-                a { href: "https://marketplace.visualstudio.com/items?itemName=DioxusLabs.dioxus", "💫 VSCode Extension" }
-                a { href: "https://discord.gg/XgGxMSkvUM", "👋 Community Discord" }
-            }
+        Style {}
+        if is_loaded() {
+            Router::<Route> {}
         }
     }
 }
 
-/// Home page
-#[component]
-fn Home() -> Element {
-    rsx! {
-        Hero {}
+/// Updates the current session, remembering it across visits.
+fn set_session(mut current: CurrentSession, session: Option<Session>) {
+    match &session {
+        Some(session) => browser::save(SESSION_KEY, session),
+        None => browser::remove(SESSION_KEY),
     }
+    current.set(session);
 }
 
-/// Blog page
-#[component]
-fn Blog(id: i32) -> Element {
-    rsx! {
-        div {
-            id: "blog",
-
-            // Content
-            h1 { "This is blog #{id}!" }
-            p { "In blog #{id}, we show how the Dioxus router works and how URL parameters can be passed as props to our route components." }
-
-            // Navigation links
-            Link {
-                to: Route::Blog { id: id - 1 },
-                "Previous"
-            }
-            span { " <---> " }
-            Link {
-                to: Route::Blog { id: id + 1 },
-                "Next"
-            }
-        }
-    }
+/// Whether `redirect_to` is a URL the auth service lets tokens be sent to.
+fn allowed_redirect(config: CurrentConfig, redirect_to: &str) -> Option<String> {
+    let config = config.read();
+    let config = config.as_ref()?;
+    (!redirect_to.is_empty() && config.is_allowed_redirect(redirect_to)).then(|| redirect_to.into())
 }
 
-/// Shared navbar component.
-#[component]
-fn Navbar() -> Element {
-    rsx! {
-        div {
-            id: "navbar",
-            Link {
-                to: Route::Home {},
-                "Home"
-            }
-            Link {
-                to: Route::Blog { id: 1 },
-                "Blog"
-            }
-        }
-
-        Outlet::<Route> {}
-    }
+/// Sends the user back to the app that asked for a login, with the token in
+/// the fragment, or to their account page when nobody asked.
+fn finish_login(navigator: Navigator, config: CurrentConfig, redirect_to: &str, session: &Session) {
+    match allowed_redirect(config, redirect_to) {
+        Some(url) => navigator.push(NavigationTarget::<Route>::External(format!(
+            "{url}#token={}",
+            session.token
+        ))),
+        None => navigator.push(Route::Account {}),
+    };
 }
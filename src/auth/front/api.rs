@@ -0,0 +1,168 @@
+use reqwest::{Client, Response};
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use uuid::Uuid;
+
+use crate::auth::dto::{
+    AuthConfig, AuthProvider, ChangePasswordRequest, ChangePasswordResponse,
+    FetchAccountsResponse, FinishLinkRequest, FinishLinkResponse, LinkLocalAccountRequest, LinkLocalAccountResponse, LoginResponse,
+    LogoutResponse, PasswordLoginRequest, RegisterRequest, RegisterResponse, Session,
+    StartLinkRequest, StartLinkResponse, UnlinkAccountResponse, VerifyTokenRequest,
+    VerifyTokenResponse,
+};
+use crate::service::HttpErrorBody;
+
+static BASE_URL: &str = dotenvy_macro::dotenv!("AUTH_BASE_URL");
+
+/// Parses a successful response, or turns the error into something to show the user.
+async fn parse_response<T: DeserializeOwned>(response: Response) -> Result<T, Box<dyn Error>> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response.json::<T>().await?);
+    }
+    match response.json::<HttpErrorBody>().await {
+        Ok(body) => Err(String::from(body.error).into()),
+        Err(_) if status == reqwest::StatusCode::UNAUTHORIZED => {
+            Err("The session has expired, please sign in again".into())
+        }
+        Err(_) if status == reqwest::StatusCode::FORBIDDEN => Err("Not allowed".into()),
+        Err(_) => Err(format!("Request failed: {status}").into()),
+    }
+}
+
+pub async fn register(request: &RegisterRequest) -> Result<RegisterResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/register"))
+        .json(request)
+        .send()
+        .await?;
+    parse_response(response).await
+}
+
+pub async fn login(request: &PasswordLoginRequest) -> Result<LoginResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/login"))
+        .json(request)
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        return Err("Wrong login or password".into());
+    }
+    parse_response(response).await
+}
+
+pub async fn change_password(
+    token: &str,
+    request: &ChangePasswordRequest,
+) -> Result<ChangePasswordResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/password"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(request)
+        .send()
+        .await?;
+    parse_response(response).await
+}
+
+pub async fn fetch_config() -> Result<AuthConfig, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client.get(format!("{BASE_URL}/config")).send().await?;
+    parse_response(response).await
+}
+
+/// Where to send the browser to sign in with an OpenID Connect provider.
+pub fn oidc_login_url(provider: AuthProvider, redirect_to: &str) -> String {
+    let base = format!("{BASE_URL}/login/{}", provider.slug());
+    reqwest::Url::parse_with_params(&base, &[("redirect_to", redirect_to)])
+        .map(String::from)
+        .unwrap_or(base)
+}
+
+/// Turns a token handed over in a redirect into a full session.
+pub async fn verify(token: &str) -> Result<Session, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/verify"))
+        .json(&VerifyTokenRequest { token: token.into() })
+        .send()
+        .await?;
+    let verified = parse_response::<VerifyTokenResponse>(response).await?;
+    Ok(Session {
+        token: token.into(),
+        user_id: verified.user_id,
+        expires_at: verified.expires_at,
+    })
+}
+
+pub async fn logout(token: &str) -> Result<LogoutResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/logout"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await?;
+    parse_response(response).await
+}
+
+pub async fn fetch_accounts(token: &str) -> Result<FetchAccountsResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{BASE_URL}/accounts"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await?;
+    parse_response(response).await
+}
+
+pub async fn link_local_account(
+    token: &str,
+    request: &LinkLocalAccountRequest,
+) -> Result<LinkLocalAccountResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/accounts/local"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(request)
+        .send()
+        .await?;
+    parse_response(response).await
+}
+
+pub async fn start_link(
+    token: &str,
+    provider: AuthProvider,
+    request: &StartLinkRequest,
+) -> Result<StartLinkResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/accounts/link/{}", provider.slug()))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(request)
+        .send()
+        .await?;
+    parse_response(response).await
+}
+
+pub async fn finish_link(token: &str, request: &FinishLinkRequest) -> Result<FinishLinkResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .post(format!("{BASE_URL}/accounts/link"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(request)
+        .send()
+        .await?;
+    parse_response(response).await
+}
+
+pub async fn unlink_account(token: &str, account_id: Uuid) -> Result<UnlinkAccountResponse, Box<dyn Error>> {
+    let client = Client::new();
+    let response = client
+        .delete(format!("{BASE_URL}/account/{account_id}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await?;
+    parse_response(response).await
+}
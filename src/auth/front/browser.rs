@@ -0,0 +1,30 @@
+use dioxus::prelude::*;
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Reads a JSON value saved with `save`, if there is one and it still parses.
+pub async fn load<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let script = format!("return localStorage.getItem({});", serde_json::to_string(key).ok()?);
+    let item = document::eval(&script).await.ok()?;
+    serde_json::from_str(item.as_str()?).ok()
+}
+
+pub fn save<T: Serialize>(key: &str, value: &T) {
+    let (Ok(key), Ok(value)) = (serde_json::to_string(key), serde_json::to_string(value)) else {
+        return;
+    };
+    // The stored item is itself a JSON string, so it gets encoded twice
+    let value = serde_json::to_string(&value).unwrap();
+    document::eval(&format!("localStorage.setItem({key}, {value});"));
+}
+
+pub fn remove(key: &str) {
+    if let Ok(key) = serde_json::to_string(key) {
+        document::eval(&format!("localStorage.removeItem({key});"));
+    }
+}
+
+/// Scheme, host and port this app is served from.
+pub async fn origin() -> Option<String> {
+    let origin = document::eval("return window.location.origin;").await.ok()?;
+    origin.as_str().map(Into::into)
+}
@@ -0,0 +1,14 @@
+use dioxus::prelude::*;
+
+mod account;
+mod forms;
+
+pub use account::{Account, Logout, SignedIn};
+pub use forms::{ChangePassword, Login, Register};
+
+#[component]
+pub fn Style() -> Element {
+    rsx! {
+        document::Stylesheet { href: forms::CSS }
+    }
+}
@@ -0,0 +1,277 @@
+use dioxus::prelude::*;
+
+use super::forms::provider_label;
+use crate::auth::dto::{FetchAccountsResponse, FinishLinkRequest, LinkLocalAccountRequest, StartLinkRequest};
+use crate::auth::front::{
+    CurrentConfig, CurrentSession, REDIRECT_KEY, Route, allowed_redirect, api, browser,
+    finish_login, set_session,
+};
+
+/// Lists the ways the user can sign in and lets them add or remove some.
+#[component]
+pub fn Account() -> Element {
+    let b = classnames::classname("auth-form");
+    let session = use_context::<CurrentSession>();
+    let config = use_context::<CurrentConfig>();
+    let navigator = use_navigator();
+    let mut accounts = use_signal(|| None::<FetchAccountsResponse>);
+    let mut error = use_signal(|| None::<String>);
+    let mut login = use_signal(String::new);
+    let mut password = use_signal(String::new);
+    let token = session.read().as_ref().map(|session| session.token.clone());
+
+    use_effect({
+        let token = token.clone();
+        move || {
+            let Some(token) = token.clone() else {
+                navigator.replace(Route::Login { redirect_to: String::new() });
+                return;
+            };
+            spawn(async move {
+                match api::fetch_accounts(&token).await {
+                    Ok(fetched) => accounts.set(Some(fetched)),
+                    Err(err) => error.set(Some(err.to_string())),
+                }
+            });
+        }
+    });
+
+    let Some(token) = token else {
+        return rsx! {};
+    };
+
+    let handle_set_password = {
+        let token = token.clone();
+        move |evt: FormEvent| {
+            evt.prevent_default();
+            let Some(from_user_id) = session.read().as_ref().map(|session| session.user_id) else {
+                return;
+            };
+            let request = LinkLocalAccountRequest {
+                from_user_id,
+                login: login.read().as_str().into(),
+                password: password.read().as_str().into(),
+            };
+            let token = token.clone();
+            spawn(async move {
+                match api::link_local_account(&token, &request).await {
+                    Ok(updated) => {
+                        error.set(None);
+                        password.set(String::new());
+                        accounts.set(Some(updated));
+                    }
+                    Err(err) => error.set(Some(err.to_string())),
+                }
+            });
+        }
+    };
+
+    let linked = accounts.read().clone();
+    let Some(linked) = linked else {
+        return rsx! {
+            div { class: b.to_string(),
+                h1 { class: b.el("title").to_string(), "Your account" }
+                if let Some(error) = error() {
+                    p { class: b.el("error").to_string(), "{error}" }
+                    Link { to: Route::Logout { redirect_to: String::new() }, "Sign in again" }
+                }
+            }
+        };
+    };
+    let can_unlink = linked.accounts.len() > 1;
+    let has_password = linked.accounts.iter().any(|account| !account.provider.uses_oidc());
+    let linkable = config
+        .read()
+        .as_ref()
+        .map(|config| {
+            config
+                .providers
+                .iter()
+                .copied()
+                .filter(|provider| {
+                    provider.uses_oidc()
+                        && !linked.accounts.iter().any(|account| account.provider == *provider)
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    rsx! {
+        div { class: b.to_string(),
+            h1 { class: b.el("title").to_string(), "{linked.name}" }
+            ul { class: b.el("accounts").to_string(),
+                for account in linked.accounts.iter().cloned() {
+                    li { key: "{account.id}", class: b.el("account").to_string(),
+                        span { "{provider_label(account.provider)}: {account.login}" }
+                        if can_unlink {
+                            button {
+                                class: b.el("unlink").to_string(),
+                                r#type: "button",
+                                onclick: {
+                                    let token = token.clone();
+                                    move |_| {
+                                        let token = token.clone();
+                                        spawn(async move {
+                                            match api::unlink_account(&token, account.id).await {
+                                                Ok(updated) => accounts.set(Some(updated)),
+                                                Err(err) => error.set(Some(err.to_string())),
+                                            }
+                                        });
+                                    }
+                                },
+                                "Remove"
+                            }
+                        }
+                    }
+                }
+            }
+            for provider in linkable {
+                button {
+                    key: "{provider.slug()}",
+                    class: b.el("provider").to_string(),
+                    r#type: "button",
+                    onclick: {
+                        let token = token.clone();
+                        move |_| {
+                            let token = token.clone();
+                            spawn(async move {
+                                let origin = browser::origin().await.unwrap_or_default();
+                                let request = StartLinkRequest {
+                                    from_user_id: linked.user_id,
+                                    redirect_to: format!("{origin}/signed-in").into(),
+                                };
+                                browser::remove(REDIRECT_KEY);
+                                match api::start_link(&token, provider, &request).await {
+                                    Ok(started) => {
+                                        navigator.push(NavigationTarget::<Route>::External(
+                                            started.authorization_url.into(),
+                                        ));
+                                    }
+                                    Err(err) => error.set(Some(err.to_string())),
+                                }
+                            });
+                        }
+                    },
+                    "Link {provider_label(provider)}"
+                }
+            }
+            if has_password {
+                Link { to: Route::ChangePassword {}, "Change password" }
+            } else {
+                form { class: b.el("section").to_string(), onsubmit: handle_set_password,
+                    h2 { class: b.el("subtitle").to_string(), "Sign in with a password too" }
+                    input {
+                        class: b.el("input").to_string(),
+                        r#type: "text",
+                        placeholder: "Login",
+                        autocomplete: "username",
+                        value: "{login}",
+                        oninput: move |evt| login.set(evt.value()),
+                    }
+                    input {
+                        class: b.el("input").to_string(),
+                        r#type: "password",
+                        placeholder: "Password",
+                        autocomplete: "new-password",
+                        value: "{password}",
+                        oninput: move |evt| password.set(evt.value()),
+                    }
+                    button { class: b.el("submit").to_string(), r#type: "submit", "Set password" }
+                }
+            }
+            if let Some(error) = error() {
+                p { class: b.el("error").to_string(), "{error}" }
+            }
+            Link {
+                class: b.el("logout").to_string(),
+                to: Route::Logout { redirect_to: String::new() },
+                "Sign out"
+            }
+        }
+    }
+}
+
+/// Where OpenID Connect logins come back to, with the token in the fragment, or the
+/// code that finishes linking an account.
+#[component]
+pub fn SignedIn(fragment: String) -> Element {
+    let b = classnames::classname("auth-form");
+    let session = use_context::<CurrentSession>();
+    let config = use_context::<CurrentConfig>();
+    let navigator = use_navigator();
+    let mut error = use_signal(|| None::<String>);
+
+    use_hook(move || {
+        // Only the session that started the link can finish it
+        if let Some(code) = fragment.strip_prefix("link=") {
+            let request = session.read().as_ref().map(|session| {
+                let request = FinishLinkRequest { from_user_id: session.user_id, code: code.into() };
+                (session.token.clone(), request)
+            });
+            return spawn(async move {
+                let Some((token, request)) = request else {
+                    error.set(Some("Sign in again to link the account".into()));
+                    return;
+                };
+                match api::finish_link(&token, &request).await {
+                    Ok(_) => {
+                        navigator.push(Route::Account {});
+                    }
+                    Err(err) => error.set(Some(err.to_string())),
+                }
+            });
+        }
+        let token = fragment.strip_prefix("token=").unwrap_or_default().to_string();
+        spawn(async move {
+            match api::verify(&token).await {
+                Ok(new_session) => {
+                    let redirect_to = browser::load::<String>(REDIRECT_KEY).await.unwrap_or_default();
+                    browser::remove(REDIRECT_KEY);
+                    finish_login(navigator, config, &redirect_to, &new_session);
+                    set_session(session, Some(new_session));
+                }
+                Err(err) => error.set(Some(err.to_string())),
+            }
+        })
+    });
+
+    rsx! {
+        div { class: b.to_string(),
+            h1 { class: b.el("title").to_string(), "Signing in…" }
+            if let Some(error) = error() {
+                p { class: b.el("error").to_string(), "{error}" }
+                Link { to: Route::Login { redirect_to: String::new() }, "Back to sign in" }
+            }
+        }
+    }
+}
+
+/// Ends the session, then goes back to `redirect_to` if it is allowed.
+#[component]
+pub fn Logout(redirect_to: String) -> Element {
+    let b = classnames::classname("auth-form");
+    let session = use_context::<CurrentSession>();
+    let config = use_context::<CurrentConfig>();
+    let navigator = use_navigator();
+
+    use_hook(move || {
+        let token = session.read().as_ref().map(|session| session.token.clone());
+        spawn(async move {
+            if let Some(token) = token {
+                // Forgetting the token is what matters here; revoking it is best-effort
+                api::logout(&token).await.ok();
+            }
+            set_session(session, None);
+            match allowed_redirect(config, &redirect_to) {
+                Some(url) => navigator.replace(NavigationTarget::<Route>::External(url)),
+                None => navigator.replace(Route::Login { redirect_to: String::new() }),
+            };
+        })
+    });
+
+    rsx! {
+        div { class: b.to_string(),
+            h1 { class: b.el("title").to_string(), "Signing out…" }
+        }
+    }
+}
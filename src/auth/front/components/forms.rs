@@ -0,0 +1,288 @@
+use dioxus::prelude::*;
+
+use crate::auth::dto::{AuthProvider, ChangePasswordRequest, PasswordLoginRequest, RegisterRequest};
+use crate::auth::front::{
+    CurrentConfig, CurrentSession, REDIRECT_KEY, Route, allowed_redirect, api, browser,
+    finish_login, set_session,
+};
+
+pub static CSS: Asset = asset!("/src/auth/front/components/forms.css");
+
+#[component]
+fn SessionNotice() -> Element {
+    let b = classnames::classname("auth-form");
+    let session = use_context::<CurrentSession>();
+    rsx! {
+        if let Some(session) = session.read().as_ref() {
+            p { class: b.el("notice").to_string(), "Signed in as user {session.user_id}" }
+        }
+    }
+}
+
+pub fn provider_label(provider: AuthProvider) -> &'static str {
+    match provider {
+        AuthProvider::Google => "Google",
+        AuthProvider::Local => "Password",
+    }
+}
+
+/// Buttons to sign in through each configured OpenID Connect provider.
+#[component]
+fn ProviderButtons(redirect_to: String) -> Element {
+    let b = classnames::classname("auth-form");
+    let config = use_context::<CurrentConfig>();
+    let navigator = use_navigator();
+    let providers = config
+        .read()
+        .as_ref()
+        .map(|config| config.providers.iter().copied().filter(|p| p.uses_oidc()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    rsx! {
+        for provider in providers {
+            button {
+                key: "{provider.slug()}",
+                class: b.el("provider").to_string(),
+                r#type: "button",
+                onclick: {
+                    let redirect_to = redirect_to.clone();
+                    move |_| {
+                        let redirect_to = redirect_to.clone();
+                        spawn(async move {
+                            browser::save(REDIRECT_KEY, &redirect_to);
+                            let origin = browser::origin().await.unwrap_or_default();
+                            let url = api::oidc_login_url(provider, &format!("{origin}/signed-in"));
+                            navigator.push(NavigationTarget::<Route>::External(url));
+                        });
+                    }
+                },
+                "Continue with {provider_label(provider)}"
+            }
+        }
+    }
+}
+
+#[component]
+fn FormError(error: Signal<Option<String>>) -> Element {
+    let b = classnames::classname("auth-form");
+    rsx! {
+        if let Some(error) = error() {
+            p { class: b.el("error").to_string(), "{error}" }
+        }
+    }
+}
+
+#[component]
+pub fn Login(redirect_to: String) -> Element {
+    let b = classnames::classname("auth-form");
+    let session = use_context::<CurrentSession>();
+    let config = use_context::<CurrentConfig>();
+    let navigator = use_navigator();
+    let mut login = use_signal(String::new);
+    let mut password = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+
+    let submit_redirect_to = redirect_to.clone();
+    let handle_submit = move |evt: FormEvent| {
+        evt.prevent_default();
+        let request = PasswordLoginRequest {
+            login: login.read().as_str().into(),
+            password: password.read().as_str().into(),
+        };
+        let redirect_to = submit_redirect_to.clone();
+        spawn(async move {
+            match api::login(&request).await {
+                Ok(new_session) => {
+                    error.set(None);
+                    password.set(String::new());
+                    finish_login(navigator, config, &redirect_to, &new_session);
+                    set_session(session, Some(new_session));
+                }
+                Err(err) => error.set(Some(err.to_string())),
+            }
+        });
+    };
+
+    // Already signed in, so the app asking for a login can have the token right away
+    use_effect({
+        let redirect_to = redirect_to.clone();
+        move || {
+            if let Some(session) = session.read().as_ref()
+                && allowed_redirect(config, &redirect_to).is_some()
+            {
+                finish_login(navigator, config, &redirect_to, session);
+            }
+        }
+    });
+
+    let allow_registration = config.read().as_ref().is_some_and(|config| config.allow_registration);
+
+    rsx! {
+        form { class: b.to_string(), onsubmit: handle_submit,
+            h1 { class: b.el("title").to_string(), "Sign in" }
+            SessionNotice {}
+            ProviderButtons { redirect_to: redirect_to.clone() }
+            input {
+                class: b.el("input").to_string(),
+                r#type: "text",
+                placeholder: "Login",
+                autocomplete: "username",
+                value: "{login}",
+                oninput: move |evt| login.set(evt.value()),
+            }
+            input {
+                class: b.el("input").to_string(),
+                r#type: "password",
+                placeholder: "Password",
+                autocomplete: "current-password",
+                value: "{password}",
+                oninput: move |evt| password.set(evt.value()),
+            }
+            FormError { error }
+            button { class: b.el("submit").to_string(), r#type: "submit", "Sign in" }
+            div { class: b.el("links").to_string(),
+                if allow_registration {
+                    Link { to: Route::Register { redirect_to: redirect_to.clone() }, "Create an account" }
+                }
+                if session.read().is_some() {
+                    Link { to: Route::Account {}, "Your account" }
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn Register(redirect_to: String) -> Element {
+    let b = classnames::classname("auth-form");
+    let session = use_context::<CurrentSession>();
+    let config = use_context::<CurrentConfig>();
+    let navigator = use_navigator();
+    let mut name = use_signal(String::new);
+    let mut login = use_signal(String::new);
+    let mut password = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+
+    let submit_redirect_to = redirect_to.clone();
+    let handle_submit = move |evt: FormEvent| {
+        evt.prevent_default();
+        let request = RegisterRequest {
+            login: login.read().as_str().into(),
+            name: name.read().as_str().into(),
+            password: password.read().as_str().into(),
+        };
+        let redirect_to = submit_redirect_to.clone();
+        spawn(async move {
+            match api::register(&request).await {
+                Ok(new_session) => {
+                    error.set(None);
+                    password.set(String::new());
+                    finish_login(navigator, config, &redirect_to, &new_session);
+                    set_session(session, Some(new_session));
+                }
+                Err(err) => error.set(Some(err.to_string())),
+            }
+        });
+    };
+
+    rsx! {
+        form { class: b.to_string(), onsubmit: handle_submit,
+            h1 { class: b.el("title").to_string(), "Create an account" }
+            SessionNotice {}
+            ProviderButtons { redirect_to: redirect_to.clone() }
+            input {
+                class: b.el("input").to_string(),
+                r#type: "text",
+                placeholder: "Name",
+                autocomplete: "name",
+                value: "{name}",
+                oninput: move |evt| name.set(evt.value()),
+            }
+            input {
+                class: b.el("input").to_string(),
+                r#type: "text",
+                placeholder: "Login",
+                autocomplete: "username",
+                value: "{login}",
+                oninput: move |evt| login.set(evt.value()),
+            }
+            input {
+                class: b.el("input").to_string(),
+                r#type: "password",
+                placeholder: "Password",
+                autocomplete: "new-password",
+                value: "{password}",
+                oninput: move |evt| password.set(evt.value()),
+            }
+            FormError { error }
+            button { class: b.el("submit").to_string(), r#type: "submit", "Create account" }
+            div { class: b.el("links").to_string(),
+                Link { to: Route::Login { redirect_to: redirect_to.clone() }, "Already have an account?" }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn ChangePassword() -> Element {
+    let b = classnames::classname("auth-form");
+    let session = use_context::<CurrentSession>();
+    let mut current_password = use_signal(String::new);
+    let mut new_password = use_signal(String::new);
+    let mut error = use_signal(|| None::<String>);
+    let mut is_changed = use_signal(|| false);
+
+    let handle_submit = move |evt: FormEvent| {
+        evt.prevent_default();
+        let Some(token) = session.read().as_ref().map(|session| session.token.clone()) else {
+            error.set(Some("Sign in first".into()));
+            return;
+        };
+        let request = ChangePasswordRequest {
+            current_password: current_password.read().as_str().into(),
+            new_password: new_password.read().as_str().into(),
+        };
+        spawn(async move {
+            match api::change_password(&token, &request).await {
+                Ok(()) => {
+                    error.set(None);
+                    current_password.set(String::new());
+                    new_password.set(String::new());
+                    is_changed.set(true);
+                }
+                Err(err) => error.set(Some(err.to_string())),
+            }
+        });
+    };
+
+    rsx! {
+        form { class: b.to_string(), onsubmit: handle_submit,
+            h1 { class: b.el("title").to_string(), "Change password" }
+            SessionNotice {}
+            input {
+                class: b.el("input").to_string(),
+                r#type: "password",
+                placeholder: "Current password",
+                autocomplete: "current-password",
+                value: "{current_password}",
+                oninput: move |evt| current_password.set(evt.value()),
+            }
+            input {
+                class: b.el("input").to_string(),
+                r#type: "password",
+                placeholder: "New password",
+                autocomplete: "new-password",
+                value: "{new_password}",
+                oninput: move |evt| new_password.set(evt.value()),
+            }
+            FormError { error }
+            if is_changed() {
+                p { class: b.el("notice").to_string(), "Password changed. Other sessions were signed out." }
+            }
+            button { class: b.el("submit").to_string(), r#type: "submit", "Change password" }
+            div { class: b.el("links").to_string(),
+                Link { to: Route::Account {}, "Back to your account" }
+            }
+        }
+    }
+}
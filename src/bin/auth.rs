@@ -1,4 +1,4 @@
-#[tokio::main]
+#[actix::main]
 async fn main() {
     // Initialize logging
     tracing_subscriber::fmt::init();
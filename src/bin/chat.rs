@@ -26,6 +26,7 @@ async fn main() {
         Err(err) => panic!("Failed to parse PORT: {}", err),
     };
     let client = artilect::infer::Client::new();
+    let auth = artilect::auth::client::AuthClient::from_env();
 
-    artilect::actuators::chat::back::serve(name, database_url.into(), port, client).await;
+    artilect::actuators::chat::back::serve(name, database_url.into(), port, client, auth).await;
 }
@@ -1,17 +1,39 @@
+use base64::prelude::{BASE64_STANDARD, Engine};
+use futures_util::{Stream, StreamExt, stream::{self, BoxStream}};
 use indoc::formatdoc;
 use ouroboros::self_referencing;
 use uuid::Uuid;
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc};
 
+mod backend;
 pub mod config;
+mod embedding;
 mod error;
 pub use error::InferError;
 mod openai;
-use openai::{ApiError, OpenAIMessage, OpenAIContentPart};
+use openai::{
+    ApiError, AudioData, FileData, ImageUrlData, OpenAIContentPart, OpenAIFunctionCall,
+    OpenAIMessage, OpenAIToolCall,
+};
 mod parsing;
+mod profile;
+mod streaming;
+#[cfg(test)]
+mod testing;
+mod tools;
 mod util;
 
-pub use parsing::{FromLlmReply, ParseError, PlainText, WithReasoning, YesNoReply};
+pub use backend::{
+    Backend, Completion, CompletionDelta, CompletionRequest, CompletionStream, LlamaCppBackend,
+    MockBackend, OllamaBackend, OpenAIBackend,
+};
+pub use embedding::Embedding;
+pub use parsing::{
+    FromLlmReply, JsonType, ParseError, PlainText, WithReasoning, YesNoReply, find_and_parse_json,
+};
+pub use profile::{BackendKind, Model, ModelProfile, ProfileError};
+pub use streaming::{InferDelta, ReasoningSplitter};
+pub use tools::{Tool, ToolChoice, ToolError, ToolRegistry};
 
 const AGENT_PROMPT_TEXT: &str = "You are the inference agent. \
 You are responsible for assisting other agents by solving \
@@ -19,11 +41,59 @@ various isolated problems.";
 
 pub struct Client {
     id: Uuid,
+    model: Model,
+    utility_model: Option<Model>,
 }
 
 impl Client {
+    /// Uses the models configured through the environment, see `config`.
     pub fn new() -> Self {
-        Self { id: Uuid::new_v4() }
+        let main_profile = config::main_profile();
+        let utility_model = config::utility_profile(&main_profile).map(Model::new);
+        Self::from_model(Model::new(main_profile)).with_optional_utility_model(utility_model)
+    }
+
+    pub fn from_model(model: Model) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            model,
+            utility_model: None,
+        }
+    }
+
+    pub fn from_profile(profile: ModelProfile) -> Self {
+        Self::from_model(Model::new(profile))
+    }
+
+    /// Uses the default profile with the given backend.
+    pub fn with_backend(backend: impl Backend + 'static) -> Self {
+        Self::from_model(Model::with_backend(ModelProfile::default(), backend))
+    }
+
+    pub fn with_utility_model(self, utility_model: Model) -> Self {
+        self.with_optional_utility_model(Some(utility_model))
+    }
+
+    fn with_optional_utility_model(mut self, utility_model: Option<Model>) -> Self {
+        self.utility_model = utility_model;
+        self
+    }
+
+    pub fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// The model for quick auxiliary calls, falling back to the main one.
+    pub fn utility_model(&self) -> &Model {
+        self.utility_model.as_ref().unwrap_or(&self.model)
+    }
+
+    /// Embeds `inputs` with the main model's backend, one embedding per input.
+    pub async fn embed(&self, inputs: &[&str]) -> Result<Vec<Embedding>, InferError> {
+        if inputs.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(self.model.backend.embed(inputs).await?)
     }
 }
 
@@ -32,6 +102,7 @@ pub enum MessageRole {
     System,
     User,
     Assistant,
+    Tool,
 }
 
 impl MessageRole {
@@ -41,25 +112,22 @@ impl MessageRole {
             Self::System => openai::ROLE_SYSTEM,
             Self::User => openai::ROLE_USER,
             Self::Assistant => openai::ROLE_ASSISTANT,
+            Self::Tool => openai::ROLE_TOOL,
         }
     }
 }
 
-// push content blocks instead, e.g.
-// pub enum ContentBlock {
-//     NewMessage(MessageRole),
-//     Text(Box<str>),
-//     ImageUrl { url: String },
-//     ImageFile { data: Vec<u8> },
-//     // ... other content types
-// }
-
+#[derive(Debug, Clone)]
 pub struct Message {
     pub role: MessageRole,
     pub content: Vec<ContentBlock>,
 }
 
 impl Message {
+    pub fn new(role: MessageRole, content: Vec<ContentBlock>) -> Self {
+        Self { role, content }
+    }
+
     pub fn new_text(role: MessageRole, text: impl Into<String>) -> Self {
         Self {
             role,
@@ -87,11 +155,137 @@ impl Message {
             content: vec![ContentBlock::Text(text.into().into())],
         }
     }
+
+    pub fn new_image_url(role: MessageRole, url: impl Into<Box<str>>) -> Self {
+        Self::new(role, vec![ContentBlock::ImageUrl(url.into())])
+    }
+
+    pub fn new_image(role: MessageRole, media_type: impl Into<Box<str>>, data: impl Into<Arc<[u8]>>) -> Self {
+        Self::new(role, vec![ContentBlock::image(media_type, data)])
+    }
+
+    pub fn new_audio(role: MessageRole, format: impl Into<Box<str>>, data: impl Into<Arc<[u8]>>) -> Self {
+        Self::new(role, vec![ContentBlock::audio(format, data)])
+    }
+
+    pub fn new_file(
+        role: MessageRole,
+        filename: impl Into<Box<str>>,
+        media_type: impl Into<Box<str>>,
+        data: impl Into<Arc<[u8]>>,
+    ) -> Self {
+        Self::new(role, vec![ContentBlock::file(filename, media_type, data)])
+    }
+
+    pub fn estimated_tokens(&self) -> usize {
+        MESSAGE_OVERHEAD_TOKENS + self.content.iter().map(ContentBlock::estimated_tokens).sum::<usize>()
+    }
+
+    /// Appends another block, e.g. to attach a screenshot to a text message.
+    pub fn with_block(mut self, block: ContentBlock) -> Self {
+        self.content.push(block);
+        self
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum ContentBlock {
     Text(Box<str>),
+    ImageUrl(Box<str>),
+    /// Inline image, sent to the model as a base64 `data:` URI
+    Image {
+        media_type: Box<str>,
+        data: Arc<[u8]>,
+    },
+    /// Inline audio; `format` is the encoding as the API names it, e.g. `wav` or `mp3`
+    Audio {
+        format: Box<str>,
+        data: Arc<[u8]>,
+    },
+    File {
+        filename: Option<Box<str>>,
+        media_type: Box<str>,
+        data: Arc<[u8]>,
+    },
+}
+
+impl ContentBlock {
+    pub fn image(media_type: impl Into<Box<str>>, data: impl Into<Arc<[u8]>>) -> Self {
+        Self::Image {
+            media_type: media_type.into(),
+            data: data.into(),
+        }
+    }
+
+    pub fn audio(format: impl Into<Box<str>>, data: impl Into<Arc<[u8]>>) -> Self {
+        Self::Audio {
+            format: format.into(),
+            data: data.into(),
+        }
+    }
+
+    pub fn file(
+        filename: impl Into<Box<str>>,
+        media_type: impl Into<Box<str>>,
+        data: impl Into<Arc<[u8]>>,
+    ) -> Self {
+        Self::File {
+            filename: Some(filename.into()),
+            media_type: media_type.into(),
+            data: data.into(),
+        }
+    }
+
+    fn as_openai_content_part(&self) -> OpenAIContentPart {
+        match self {
+            Self::Text(text) => OpenAIContentPart::Text {
+                text: text.to_string(),
+            },
+            Self::ImageUrl(url) => OpenAIContentPart::ImageUrl {
+                image_url: ImageUrlData { url: url.to_string() },
+            },
+            Self::Image { media_type, data } => OpenAIContentPart::ImageUrl {
+                image_url: ImageUrlData { url: data_uri(media_type, data) },
+            },
+            Self::Audio { format, data } => OpenAIContentPart::Audio {
+                input_audio: AudioData {
+                    format: format.to_string(),
+                    data: BASE64_STANDARD.encode(data),
+                },
+            },
+            Self::File { filename, media_type, data } => OpenAIContentPart::File {
+                file: FileData {
+                    file_data: Some(data_uri(media_type, data)),
+                    file_id: None,
+                    filename: filename.as_ref().map(|f| f.to_string()),
+                },
+            },
+        }
+    }
+}
+
+/// Rough token count of `text`, about four characters per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Role markers and separators a chat template adds around each message
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// A flat guess for media, which models tokenize in their own ways
+const MEDIA_BLOCK_TOKENS: usize = 1024;
+
+impl ContentBlock {
+    pub fn estimated_tokens(&self) -> usize {
+        match self {
+            Self::Text(text) => estimate_tokens(text),
+            _ => MEDIA_BLOCK_TOKENS,
+        }
+    }
+}
+
+fn data_uri(media_type: &str, data: &[u8]) -> String {
+    format!("data:{media_type};base64,{}", BASE64_STANDARD.encode(data))
 }
 
 pub struct ChainLink {
@@ -99,15 +293,53 @@ pub struct ChainLink {
     pub prev: Option<Arc<ChainLink>>,
 }
 
+/// A function call requested by the model, with its arguments still JSON-encoded.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ToolCall {
+    pub id: Box<str>,
+    pub name: Box<str>,
+    pub arguments: Box<str>,
+}
+
+impl From<OpenAIToolCall> for ToolCall {
+    fn from(call: OpenAIToolCall) -> Self {
+        Self {
+            id: call.id.into(),
+            name: call.function.name.into(),
+            arguments: call.function.arguments.into(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum ChainItem {
     NewMessage(MessageRole),
     ContentBlock(ContentBlock),
+    /// Belongs to the current assistant message
+    ToolCall(ToolCall),
+    /// Makes the current message (with the `Tool` role) the reply to the given call
+    ToolResult {
+        call_id: Box<str>,
+        content: Box<str>,
+    },
+}
+
+impl ChainItem {
+    pub fn estimated_tokens(&self) -> usize {
+        match self {
+            Self::NewMessage(_) => MESSAGE_OVERHEAD_TOKENS,
+            Self::ContentBlock(block) => block.estimated_tokens(),
+            Self::ToolCall(call) => estimate_tokens(&call.name) + estimate_tokens(&call.arguments),
+            Self::ToolResult { content, .. } => estimate_tokens(content),
+        }
+    }
 }
 
 pub struct Chain<'a> {
     id: Uuid,
     client: &'a Client,
+    /// Overrides the client's model
+    model: Option<Model>,
     tail: Option<Arc<ChainLink>>,
     item_count: usize,
     message_count: usize,
@@ -118,6 +350,7 @@ impl<'a> Clone for Chain<'a> {
         Self {
             id: Uuid::new_v4(),
             client: self.client,
+            model: self.model.clone(),
             tail: self.tail.clone(),
             item_count: self.item_count,
             message_count: self.message_count,
@@ -130,6 +363,7 @@ impl<'a> Chain<'a> {
         Self {
             id: Uuid::new_v4(),
             client,
+            model: None,
             tail: None,
             item_count: 0,
             message_count: 0,
@@ -145,6 +379,40 @@ impl<'a> Chain<'a> {
         self
     }
 
+    pub fn set_model(&mut self, model: Model) {
+        self.model = Some(model);
+    }
+
+    pub fn with_model(mut self, model: Model) -> Self {
+        self.set_model(model);
+        self
+    }
+
+    pub fn with_profile(self, profile: ModelProfile) -> Self {
+        self.with_model(Model::new(profile))
+    }
+
+    /// Switches to the client's utility model, for calls that don't need the main one.
+    pub fn with_utility_model(self) -> Self {
+        let model = self.client.utility_model().clone();
+        self.with_model(model)
+    }
+
+    pub fn model(&self) -> &Model {
+        self.model.as_ref().unwrap_or(&self.client.model)
+    }
+
+    /// Estimated prompt size, before any reasoning postfix.
+    pub fn estimated_tokens(&self) -> usize {
+        let mut tokens = 0;
+        let mut prev = self.tail.as_ref();
+        while let Some(link) = prev {
+            tokens += link.item.estimated_tokens();
+            prev = link.prev.as_ref();
+        }
+        tokens
+    }
+
     pub fn push_message(&mut self, Message { role, content }: Message) {
         self.push_item(ChainItem::NewMessage(role));
         for block in content {
@@ -210,14 +478,15 @@ impl<'a> Chain<'a> {
         self
     }
 
-    async fn infer_str(&self, toggle_reasoning: Option<bool>) -> Result<Box<str>, InferError> {
+    fn as_openai_messages_for_inference(&self, toggle_reasoning: Option<bool>) -> Vec<OpenAIMessage> {
         let mut messages = self.as_openai_messages();
-        if *config::MODEL_HAS_TOGGLEABLE_REASONING
+        let profile = &self.model().profile;
+        if profile.has_toggleable_reasoning()
             && let Some(toggle_reasoning) = toggle_reasoning
             && let append_str = if toggle_reasoning {
-                config::THINK_ON_POSTFIX.clone()
+                profile.think_on_postfix.clone()
             } else {
-                config::THINK_OFF_POSTFIX.clone()
+                profile.think_off_postfix.clone()
             }
             && !append_str.is_empty()
             && let Some(last_message) = messages.last_mut()
@@ -233,31 +502,65 @@ impl<'a> Chain<'a> {
         }
 
         tracing::info!("Prompt:\n{}", util::wrap_and_indent_yaml(&messages));
+        messages
+    }
 
-        match openai::openai_request(&messages, &config::DEFAULT_MODEL, &config::INFER_URL).await {
-            Ok(response) => {
-                tracing::info!("Response:\n{}", util::wrap_and_indent_yaml(&response));
-                Ok(response)
+    async fn classify_api_error(&self, error: ApiError) -> InferError {
+        match error {
+            ApiError::ErrorResponse(error_text) => {
+                match Box::pin(is_context_length_error(self.client, error_text.as_str())).await {
+                    Ok(true) => InferError::ContextLengthError(Arc::from(error_text)),
+                    Ok(false) => ApiError::ErrorResponse(error_text.clone()).into(),
+                    Err(second_error) => {
+                        eprintln!(
+                            "Warning: Error from is_context_length_error: {:?}",
+                            second_error
+                        );
+                        ApiError::ErrorResponse(error_text.clone()).into()
+                    }
+                }
             },
-            Err(error) => match error {
-                ApiError::ErrorResponse(error_text) => Err(
-                    match Box::pin(is_context_length_error(self.client, error_text.as_str())).await {
-                        Ok(true) => InferError::ContextLengthError(Arc::from(error_text)),
-                        Ok(false) => ApiError::ErrorResponse(error_text.clone()).into(),
-                        Err(second_error) => {
-                            eprintln!(
-                                "Warning: Error from is_context_length_error: {:?}",
-                                second_error
-                            );
-                            ApiError::ErrorResponse(error_text.clone()).into()
-                        }
-                    },
-                ),
-                _ => return Err(error.into()),
+            _ => error.into(),
+        }
+    }
+
+    async fn infer_str(&self, toggle_reasoning: Option<bool>) -> Result<Box<str>, InferError> {
+        let messages = self.as_openai_messages_for_inference(toggle_reasoning);
+        let request = CompletionRequest {
+            messages: &messages,
+            tools: &[],
+            tool_choice: None,
+        };
+        match self.model().backend.complete(request).await {
+            Ok(response) => {
+                tracing::info!("Response:\n{}", util::wrap_and_indent_yaml(&response.content));
+                Ok(response.content)
             },
+            Err(error) => Err(self.classify_api_error(error).await),
         }
     }
 
+    /// Streams the reply as it is generated, with `<think>` reasoning split from the answer.
+    ///
+    /// Errors returned before the first delta go through the same context length detection
+    /// as the non-streaming calls; errors in the middle of the stream are passed through as is.
+    pub async fn infer_stream(
+        &self,
+        toggle_reasoning: Option<bool>,
+    ) -> Result<BoxStream<'static, Result<InferDelta, InferError>>, InferError> {
+        let messages = self.as_openai_messages_for_inference(toggle_reasoning);
+        let request = CompletionRequest {
+            messages: &messages,
+            tools: &[],
+            tool_choice: None,
+        };
+        let deltas = match self.model().backend.complete_stream(request).await {
+            Ok(deltas) => deltas,
+            Err(error) => return Err(self.classify_api_error(error).await),
+        };
+        Ok(split_reasoning(deltas).boxed())
+    }
+
     async fn infer_and_parse<T: FromLlmReply>(
         &self,
         toggle_reasoning: Option<bool>,
@@ -269,7 +572,7 @@ impl<'a> Chain<'a> {
         &self,
         with_reasoning: bool,
     ) -> Result<(WithReasoning<T>, Box<str>), InferError> {
-        Ok(if *config::MODEL_HAS_REASONING {
+        Ok(if self.model().profile.has_reasoning {
             let response = self.infer_str(Some(with_reasoning)).await?;
             let (value, _, value_str) = WithReasoning::<T>::parse(&response)?;
             (value, value_str.into())
@@ -309,6 +612,61 @@ impl<'a> Chain<'a> {
         })
     }
 
+    /// Lets the model call `tools` until it answers with content, which is then parsed as `T`.
+    /// The calls, their results and the final answer all get pushed to the chain.
+    pub async fn infer_with_tools<T: FromLlmReply>(
+        &mut self,
+        tools: &ToolRegistry,
+        mut tool_choice: ToolChoice,
+        max_rounds: usize,
+    ) -> Result<T, InferError> {
+        let openai_tools = tools.as_openai_tools();
+        for _ in 0..max_rounds {
+            let messages = self.as_openai_messages_for_inference(None);
+            let request = CompletionRequest {
+                messages: &messages,
+                tools: &openai_tools,
+                tool_choice: (!tools.is_empty()).then(|| tool_choice.as_openai_value()),
+            };
+            let response = match self.model().backend.complete(request).await {
+                Ok(response) => response,
+                Err(error) => return Err(self.classify_api_error(error).await),
+            };
+            tracing::info!("Response:\n{}", util::wrap_and_indent_yaml(&response));
+
+            let content = response.content;
+            self.push_item(ChainItem::NewMessage(MessageRole::Assistant));
+            if !content.is_empty() {
+                self.push_item(ChainItem::ContentBlock(ContentBlock::Text(content.clone())));
+            }
+            if response.tool_calls.is_empty() {
+                return Ok(T::from_reply(&content)?);
+            }
+
+            let calls = response.tool_calls;
+            for call in &calls {
+                self.push_item(ChainItem::ToolCall(call.clone()));
+            }
+            for call in calls {
+                let result = match tools.call(&call.name, &call.arguments).await {
+                    Ok(result) => result,
+                    Err(error) => {
+                        tracing::warn!("Tool call {} ({}) failed: {}", call.id, call.name, error);
+                        format!("Error: {error}")
+                    }
+                };
+                self.push_item(ChainItem::NewMessage(MessageRole::Tool));
+                self.push_item(ChainItem::ToolResult {
+                    call_id: call.id,
+                    content: result.into(),
+                });
+            }
+            // A forced choice only applies to the first round, or the model could never answer
+            tool_choice = ToolChoice::Auto;
+        }
+        Err(InferError::ToolRoundsExceeded(max_rounds))
+    }
+
     pub async fn infer_drop<T: FromLlmReply>(
         self,
         with_reasoning: bool,
@@ -343,7 +701,7 @@ impl<'a> Chain<'a> {
     }
 
     pub fn as_openai_messages(&self) -> Vec<OpenAIMessage> {
-        let do_convert_system_to_user = !*config::MODEL_USE_SYSTEM_PROMPT;
+        let do_convert_system_to_user = !self.model().profile.use_system_prompt;
         let mut chain_items = Vec::with_capacity(self.item_count);
         let mut prev = self.tail.clone();
         while let Some(cur_item) = prev {
@@ -360,7 +718,7 @@ impl<'a> Chain<'a> {
                         let mut messages = Vec::with_capacity(self.message_count);
                         let mut cur_message = OpenAIMessage {
                             role: role.into_role_str(do_convert_system_to_user),
-                            content: Vec::new(),
+                            ..Default::default()
                         };
                         for item in chain_items {
                             match item {
@@ -383,8 +741,25 @@ impl<'a> Chain<'a> {
                                                 });
                                             }
                                         },
+                                        block => cur_message.content.push(block.as_openai_content_part()),
                                     }
                                 },
+                                ChainItem::ToolCall(call) => {
+                                    cur_message.tool_calls.push(OpenAIToolCall {
+                                        id: call.id.to_string(),
+                                        kind: "function".into(),
+                                        function: OpenAIFunctionCall {
+                                            name: call.name.to_string(),
+                                            arguments: call.arguments.to_string(),
+                                        },
+                                    });
+                                },
+                                ChainItem::ToolResult { call_id, content } => {
+                                    cur_message.tool_call_id = Some(call_id.to_string());
+                                    cur_message.content.push(OpenAIContentPart::Text {
+                                        text: content.to_string(),
+                                    });
+                                },
                             }
                         }
                         messages.push(cur_message);
@@ -397,6 +772,55 @@ impl<'a> Chain<'a> {
     }
 }
 
+struct SplitReasoningState<S> {
+    deltas: Option<S>,
+    splitter: ReasoningSplitter,
+    pending: VecDeque<Result<InferDelta, InferError>>,
+    response: String,
+}
+
+fn split_reasoning<S>(deltas: S) -> impl Stream<Item = Result<InferDelta, InferError>>
+where
+    S: Stream<Item = Result<CompletionDelta, ApiError>> + Unpin,
+{
+    let state = SplitReasoningState {
+        deltas: Some(deltas),
+        splitter: ReasoningSplitter::new(),
+        pending: VecDeque::new(),
+        response: String::new(),
+    };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+            let deltas = state.deltas.as_mut()?;
+            match deltas.next().await {
+                Some(Ok(delta)) => {
+                    if let Some(reasoning) = delta.reasoning
+                        && !reasoning.is_empty()
+                    {
+                        state.pending.push_back(Ok(InferDelta::Reasoning(reasoning)));
+                    }
+                    if let Some(content) = delta.content {
+                        state.response.push_str(&content);
+                        state.pending.extend(state.splitter.push(&content).into_iter().map(Ok));
+                    }
+                }
+                Some(Err(error)) => {
+                    state.deltas = None;
+                    state.pending.push_back(Err(error.into()));
+                }
+                None => {
+                    state.deltas = None;
+                    tracing::info!("Response:\n{}", util::wrap_and_indent_yaml(&state.response));
+                    state.pending.extend(state.splitter.finish().into_iter().map(Ok));
+                }
+            }
+        }
+    })
+}
+
 #[self_referencing]
 pub struct RootChain {
     client: Client,
@@ -424,6 +848,10 @@ impl RootChain {
         }.build()
     }
 
+    pub fn client(&self) -> &Client {
+        self.borrow_client()
+    }
+
     pub fn fork(&self) -> Chain {
         self.with_chain(|chain| chain.clone())
     }
@@ -446,6 +874,7 @@ pub async fn is_context_length_error(client: &Client, error: &str) -> Result<boo
     let quoted_error = format!("\"{}\"", error.replace('\\', "\\\\").replace('"', "\\\""));
 
     Ok(Chain::new(client)
+        .with_utility_model()
         .with_message(
             Message::new_text_user(
                 formatdoc! {"
@@ -470,22 +899,28 @@ mod tests {
     use once_cell::sync::Lazy;
     use parsing::{FromLlmReplyArray, FromLlmReplyArrayItem};
     use serde::Deserialize;
-    use dotenvy::dotenv;
 
     use super::*;
 
-    static CLIENT: Lazy<Client> = Lazy::new(|| {
-        dotenv().ok();
-        Client { id: Uuid::new_v4() }
-    });
+    /// For chains that are only rendered, never inferred
+    static CLIENT: Lazy<Client> = Lazy::new(|| Client::with_backend(MockBackend::new()));
 
     mod top_level_array_parsing {
         use super::*;
         #[tokio::test]
         async fn parses_top_level_array_of_strings() {
-            let result = Chain::new(&CLIENT).with_message(Message {
-                role: MessageRole::User,
-                content: formatdoc! {"
+            let client = Client::with_backend(MockBackend::new().with_reply(formatdoc! {"
+                ```json
+                [
+                    \"And you, my father, there on the sad height,\",
+                    \"Curse, bless, me now with your fierce tears, I pray.\",
+                    \"Do not go gentle into that good night.\",
+                    \"Rage, rage against the dying of the light.\"
+                ]
+                ```
+            "}));
+            let result = Chain::new(&client).with_message(Message::new_text_user(
+                formatdoc! {"
                     # Instructions
 
                     Break down the following text into a list of lines, return as JSON array of strings:
@@ -498,8 +933,8 @@ mod tests {
                     # Format Instructions
 
                     With no preamble, respond with a JSON array of strings.
-                "}.into(),
-            }).infer_drop::<Vec<Box<str>>>(false)
+                "},
+            )).infer_drop::<Vec<Box<str>>>(false)
                 .await
                 .map(|lines| {
                     lines
@@ -535,9 +970,19 @@ mod tests {
                 habitable: bool,
             }
 
-            let result = Chain::new(&CLIENT).with_message(Message {
-                role: MessageRole::User,
-                content: formatdoc! {"
+            let client = Client::with_backend(
+                MockBackend::new()
+                    .with_reply("The Sun is 1 solar mass, Proxima Centauri b is tiny, Betelgeuse is huge.")
+                    .with_reply(formatdoc! {r#"
+                        [
+                            {{"name": "Sun", "mass": 1.0, "habitable": false}},
+                            {{"name": "Proxima Centauri b", "mass": 0.0000035, "habitable": true}},
+                            {{"name": "Betelgeuse", "mass": 19.0, "habitable": false}}
+                        ]
+                    "#}),
+            );
+            let result = Chain::new(&client).with_message(Message::new_text_user(
+                formatdoc! {"
                     # Instructions
 
                     Here are some interesting celestial objects to consider:
@@ -556,8 +1001,8 @@ mod tests {
                         \"mass\": the mass of the celestial object in solar masses
                         \"habitable\": true if the celestial object is habitable, false otherwise
                     }}
-                "}.into(),
-            }).infer_drop::<Vec<SpaceObject>>(true).await;
+                "},
+            )).infer_drop::<Vec<SpaceObject>>(true).await;
             assert!(result.is_ok());
 
             let objects = result.unwrap().value;
@@ -581,33 +1026,385 @@ mod tests {
         }
     }
 
+    mod streaming {
+        use super::*;
+        use testing::{MockResponse, mock_server};
+
+        fn chunk(content: &str) -> String {
+            format!(
+                "data: {}\n\n",
+                serde_json::json!({ "choices": [{ "delta": { "content": content } }] })
+            )
+        }
+
+        #[tokio::test]
+        async fn streams_deltas_with_reasoning_split() {
+            let (url, server) = mock_server(vec![MockResponse::sse([
+                chunk("<thi"),
+                chunk("nk>Short one.</"),
+                chunk("think>\n\nHel"),
+                ": keep-alive\n\n".to_string(),
+                chunk("lo!"),
+                "data: [DONE]\n\n".to_string(),
+            ])])
+            .await;
+
+            let client = Client::with_backend(OpenAIBackend::new(url, "test-model"));
+            let deltas = Chain::new(&client)
+                .with_message(Message::new_text_user("Hi"))
+                .infer_stream(None)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+
+            let content = deltas
+                .iter()
+                .filter_map(|delta| match delta {
+                    InferDelta::Content(text) => Some(text.as_ref()),
+                    _ => None,
+                })
+                .collect::<String>();
+            assert_eq!(content, "Hello!");
+            assert_eq!(deltas[0], InferDelta::Reasoning("Short one.".into()));
+
+            let requests = server.await.unwrap();
+            let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+            assert!(requests[0].head.starts_with("POST /v1/chat/completions"));
+            assert_eq!(body["stream"], true);
+            assert_eq!(body["model"], "test-model");
+        }
+
+        #[tokio::test]
+        async fn passes_through_separate_reasoning_field() {
+            let (url, _) = mock_server(vec![MockResponse::sse([
+                format!(
+                    "data: {}\n\n",
+                    serde_json::json!({ "choices": [{ "delta": { "reasoning_content": "Hmm." } }] })
+                ),
+                chunk("Yes."),
+                "data: [DONE]\n\n".to_string(),
+            ])])
+            .await;
+
+            let client = Client::with_backend(OpenAIBackend::new(url, "test-model"));
+            let deltas = Chain::new(&client)
+                .with_message(Message::new_text_user("Hi"))
+                .infer_stream(None)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(
+                deltas,
+                vec![InferDelta::Reasoning("Hmm.".into()), InferDelta::Content("Yes.".into())]
+            );
+        }
+
+        #[tokio::test]
+        async fn reports_error_response_before_streaming() {
+            let (url, _) = mock_server(vec![
+                MockResponse::json(r#"{"error": "model not loaded"}"#).with_status(400),
+            ])
+            .await;
+
+            let messages = Chain::new(&CLIENT)
+                .with_message(Message::new_text_user("Hi"))
+                .as_openai_messages();
+            let backend = OpenAIBackend::new(url, "test-model");
+            let result = backend
+                .complete_stream(CompletionRequest {
+                    messages: &messages,
+                    tools: &[],
+                    tool_choice: None,
+                })
+                .await;
+            assert!(matches!(result, Err(ApiError::ErrorResponse(error)) if error == "model not loaded"));
+        }
+
+        #[tokio::test]
+        async fn streams_mock_reply() {
+            let client = Client::with_backend(MockBackend::new().with_reply("<think>Easy.</think>Two words"));
+            let deltas = Chain::new(&client)
+                .with_message(Message::new_text_user("Hi"))
+                .infer_stream(None)
+                .await
+                .unwrap()
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            assert_eq!(
+                deltas,
+                vec![
+                    InferDelta::Reasoning("Easy.".into()),
+                    InferDelta::Content("Two ".into()),
+                    InferDelta::Content("words".into()),
+                ]
+            );
+        }
+    }
+
+    mod tool_calling {
+        use super::*;
+        use testing::{MockResponse, mock_server};
+
+        #[derive(Deserialize)]
+        struct AddArguments {
+            a: i64,
+            b: i64,
+        }
+
+        fn add_tool() -> Tool {
+            Tool::typed(
+                "add",
+                "Adds two integers",
+                serde_json::json!({
+                    "type": "object",
+                    "properties": {
+                        "a": { "type": "integer" },
+                        "b": { "type": "integer" },
+                    },
+                    "required": ["a", "b"],
+                }),
+                |AddArguments { a, b }| async move { Ok((a + b).to_string()) },
+            )
+        }
+
+        fn completion(message: serde_json::Value) -> MockResponse {
+            MockResponse::json(serde_json::json!({ "choices": [{ "message": message }] }).to_string())
+        }
+
+        #[tokio::test]
+        async fn runs_tools_until_answer() {
+            let (url, server) = mock_server(vec![
+                completion(serde_json::json!({
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "add", "arguments": "{\"a\": 2, \"b\": 3}" },
+                    }],
+                })),
+                completion(serde_json::json!({ "content": "2 + 3 = 5" })),
+            ])
+            .await;
+
+            let tools = ToolRegistry::new().with_tool(add_tool());
+            let client = Client::with_backend(OpenAIBackend::new(url, "test-model"));
+            let mut chain = Chain::new(&client).with_message(Message::new_text_user("What is 2 + 3?"));
+            let PlainText(answer) = chain
+                .infer_with_tools(&tools, ToolChoice::Required, 4)
+                .await
+                .unwrap();
+            assert_eq!(&*answer, "2 + 3 = 5");
+
+            let requests = server.await.unwrap();
+            let first: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+            assert_eq!(first["tools"][0]["function"]["name"], "add");
+            assert_eq!(first["tool_choice"], "required");
+
+            let second: serde_json::Value = serde_json::from_str(&requests[1].body).unwrap();
+            assert_eq!(second["tool_choice"], "auto");
+            let messages = second["messages"].as_array().unwrap();
+            assert_eq!(messages[1]["role"], "assistant");
+            assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+            assert_eq!(messages[2]["role"], "tool");
+            assert_eq!(messages[2]["tool_call_id"], "call_1");
+            assert_eq!(messages[2]["content"][0]["text"], "5");
+
+            // The whole exchange is now part of the chain
+            assert_eq!(chain.as_openai_messages().len(), 4);
+        }
+
+        #[tokio::test]
+        async fn reports_tool_errors_to_model() {
+            let (url, server) = mock_server(vec![
+                completion(serde_json::json!({
+                    "tool_calls": [{
+                        "id": "call_1",
+                        "type": "function",
+                        "function": { "name": "multiply", "arguments": "{}" },
+                    }],
+                })),
+                completion(serde_json::json!({ "content": "Sorry, I can't multiply." })),
+            ])
+            .await;
+
+            let tools = ToolRegistry::new().with_tool(add_tool());
+            let client = Client::with_backend(OpenAIBackend::new(url, "test-model"));
+            let mut chain = Chain::new(&client).with_message(Message::new_text_user("What is 2 * 3?"));
+            chain
+                .infer_with_tools::<PlainText>(&tools, ToolChoice::Auto, 4)
+                .await
+                .unwrap();
+
+            let requests = server.await.unwrap();
+            let second: serde_json::Value = serde_json::from_str(&requests[1].body).unwrap();
+            assert_eq!(second["messages"][2]["content"][0]["text"], "Error: Unknown tool: multiply");
+        }
+
+        #[tokio::test]
+        async fn gives_up_after_max_rounds() {
+            let call = || ToolCall {
+                id: "call_1".into(),
+                name: "add".into(),
+                arguments: r#"{"a": 1, "b": 1}"#.into(),
+            };
+            let backend = MockBackend::new().with_tool_calls([call()]).with_tool_calls([call()]);
+            let client = Client::with_backend(backend.clone());
+
+            let tools = ToolRegistry::new().with_tool(add_tool());
+            let mut chain = Chain::new(&client).with_message(Message::new_text_user("Keep adding"));
+            let result = chain
+                .infer_with_tools::<PlainText>(&tools, ToolChoice::Auto, 2)
+                .await;
+            assert!(matches!(result, Err(InferError::ToolRoundsExceeded(2))));
+            assert_eq!(backend.requests()[1]["messages"][2]["content"][0]["text"], "2");
+        }
+    }
+
+    mod multimodal {
+        use super::*;
+
+        #[test]
+        fn emits_content_parts_for_each_block() {
+            let chain = Chain::new(&CLIENT).with_message(
+                Message::new_text_user("What is on this screenshot?")
+                    .with_block(ContentBlock::image("image/png", vec![1u8, 2, 3]))
+                    .with_block(ContentBlock::ImageUrl("https://example.com/cat.jpg".into()))
+                    .with_block(ContentBlock::audio("wav", vec![4u8]))
+                    .with_block(ContentBlock::file("notes.pdf", "application/pdf", vec![5u8])),
+            );
+            let messages = serde_json::to_value(chain.as_openai_messages()).unwrap();
+            assert_eq!(
+                messages[0]["content"],
+                serde_json::json!([
+                    { "type": "text", "text": "What is on this screenshot?" },
+                    { "type": "image_url", "image_url": { "url": "data:image/png;base64,AQID" } },
+                    { "type": "image_url", "image_url": { "url": "https://example.com/cat.jpg" } },
+                    { "type": "input_audio", "input_audio": { "format": "wav", "data": "BA==" } },
+                    {
+                        "type": "file",
+                        "file": { "file_data": "data:application/pdf;base64,BQ==", "filename": "notes.pdf" },
+                    },
+                ])
+            );
+        }
+
+        #[test]
+        fn keeps_text_after_image_separate() {
+            let chain = Chain::new(&CLIENT)
+                .with_message(Message::new_image_url(MessageRole::User, "https://example.com/a.png"))
+                .with_item(ChainItem::ContentBlock(ContentBlock::Text("Describe it.".into())));
+            let messages = serde_json::to_value(chain.as_openai_messages()).unwrap();
+            assert_eq!(messages[0]["content"][1], serde_json::json!({ "type": "text", "text": "Describe it." }));
+        }
+    }
+
+    mod profiles {
+        use super::*;
+
+        #[test]
+        fn estimates_chain_tokens() {
+            let chain = Chain::new(&CLIENT)
+                .with_message(Message::new_text_system("12345678"))
+                .with_message(Message::new_text_user("123").with_block(ContentBlock::image("image/png", vec![0u8])));
+            assert_eq!(
+                chain.estimated_tokens(),
+                2 * MESSAGE_OVERHEAD_TOKENS + 2 + 1 + MEDIA_BLOCK_TOKENS
+            );
+        }
+
+        #[test]
+        fn applies_overridden_profile() {
+            let profile = ModelProfile {
+                use_system_prompt: false,
+                think_off_postfix: " /no_think".into(),
+                ..Default::default()
+            };
+            let chain = Chain::new(&CLIENT)
+                .with_message(Message::new_text_system("Be brief."))
+                .with_message(Message::new_text_user("Hi"));
+            assert_eq!(chain.as_openai_messages()[0].role, openai::ROLE_SYSTEM);
+
+            let chain = chain.with_model(Model::with_backend(profile, MockBackend::new()));
+            let messages = chain.as_openai_messages_for_inference(Some(false));
+            assert_eq!(messages[0].role, openai::ROLE_USER);
+            assert!(matches!(&messages[1].content[0], OpenAIContentPart::Text { text } if text == "Hi /no_think"));
+        }
+    }
+
     mod context_length_error {
         use super::*;
 
+        fn client_answering(is_context_length_error: bool) -> (Client, MockBackend) {
+            let backend = MockBackend::new().with_reply(format!(r#"{{"answer": {is_context_length_error}}}"#));
+            (Client::with_backend(backend.clone()), backend)
+        }
+
         #[tokio::test]
         async fn detects_context_length_error() {
             let error = "Trying to keep the first 5406 tokens when context the overflows. \
                 However, the model is loaded with context length of only 1056 tokens, which is not enough. \
                 Try to load the model with a larger context length, or provide a shorter input";
 
-            assert!(is_context_length_error(&CLIENT, error).await.unwrap());
-        }
-        #[tokio::test]
-        async fn ignores_rate_limit_error() {
-            let error = "API rate limit exceeded. Please try again later.";
-            assert!(!is_context_length_error(&CLIENT, error).await.unwrap());
+            let (client, backend) = client_answering(true);
+            assert!(is_context_length_error(&client, error).await.unwrap());
+            let prompt = backend.requests()[0]["messages"][0]["content"][0]["text"].to_string();
+            assert!(prompt.contains("context length of only 1056 tokens"));
         }
 
         #[tokio::test]
-        async fn ignores_auth_error() {
-            let error = "Invalid API key. Please check your credentials and try again.";
-            assert!(!is_context_length_error(&CLIENT, error).await.unwrap());
+        async fn classifies_failed_inference() {
+            let utility_backend = MockBackend::new().with_reply(r#"{"answer": true}"#);
+            let client = Client::with_backend(MockBackend::new().with_error("context window exceeded"))
+                .with_utility_model(Model::with_backend(ModelProfile::default(), utility_backend.clone()));
+            let result = Chain::new(&client)
+                .with_message(Message::new_text_user("Hi"))
+                .infer_drop::<PlainText>(false)
+                .await;
+            assert!(matches!(result, Err(InferError::ContextLengthError(error)) if &*error == "context window exceeded"));
+            // The check itself went to the utility model
+            assert_eq!(utility_backend.requests().len(), 1);
         }
 
-        #[tokio::test]
-        async fn ignores_overload_error() {
-            let error = "Model 'gpt-4' is currently overloaded. Please try again later.";
-            assert!(!is_context_length_error(&CLIENT, error).await.unwrap());
+        /// Asks the models configured through the environment, as a mock can't judge an error
+        mod live {
+            use super::*;
+
+            fn client() -> Client {
+                dotenvy::dotenv().ok();
+                Client::new()
+            }
+
+            #[tokio::test]
+            #[ignore = "needs a running model"]
+            async fn ignores_rate_limit_error() {
+                let error = "API rate limit exceeded. Please try again later.";
+                assert!(!is_context_length_error(&client(), error).await.unwrap());
+            }
+
+            #[tokio::test]
+            #[ignore = "needs a running model"]
+            async fn ignores_auth_error() {
+                let error = "Invalid API key. Please check your credentials and try again.";
+                assert!(!is_context_length_error(&client(), error).await.unwrap());
+            }
+
+            #[tokio::test]
+            #[ignore = "needs a running model"]
+            async fn ignores_overload_error() {
+                let error = "Model 'gpt-4' is currently overloaded. Please try again later.";
+                assert!(!is_context_length_error(&client(), error).await.unwrap());
+            }
         }
     }
 }
@@ -0,0 +1,69 @@
+use futures_util::{future::BoxFuture, stream::BoxStream};
+
+use super::{
+    Embedding, ToolCall,
+    openai::{ApiError, OpenAIMessage, OpenAITool},
+};
+
+mod llama_cpp;
+mod mock;
+mod ollama;
+mod openai;
+
+pub use llama_cpp::LlamaCppBackend;
+pub use mock::MockBackend;
+pub use ollama::OllamaBackend;
+pub use openai::OpenAIBackend;
+
+/// What a chain sends for inference. Messages use the OpenAI shape, which every
+/// other backend translates from.
+#[derive(Debug)]
+pub struct CompletionRequest<'a> {
+    pub messages: &'a [OpenAIMessage],
+    pub tools: &'a [OpenAITool<'a>],
+    pub tool_choice: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Completion {
+    pub content: Box<str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Debug, Default)]
+pub struct CompletionDelta {
+    pub content: Option<Box<str>>,
+    /// For servers that report reasoning separately instead of inline `<think>` tags
+    pub reasoning: Option<Box<str>>,
+}
+
+pub type CompletionStream = BoxStream<'static, Result<CompletionDelta, ApiError>>;
+
+/// A chat inference server protocol.
+pub trait Backend: Send + Sync {
+    fn complete<'a>(&'a self, request: CompletionRequest<'a>) -> BoxFuture<'a, Result<Completion, ApiError>>;
+
+    fn complete_stream<'a>(
+        &'a self,
+        request: CompletionRequest<'a>,
+    ) -> BoxFuture<'a, Result<CompletionStream, ApiError>>;
+
+    /// One embedding per input, in the same order.
+    fn embed<'a>(&'a self, inputs: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<Embedding>, ApiError>> {
+        let _ = inputs;
+        Box::pin(async { Err(ApiError::Unsupported("embeddings")) })
+    }
+}
+
+/// Joins the text parts of a message, for protocols that take plain string content.
+fn text_content(message: &OpenAIMessage) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|part| match part {
+            super::openai::OpenAIContentPart::Text { text } => Some(text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
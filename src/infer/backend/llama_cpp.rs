@@ -0,0 +1,199 @@
+use futures_util::{FutureExt, StreamExt, future::BoxFuture};
+use serde::{Deserialize, Serialize};
+
+use super::{Backend, Completion, CompletionDelta, CompletionRequest, CompletionStream, text_content};
+use crate::{
+    infer::openai::{ApiError, OpenAIMessage, ROLE_ASSISTANT},
+    sse,
+};
+
+/// llama.cpp server's raw `/completion`, with the chat rendered as a ChatML prompt.
+///
+/// Only text content is sent, and tools aren't supported.
+pub struct LlamaCppBackend {
+    url: Box<str>,
+}
+
+impl LlamaCppBackend {
+    pub fn new(url: impl Into<Box<str>>) -> Self {
+        Self { url: url.into() }
+    }
+
+    async fn send(&self, request: &CompletionRequest<'_>, stream: bool) -> Result<reqwest::Response, ApiError> {
+        if !request.tools.is_empty() {
+            return Err(ApiError::Unsupported("tools"));
+        }
+        let prompt = chatml_prompt(request.messages);
+        let response = reqwest::Client::new()
+            .post(format!("{}/completion", self.url))
+            .json(&LlamaCppRequest {
+                prompt: &prompt,
+                stream,
+                cache_prompt: true,
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let response_text = response.text().await?;
+            return Err(error_from_response(&response_text));
+        }
+        Ok(response)
+    }
+}
+
+/// Renders messages in the ChatML format. A trailing assistant message is left
+/// open so the model continues it instead of starting a new one.
+fn chatml_prompt(messages: &[OpenAIMessage]) -> String {
+    let mut prompt = String::new();
+    for (index, message) in messages.iter().enumerate() {
+        if message.content.len() > message.content.iter().filter(|p| p.is_text()).count() {
+            tracing::warn!("llama.cpp backend only sends text, skipping other content parts");
+        }
+        prompt.push_str("<|im_start|>");
+        prompt.push_str(message.role);
+        prompt.push('\n');
+        prompt.push_str(&text_content(message));
+        let is_prefill = index + 1 == messages.len() && message.role == ROLE_ASSISTANT;
+        if is_prefill {
+            return prompt;
+        }
+        prompt.push_str("<|im_end|>\n");
+    }
+    prompt.push_str("<|im_start|>assistant\n");
+    prompt
+}
+
+fn error_from_response(response_text: &str) -> ApiError {
+    match serde_json::from_str::<LlamaCppResponse>(response_text) {
+        Ok(LlamaCppResponse { error: Some(error), .. }) => ApiError::ErrorResponse(match error {
+            serde_json::Value::String(message) => message,
+            error => error
+                .get("message")
+                .and_then(|message| message.as_str())
+                .map(String::from)
+                .unwrap_or_else(|| error.to_string()),
+        }),
+        _ => ApiError::ErrorResponse(response_text.to_string()),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct LlamaCppRequest<'a> {
+    prompt: &'a str,
+    stream: bool,
+    cache_prompt: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct LlamaCppResponse {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    error: Option<serde_json::Value>,
+}
+
+impl Backend for LlamaCppBackend {
+    fn complete<'a>(&'a self, request: CompletionRequest<'a>) -> BoxFuture<'a, Result<Completion, ApiError>> {
+        async move {
+            let response_text = self.send(&request, false).await?.text().await?;
+            let response = serde_json::from_str::<LlamaCppResponse>(&response_text)?;
+            if response.error.is_some() {
+                return Err(error_from_response(&response_text));
+            }
+            Ok(Completion {
+                content: response.content.into(),
+                tool_calls: Vec::new(),
+            })
+        }
+        .boxed()
+    }
+
+    fn complete_stream<'a>(
+        &'a self,
+        request: CompletionRequest<'a>,
+    ) -> BoxFuture<'a, Result<CompletionStream, ApiError>> {
+        async move {
+            let response = self.send(&request, true).await?;
+            Ok(sse::decode(response.bytes_stream().boxed())
+                .map(|event| {
+                    let event = event?;
+                    let response = serde_json::from_str::<LlamaCppResponse>(&event.data)?;
+                    if response.error.is_some() {
+                        return Err(error_from_response(&event.data));
+                    }
+                    Ok(CompletionDelta {
+                        content: Some(response.content.into()),
+                        reasoning: None,
+                    })
+                })
+                .boxed())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::{
+        openai::OpenAIContentPart,
+        testing::{MockResponse, mock_server},
+    };
+
+    fn message(role: &'static str, text: &str) -> OpenAIMessage {
+        OpenAIMessage {
+            role,
+            content: vec![OpenAIContentPart::Text { text: text.into() }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn continues_trailing_assistant_message() {
+        let prompt = chatml_prompt(&[message("user", "Hi"), message("assistant", "<think>")]);
+        assert_eq!(prompt, "<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n<think>");
+    }
+
+    #[tokio::test]
+    async fn streams_completion_events() {
+        let event = |content: &str| format!("data: {}\n\n", serde_json::json!({ "content": content, "stop": false }));
+        let (url, server) = mock_server(vec![MockResponse::sse([event("Hel"), event("lo")])]).await;
+
+        let messages = [message("user", "Hi")];
+        let content = LlamaCppBackend::new(url)
+            .complete_stream(CompletionRequest {
+                messages: &messages,
+                tools: &[],
+                tool_choice: None,
+            })
+            .await
+            .unwrap()
+            .map(|delta| delta.unwrap().content.unwrap_or_default().to_string())
+            .collect::<String>()
+            .await;
+        assert_eq!(content, "Hello");
+
+        let requests = server.await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert!(requests[0].head.starts_with("POST /completion"));
+        assert_eq!(body["prompt"], "<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n");
+    }
+
+    #[tokio::test]
+    async fn reports_error_objects() {
+        let (url, _) = mock_server(vec![
+            MockResponse::json(r#"{"error": {"code": 500, "message": "slot unavailable"}}"#).with_status(500),
+        ])
+        .await;
+
+        let messages = [message("user", "Hi")];
+        let result = LlamaCppBackend::new(url)
+            .complete(CompletionRequest {
+                messages: &messages,
+                tools: &[],
+                tool_choice: None,
+            })
+            .await;
+        assert!(matches!(result, Err(ApiError::ErrorResponse(error)) if error == "slot unavailable"));
+    }
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::{FutureExt, StreamExt, future::BoxFuture, stream};
+
+use super::{Backend, Completion, CompletionDelta, CompletionRequest, CompletionStream};
+use crate::infer::{Embedding, ToolCall, openai::ApiError};
+
+const MOCK_EMBEDDING_DIMENSIONS: usize = 64;
+
+enum ScriptedReply {
+    Completion(Completion),
+    Error(String),
+}
+
+#[derive(Default)]
+struct MockState {
+    replies: VecDeque<ScriptedReply>,
+    requests: Vec<serde_json::Value>,
+}
+
+/// Replays scripted replies in order, without any network. Clones share the
+/// same script, so a test can keep one to inspect the recorded requests.
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_reply(self, content: impl Into<Box<str>>) -> Self {
+        self.push(ScriptedReply::Completion(Completion {
+            content: content.into(),
+            tool_calls: Vec::new(),
+        }))
+    }
+
+    pub fn with_tool_calls(self, tool_calls: impl IntoIterator<Item = ToolCall>) -> Self {
+        self.push(ScriptedReply::Completion(Completion {
+            content: "".into(),
+            tool_calls: tool_calls.into_iter().collect(),
+        }))
+    }
+
+    /// Fails the next request the way a server's error response would.
+    pub fn with_error(self, message: impl Into<String>) -> Self {
+        self.push(ScriptedReply::Error(message.into()))
+    }
+
+    /// The requests received so far, as `{messages, tools, tool_choice}` JSON.
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    fn push(self, reply: ScriptedReply) -> Self {
+        self.state.lock().unwrap().replies.push_back(reply);
+        self
+    }
+
+    fn next_reply(&self, request: &CompletionRequest) -> Result<Completion, ApiError> {
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(serde_json::json!({
+            "messages": request.messages,
+            "tools": request.tools,
+            "tool_choice": request.tool_choice,
+        }));
+        match state.replies.pop_front() {
+            Some(ScriptedReply::Completion(completion)) => Ok(completion),
+            Some(ScriptedReply::Error(message)) => Err(ApiError::ErrorResponse(message)),
+            None => Err(ApiError::ErrorResponse("MockBackend has no scripted replies left".into())),
+        }
+    }
+}
+
+impl Backend for MockBackend {
+    fn complete<'a>(&'a self, request: CompletionRequest<'a>) -> BoxFuture<'a, Result<Completion, ApiError>> {
+        let reply = self.next_reply(&request);
+        async move { reply }.boxed()
+    }
+
+    /// Streams the scripted content a word at a time.
+    fn complete_stream<'a>(
+        &'a self,
+        request: CompletionRequest<'a>,
+    ) -> BoxFuture<'a, Result<CompletionStream, ApiError>> {
+        let reply = self.next_reply(&request);
+        async move {
+            let content = reply?.content;
+            let deltas = content
+                .split_inclusive(char::is_whitespace)
+                .map(|word| {
+                    Ok(CompletionDelta {
+                        content: Some(word.into()),
+                        reasoning: None,
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(stream::iter(deltas).boxed())
+        }
+        .boxed()
+    }
+
+    /// Hashes words into buckets, so texts sharing words come out similar.
+    fn embed<'a>(&'a self, inputs: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<Embedding>, ApiError>> {
+        let embeddings = inputs
+            .iter()
+            .map(|input| {
+                let mut values = vec![0.0; MOCK_EMBEDDING_DIMENSIONS];
+                for word in input.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+                    // FNV-1a, to stay the same across runs
+                    let hash = word
+                        .to_lowercase()
+                        .bytes()
+                        .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+                    values[(hash % MOCK_EMBEDDING_DIMENSIONS as u64) as usize] += 1.0;
+                }
+                Embedding::from(values)
+            })
+            .collect();
+        async move { Ok(embeddings) }.boxed()
+    }
+}
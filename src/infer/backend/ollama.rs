@@ -0,0 +1,349 @@
+use futures_util::{FutureExt, StreamExt, future::BoxFuture, stream};
+use serde::{Deserialize, Serialize};
+
+use super::{Backend, Completion, CompletionDelta, CompletionRequest, CompletionStream, text_content};
+use crate::infer::{
+    Embedding, ToolCall,
+    openai::{ApiError, OpenAIContentPart, OpenAIMessage, OpenAITool},
+};
+
+/// Ollama's native `/api/chat`, which streams newline-delimited JSON, and `/api/embed`.
+pub struct OllamaBackend {
+    url: Box<str>,
+    model: Box<str>,
+    embedding_model: Option<Box<str>>,
+}
+
+impl OllamaBackend {
+    pub fn new(url: impl Into<Box<str>>, model: impl Into<Box<str>>) -> Self {
+        Self {
+            url: url.into(),
+            model: model.into(),
+            embedding_model: None,
+        }
+    }
+
+    /// Without one, embeddings are requested from the chat model.
+    pub fn with_embedding_model(mut self, embedding_model: Option<Box<str>>) -> Self {
+        self.embedding_model = embedding_model;
+        self
+    }
+
+    async fn send(&self, request: &CompletionRequest<'_>, stream: bool) -> Result<reqwest::Response, ApiError> {
+        let ollama_request = OllamaRequest {
+            model: &self.model,
+            messages: request.messages.iter().map(OllamaMessage::from).collect(),
+            stream,
+            tools: request.tools,
+        };
+        let response = reqwest::Client::new()
+            .post(format!("{}/api/chat", self.url))
+            .json(&ollama_request)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let response_text = response.text().await?;
+            return Err(match serde_json::from_str::<OllamaResponse>(&response_text) {
+                Ok(OllamaResponse { error: Some(error), .. }) => ApiError::ErrorResponse(error),
+                _ => ApiError::ErrorResponse(response_text),
+            });
+        }
+        Ok(response)
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaEmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [&'a str],
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaEmbedResponse {
+    #[serde(default)]
+    embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OllamaRequest<'a> {
+    model: &'a str,
+    messages: Vec<OllamaMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    tools: &'a [OpenAITool<'a>],
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OllamaMessage {
+    #[serde(default)]
+    role: String,
+    #[serde(default)]
+    content: String,
+    /// Base64 without the `data:` prefix
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thinking: Option<String>,
+}
+
+impl From<&OpenAIMessage> for OllamaMessage {
+    fn from(message: &OpenAIMessage) -> Self {
+        let images = message
+            .content
+            .iter()
+            .filter_map(|part| match part {
+                OpenAIContentPart::ImageUrl { image_url } => match image_url.url.split_once(";base64,") {
+                    Some((_, data)) if image_url.url.starts_with("data:") => Some(data.to_string()),
+                    _ => {
+                        tracing::warn!("Ollama only accepts inline images, skipping {}", image_url.url);
+                        None
+                    }
+                },
+                _ => None,
+            })
+            .collect();
+        Self {
+            role: message.role.to_string(),
+            content: text_content(message),
+            images,
+            tool_calls: message
+                .tool_calls
+                .iter()
+                .map(|call| OllamaToolCall {
+                    function: OllamaFunctionCall {
+                        name: call.function.name.clone(),
+                        arguments: serde_json::from_str(&call.function.arguments)
+                            .unwrap_or_else(|_| call.function.arguments.clone().into()),
+                    },
+                })
+                .collect(),
+            thinking: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    /// Unlike OpenAI, a JSON object rather than an encoded string
+    arguments: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaResponse {
+    #[serde(default)]
+    message: Option<OllamaMessage>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+impl OllamaResponse {
+    fn into_message(self) -> Result<OllamaMessage, ApiError> {
+        match self.error {
+            Some(error) => Err(ApiError::ErrorResponse(error)),
+            None => Ok(self.message.unwrap_or_default()),
+        }
+    }
+}
+
+impl Backend for OllamaBackend {
+    fn complete<'a>(&'a self, request: CompletionRequest<'a>) -> BoxFuture<'a, Result<Completion, ApiError>> {
+        async move {
+            let response_text = self.send(&request, false).await?.text().await?;
+            let message = serde_json::from_str::<OllamaResponse>(&response_text)?.into_message()?;
+            Ok(Completion {
+                content: message.content.into(),
+                tool_calls: message
+                    .tool_calls
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, call)| ToolCall {
+                        // Ollama doesn't identify calls, but results still have to refer to them
+                        id: format!("call_{index}").into(),
+                        name: call.function.name.into(),
+                        arguments: call.function.arguments.to_string().into(),
+                    })
+                    .collect(),
+            })
+        }
+        .boxed()
+    }
+
+    fn complete_stream<'a>(
+        &'a self,
+        request: CompletionRequest<'a>,
+    ) -> BoxFuture<'a, Result<CompletionStream, ApiError>> {
+        async move {
+            let response = self.send(&request, true).await?;
+            let lines = stream::unfold(
+                (response.bytes_stream().boxed(), Vec::<u8>::new(), false),
+                |(mut bytes, mut buffer, mut is_done)| async move {
+                    loop {
+                        if let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                            let line = buffer.drain(..=end).collect::<Vec<_>>();
+                            return Some((Ok(line), (bytes, buffer, is_done)));
+                        }
+                        if is_done {
+                            let line = std::mem::take(&mut buffer);
+                            return (!line.is_empty()).then_some((Ok(line), (bytes, buffer, true)));
+                        }
+                        match bytes.next().await {
+                            Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                            Some(Err(error)) => return Some((Err(ApiError::from(error)), (bytes, Vec::new(), true))),
+                            None => is_done = true,
+                        }
+                    }
+                },
+            );
+            Ok(lines
+                .filter_map(|line| async move {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(error) => return Some(Err(error)),
+                    };
+                    let line = String::from_utf8_lossy(&line);
+                    if line.trim().is_empty() {
+                        return None;
+                    }
+                    let message = serde_json::from_str::<OllamaResponse>(&line)
+                        .map_err(ApiError::from)
+                        .and_then(OllamaResponse::into_message);
+                    Some(message.map(|message| CompletionDelta {
+                        content: Some(message.content.into()),
+                        reasoning: message.thinking.map(Into::into),
+                    }))
+                })
+                .boxed())
+        }
+        .boxed()
+    }
+
+    fn embed<'a>(&'a self, inputs: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<Embedding>, ApiError>> {
+        async move {
+            let response_text = reqwest::Client::new()
+                .post(format!("{}/api/embed", self.url))
+                .json(&OllamaEmbedRequest {
+                    model: self.embedding_model.as_deref().unwrap_or(&self.model),
+                    input: inputs,
+                })
+                .send()
+                .await?
+                .text()
+                .await?;
+            let response = match serde_json::from_str::<OllamaEmbedResponse>(&response_text) {
+                Ok(OllamaEmbedResponse { error: Some(error), .. }) => return Err(ApiError::ErrorResponse(error)),
+                Ok(response) => response,
+                Err(_) => return Err(ApiError::ErrorResponse(response_text)),
+            };
+            if response.embeddings.len() != inputs.len() {
+                return Err(ApiError::ErrorResponse(format!(
+                    "Expected {} embeddings, got {}",
+                    inputs.len(),
+                    response.embeddings.len(),
+                )));
+            }
+            Ok(response.embeddings.into_iter().map(Embedding::from).collect())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::testing::{MockResponse, mock_server};
+
+    fn user_message(text: &str) -> OpenAIMessage {
+        OpenAIMessage {
+            role: "user",
+            content: vec![OpenAIContentPart::Text { text: text.into() }],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn translates_tool_calls() {
+        let (url, server) = mock_server(vec![MockResponse::json(
+            serde_json::json!({
+                "message": {
+                    "role": "assistant",
+                    "content": "",
+                    "tool_calls": [{ "function": { "name": "add", "arguments": { "a": 1 } } }],
+                },
+                "done": true,
+            })
+            .to_string(),
+        )])
+        .await;
+
+        let messages = [user_message("Add one")];
+        let completion = OllamaBackend::new(url, "test-model")
+            .complete(CompletionRequest {
+                messages: &messages,
+                tools: &[],
+                tool_choice: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(&*completion.tool_calls[0].name, "add");
+        assert_eq!(&*completion.tool_calls[0].arguments, r#"{"a":1}"#);
+
+        let requests = server.await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert!(requests[0].head.starts_with("POST /api/chat"));
+        assert_eq!(body["messages"][0]["content"], "Add one");
+        assert_eq!(body["stream"], false);
+    }
+
+    #[tokio::test]
+    async fn streams_lines_split_across_chunks() {
+        let line = |message: serde_json::Value| format!("{}\n", serde_json::json!({ "message": message }));
+        let thinking = line(serde_json::json!({ "content": "", "thinking": "Hmm." }));
+        let (first_half, second_half) = thinking.split_at(10);
+        let (url, _) = mock_server(vec![MockResponse {
+            status: 200,
+            content_type: "application/x-ndjson",
+            chunks: vec![
+                first_half.to_string(),
+                second_half.to_string(),
+                line(serde_json::json!({ "content": "Hi" })),
+            ],
+        }])
+        .await;
+
+        let messages = [user_message("Hello")];
+        let deltas = OllamaBackend::new(url, "test-model")
+            .complete_stream(CompletionRequest {
+                messages: &messages,
+                tools: &[],
+                tool_choice: None,
+            })
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[0].reasoning.as_deref(), Some("Hmm."));
+        assert_eq!(deltas[1].content.as_deref(), Some("Hi"));
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_embeddings() {
+        let (url, _) = mock_server(vec![MockResponse::json(serde_json::json!({}).to_string())]).await;
+
+        let result = OllamaBackend::new(url, "test-model").embed(&["first"]).await;
+        assert!(matches!(result, Err(ApiError::ErrorResponse(_))));
+    }
+}
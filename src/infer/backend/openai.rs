@@ -0,0 +1,117 @@
+use futures_util::{FutureExt, StreamExt, future::BoxFuture};
+
+use super::{Backend, Completion, CompletionDelta, CompletionRequest, CompletionStream};
+use crate::infer::{
+    Embedding, ToolCall,
+    openai::{self, ApiError, OpenAIEmbeddingRequest, OpenAIRequest},
+};
+
+/// Any server exposing an OpenAI-compatible `/v1/chat/completions` and `/v1/embeddings`.
+pub struct OpenAIBackend {
+    url: Box<str>,
+    model: Box<str>,
+    embedding_model: Option<Box<str>>,
+}
+
+impl OpenAIBackend {
+    pub fn new(url: impl Into<Box<str>>, model: impl Into<Box<str>>) -> Self {
+        Self {
+            url: url.into(),
+            model: model.into(),
+            embedding_model: None,
+        }
+    }
+
+    /// Without one, embeddings are requested from the chat model.
+    pub fn with_embedding_model(mut self, embedding_model: Option<Box<str>>) -> Self {
+        self.embedding_model = embedding_model;
+        self
+    }
+
+    fn openai_request<'a>(&'a self, request: &CompletionRequest<'a>, stream: bool) -> OpenAIRequest<'a> {
+        OpenAIRequest {
+            model: &self.model,
+            messages: request.messages,
+            stream,
+            tools: request.tools,
+            tool_choice: request.tool_choice.clone(),
+        }
+    }
+}
+
+impl Backend for OpenAIBackend {
+    fn complete<'a>(&'a self, request: CompletionRequest<'a>) -> BoxFuture<'a, Result<Completion, ApiError>> {
+        async move {
+            let response = openai::openai_completion(&self.openai_request(&request, false), &self.url).await?;
+            Ok(Completion {
+                content: response.content.unwrap_or_default(),
+                tool_calls: response.tool_calls.into_iter().map(ToolCall::from).collect(),
+            })
+        }
+        .boxed()
+    }
+
+    fn complete_stream<'a>(
+        &'a self,
+        request: CompletionRequest<'a>,
+    ) -> BoxFuture<'a, Result<CompletionStream, ApiError>> {
+        async move {
+            let deltas = openai::openai_request_stream(&self.openai_request(&request, true), &self.url).await?;
+            Ok(deltas
+                .map(|delta| {
+                    delta.map(|delta| CompletionDelta {
+                        content: delta.content,
+                        reasoning: delta.reasoning_content,
+                    })
+                })
+                .boxed())
+        }
+        .boxed()
+    }
+
+    fn embed<'a>(&'a self, inputs: &'a [&'a str]) -> BoxFuture<'a, Result<Vec<Embedding>, ApiError>> {
+        async move {
+            let request = OpenAIEmbeddingRequest {
+                model: self.embedding_model.as_deref().unwrap_or(&self.model),
+                input: inputs,
+            };
+            let embeddings = openai::openai_embeddings(&request, &self.url).await?;
+            Ok(embeddings.into_iter().map(Embedding::from).collect())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infer::testing::{MockResponse, mock_server};
+
+    #[tokio::test]
+    async fn embeds_in_input_order() {
+        let (url, server) = mock_server(vec![MockResponse::json(
+            serde_json::json!({
+                "data": [
+                    { "index": 1, "embedding": [0.0, 1.0] },
+                    { "index": 0, "embedding": [1.0, 0.0] },
+                ],
+            })
+            .to_string(),
+        )])
+        .await;
+
+        let embeddings = OpenAIBackend::new(url, "chat-model")
+            .with_embedding_model(Some("embedding-model".into()))
+            .embed(&["first", "second"])
+            .await
+            .unwrap();
+        assert_eq!(embeddings[0].as_slice(), &[1.0, 0.0]);
+        assert_eq!(embeddings[1].as_slice(), &[0.0, 1.0]);
+
+        let requests = server.await.unwrap();
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert!(requests[0].head.starts_with("POST /v1/embeddings"));
+        assert_eq!(body["model"], "embedding-model");
+        assert_eq!(body["input"], serde_json::json!(["first", "second"]));
+    }
+}
@@ -1,55 +1,37 @@
-use once_cell::sync::Lazy;
 use std::env;
 
-pub static DEFAULT_MODEL: Lazy<Box<str>> = Lazy::new(|| {
-    env::var("DEFAULT_MODEL")
-        .unwrap_or_else(|_| "default".into())
-        .into_boxed_str()
-});
+use super::profile::ModelProfile;
 
-pub static INFER_URL: Lazy<Box<str>> = Lazy::new(|| {
-    env::var("INFER_URL")
-        .unwrap_or_else(|_| "http://infer".into())
-        .into_boxed_str()
-});
-
-pub static MODEL_USE_SYSTEM_PROMPT: Lazy<bool> = Lazy::new(|| {
-    env::var("MODEL_USE_SYSTEM_PROMPT")
-        .unwrap_or_else(|_| "true".into())
-        .parse()
-        .expect("MODEL_USE_SYSTEM_PROMPT must be 'true' or 'false'")
-});
-
-pub static THINK_ON_POSTFIX: Lazy<Box<str>> = Lazy::new(|| {
-    env::var("MODEL_THINK_ON_POSTFIX")
-        .unwrap_or_else(|_| "".into())
-        .into_boxed_str()
-});
-
-pub static THINK_OFF_POSTFIX: Lazy<Box<str>> = Lazy::new(|| {
-    env::var("MODEL_THINK_OFF_POSTFIX")
-        .unwrap_or_else(|_| "".into())
-        .into_boxed_str()
-});
+/// The main model. `INFER_PROFILE` may point to a TOML or YAML profile, which the
+/// `INFER_BACKEND`, `INFER_URL`, `DEFAULT_MODEL` and `MODEL_*` variables then override.
+pub fn main_profile() -> ModelProfile {
+    load_profile("", ModelProfile::default())
+}
 
-pub static MODEL_HAS_REASONING: Lazy<bool> = Lazy::new(|| {
-    env::var("MODEL_HAS_REASONING")
-        .unwrap_or_else(|_| "false".into())
-        .parse()
-        .expect("MODEL_HAS_REASONING must be 'true' or 'false'")
-});
+/// A smaller model for quick utility calls, like classifying errors or naming threads,
+/// configured with the same variables prefixed by `UTILITY_`. Anything left unset is
+/// inherited from the main profile; without `UTILITY_INFER_PROFILE` or
+/// `UTILITY_DEFAULT_MODEL` there is no separate utility model.
+pub fn utility_profile(main: &ModelProfile) -> Option<ModelProfile> {
+    if env::var_os("UTILITY_INFER_PROFILE").is_none() && env::var_os("UTILITY_DEFAULT_MODEL").is_none() {
+        return None;
+    }
+    Some(load_profile("UTILITY_", main.clone()))
+}
 
-pub static MODEL_HAS_TOGGLEABLE_REASONING: Lazy<bool> = Lazy::new(|| {
-    !THINK_ON_POSTFIX.is_empty() || !THINK_OFF_POSTFIX.is_empty()
-});
+fn load_profile(prefix: &str, base: ModelProfile) -> ModelProfile {
+    let profile = match env::var(format!("{prefix}INFER_PROFILE")) {
+        Ok(path) => ModelProfile::from_file(&path)
+            .unwrap_or_else(|error| panic!("Couldn't load {prefix}INFER_PROFILE {path}: {error}")),
+        Err(_) => base,
+    };
+    profile
+        .with_env_overrides(prefix)
+        .unwrap_or_else(|error| panic!("{error}"))
+}
 
 pub fn validate() {
-    // Trigger the lazy statics to force panics early
-    let _ = &*DEFAULT_MODEL;
-    let _ = &*INFER_URL;
-    let _ = *MODEL_USE_SYSTEM_PROMPT;
-    let _ = &*THINK_ON_POSTFIX;
-    let _ = &*THINK_OFF_POSTFIX;
-    let _ = *MODEL_HAS_REASONING;
-    let _ = *MODEL_HAS_TOGGLEABLE_REASONING;
+    // Load the profiles to force panics early
+    let main = main_profile();
+    let _ = utility_profile(&main);
 }
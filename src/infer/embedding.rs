@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+/// A text embedding, as returned by the model. Only comparable to embeddings from the same model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embedding(Arc<[f32]>);
+
+impl Embedding {
+    pub fn dimensions(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.0
+    }
+
+    /// In `[-1, 1]`, or 0 when the dimensions differ or either vector is all zeroes.
+    pub fn cosine_similarity(&self, other: &Embedding) -> f32 {
+        if self.dimensions() != other.dimensions() {
+            return 0.0;
+        }
+        let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+        for (a, b) in self.0.iter().zip(other.0.iter()) {
+            dot += a * b;
+            norm_a += a * a;
+            norm_b += b * b;
+        }
+        if norm_a == 0.0 || norm_b == 0.0 {
+            return 0.0;
+        }
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+
+    /// The `limit` candidates most similar to this one, best first.
+    pub fn most_similar<'a, T>(
+        &self,
+        candidates: impl IntoIterator<Item = (T, &'a Embedding)>,
+        limit: usize,
+    ) -> Vec<(T, f32)> {
+        let mut scored = candidates
+            .into_iter()
+            .map(|(item, embedding)| (item, self.cosine_similarity(embedding)))
+            .collect::<Vec<_>>();
+        scored.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+impl From<Vec<f32>> for Embedding {
+    fn from(values: Vec<f32>) -> Self {
+        Self(values.into())
+    }
+}
+
+impl From<Embedding> for Vec<f32> {
+    fn from(embedding: Embedding) -> Self {
+        embedding.0.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_by_cosine_similarity() {
+        let query = Embedding::from(vec![1.0, 0.0]);
+        let same_direction = Embedding::from(vec![3.0, 0.0]);
+        let orthogonal = Embedding::from(vec![0.0, 2.0]);
+        let opposite = Embedding::from(vec![-1.0, 0.0]);
+        let other_model = Embedding::from(vec![1.0, 0.0, 0.0]);
+
+        assert_eq!(query.cosine_similarity(&same_direction), 1.0);
+        assert_eq!(query.cosine_similarity(&other_model), 0.0);
+        let ranked = query.most_similar(
+            [("opposite", &opposite), ("orthogonal", &orthogonal), ("same", &same_direction)],
+            2,
+        );
+        assert_eq!(ranked, vec![("same", 1.0), ("orthogonal", 0.0)]);
+    }
+}
@@ -13,6 +13,16 @@ pub enum InferError {
 
     #[error("Context length error: {0}")]
     ContextLengthError(Arc<str>),
+
+    #[error("Model kept calling tools after {0} rounds")]
+    ToolRoundsExceeded(usize),
+}
+
+impl InferError {
+    /// Whether the backend doesn't offer the operation at all, as opposed to failing at it
+    pub fn is_unsupported(&self) -> bool {
+        matches!(self, Self::ApiError(ApiError::Unsupported(_)))
+    }
 }
 
 impl From<reqwest::Error> for InferError {
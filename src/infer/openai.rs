@@ -1,9 +1,13 @@
+use futures_util::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use crate::sse;
+
 pub const ROLE_SYSTEM: &str = "system";
 pub const ROLE_USER: &str = "user";
 pub const ROLE_ASSISTANT: &str = "assistant";
+pub const ROLE_TOOL: &str = "tool";
 
 #[derive(Error, Debug)]
 pub enum ApiError {
@@ -15,6 +19,9 @@ pub enum ApiError {
 
     #[error("Error response from API: {0}")]
     ErrorResponse(String),
+
+    #[error("Not supported by this backend: {0}")]
+    Unsupported(&'static str),
 }
 
 impl From<OpenAIError> for ApiError {
@@ -27,12 +34,24 @@ impl From<OpenAIError> for ApiError {
 pub struct OpenAIRequest<'a> {
     pub model: &'a str,
     pub messages: &'a [OpenAIMessage],
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    pub tools: &'a [OpenAITool<'a>],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIMessage {
     pub role: &'static str,
+    // Assistant messages that only carry tool calls have no content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub content: Vec<OpenAIContentPart>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<OpenAIToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
 impl Default for OpenAIMessage {
@@ -40,10 +59,45 @@ impl Default for OpenAIMessage {
         Self {
             role: ROLE_SYSTEM,
             content: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
         }
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct OpenAITool<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub function: OpenAIFunction<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenAIFunction<'a> {
+    pub name: &'a str,
+    pub description: &'a str,
+    pub parameters: &'a serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIToolCall {
+    pub id: String,
+    #[serde(rename = "type", default = "function_type")]
+    pub kind: String,
+    pub function: OpenAIFunctionCall,
+}
+
+fn function_type() -> String {
+    "function".into()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenAIFunctionCall {
+    pub name: String,
+    /// JSON-encoded, as the API sends it
+    pub arguments: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum OpenAIContentPart {
@@ -53,7 +107,7 @@ pub enum OpenAIContentPart {
     },
     #[serde(rename = "image_url")]
     ImageUrl {
-        image_url: String
+        image_url: ImageUrlData
     },
     #[serde(rename = "input_audio")]
     Audio {
@@ -65,6 +119,18 @@ pub enum OpenAIContentPart {
     }
 }
 
+impl OpenAIContentPart {
+    pub fn is_text(&self) -> bool {
+        matches!(self, Self::Text { .. })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageUrlData {
+    /// Either a regular URL or a base64 `data:` URI
+    pub url: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AudioData {
     pub format: String,
@@ -83,7 +149,10 @@ pub struct FileData {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct OpenAIResponseMessage {
-    pub content: Box<str>,
+    #[serde(default)]
+    pub content: Option<Box<str>>,
+    #[serde(default)]
+    pub tool_calls: Vec<OpenAIToolCall>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -96,22 +165,38 @@ struct OpenAIChoice {
     message: OpenAIResponseMessage,
 }
 
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChunk {
+    choices: Vec<OpenAIStreamChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIStreamChoice {
+    delta: OpenAIStreamDelta,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct OpenAIStreamDelta {
+    #[serde(default)]
+    pub content: Option<Box<str>>,
+    /// Emitted separately by some servers (e.g. llama.cpp, vLLM) instead of inline `<think>` tags
+    #[serde(default)]
+    pub reasoning_content: Option<Box<str>>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct OpenAIError {
     pub error: String,
 }
 
-pub async fn openai_request(
-    messages: &[OpenAIMessage],
-    model: &str,
+pub async fn openai_completion(
+    openai_request: &OpenAIRequest<'_>,
     infer_url: &str,
-) -> Result<Box<str>, ApiError> {
-    let openai_request = OpenAIRequest { model, messages };
-
+) -> Result<OpenAIResponseMessage, ApiError> {
     let client = reqwest::Client::new();
     let response_text = client
         .post(format!("{}/v1/chat/completions", infer_url))
-        .json(&openai_request)
+        .json(openai_request)
         .send()
         .await?
         .text()
@@ -126,7 +211,101 @@ pub async fn openai_request(
     let response: OpenAIResponse = serde_json::from_str(&response_text)?;
     Ok(response
         .choices
-        .first()
-        .map(|choice| choice.message.content.clone())
-        .unwrap_or_default())
+        .into_iter()
+        .next()
+        .map(|choice| choice.message)
+        .unwrap_or(OpenAIResponseMessage {
+            content: None,
+            tool_calls: Vec::new(),
+        }))
+}
+
+const STREAM_DONE: &str = "[DONE]";
+
+pub async fn openai_request_stream(
+    openai_request: &OpenAIRequest<'_>,
+    infer_url: &str,
+) -> Result<impl Stream<Item = Result<OpenAIStreamDelta, ApiError>> + Send + 'static, ApiError> {
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/v1/chat/completions", infer_url))
+        .json(openai_request)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let response_text = response.text().await?;
+        return Err(match serde_json::from_str::<OpenAIError>(&response_text) {
+            Ok(error_response) => ApiError::from(error_response),
+            Err(_) => ApiError::ErrorResponse(response_text),
+        });
+    }
+
+    let events = sse::decode(response.bytes_stream().boxed());
+    Ok(events
+        .take_while(|event| {
+            let is_done = matches!(event, Ok(event) if event.data.trim() == STREAM_DONE);
+            async move { !is_done }
+        })
+        .map(|event| {
+            let event = event?;
+            if let Ok(error_response) = serde_json::from_str::<OpenAIError>(&event.data) {
+                return Err(ApiError::from(error_response));
+            }
+            let chunk: OpenAIStreamChunk = serde_json::from_str(&event.data)?;
+            Ok(chunk
+                .choices
+                .into_iter()
+                .next()
+                .map(|choice| choice.delta)
+                .unwrap_or_default())
+        }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenAIEmbeddingRequest<'a> {
+    pub model: &'a str,
+    pub input: &'a [&'a str],
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbeddingResponse {
+    data: Vec<OpenAIEmbeddingData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIEmbeddingData {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Returns one vector per input, in input order.
+pub async fn openai_embeddings(
+    embedding_request: &OpenAIEmbeddingRequest<'_>,
+    infer_url: &str,
+) -> Result<Vec<Vec<f32>>, ApiError> {
+    let client = reqwest::Client::new();
+    let response_text = client
+        .post(format!("{}/v1/embeddings", infer_url))
+        .json(embedding_request)
+        .send()
+        .await?
+        .text()
+        .await?;
+
+    if let Ok(error_response) = serde_json::from_str::<OpenAIError>(&response_text) {
+        return Err(ApiError::from(error_response));
+    }
+
+    let mut response: OpenAIEmbeddingResponse = serde_json::from_str(&response_text)?;
+    response.data.sort_by_key(|data| data.index);
+    if response.data.len() != embedding_request.input.len() {
+        return Err(ApiError::ErrorResponse(format!(
+            "Expected {} embeddings, got {}",
+            embedding_request.input.len(),
+            response.data.len(),
+        )));
+    }
+    Ok(response.data.into_iter().map(|data| data.embedding).collect())
 }
@@ -0,0 +1,276 @@
+use std::{env, path::Path, str::FromStr, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::backend::{Backend, LlamaCppBackend, OllamaBackend, OpenAIBackend};
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("Invalid value for {name}: {value:?}")]
+    InvalidEnv { name: String, value: String },
+
+    #[error("Couldn't read model profile: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Invalid TOML model profile: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Invalid YAML model profile: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("Model profile must be a .toml, .yaml or .yml file: {0}")]
+    UnknownFormat(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackendKind {
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAI,
+    #[serde(rename = "ollama")]
+    Ollama,
+    #[serde(rename = "llama.cpp", alias = "llamacpp")]
+    LlamaCpp,
+}
+
+impl FromStr for BackendKind {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match &*value.to_lowercase() {
+            "openai" => Ok(Self::OpenAI),
+            "ollama" => Ok(Self::Ollama),
+            "llama.cpp" | "llamacpp" => Ok(Self::LlamaCpp),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Everything needed to talk to one model: where it is served and how it should be prompted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModelProfile {
+    pub backend: BackendKind,
+    pub url: Box<str>,
+    pub model: Box<str>,
+    /// When false, system messages are sent with the user role
+    pub use_system_prompt: bool,
+    /// Appended to the last message to turn reasoning on, e.g. `/think`
+    pub think_on_postfix: Box<str>,
+    pub think_off_postfix: Box<str>,
+    /// Whether the model emits `<think>` reasoning by itself
+    pub has_reasoning: bool,
+    /// In tokens, shared between the prompt and the reply
+    pub context_length: usize,
+    /// Served by the same backend; the chat model is asked for embeddings when unset
+    pub embedding_model: Option<Box<str>>,
+}
+
+impl Default for ModelProfile {
+    fn default() -> Self {
+        Self {
+            backend: BackendKind::default(),
+            url: "http://infer".into(),
+            model: "default".into(),
+            use_system_prompt: true,
+            think_on_postfix: "".into(),
+            think_off_postfix: "".into(),
+            has_reasoning: false,
+            context_length: 8192,
+            embedding_model: None,
+        }
+    }
+}
+
+impl ModelProfile {
+    pub fn new(backend: BackendKind, url: impl Into<Box<str>>, model: impl Into<Box<str>>) -> Self {
+        Self {
+            backend,
+            url: url.into(),
+            model: model.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn has_toggleable_reasoning(&self) -> bool {
+        !self.think_on_postfix.is_empty() || !self.think_off_postfix.is_empty()
+    }
+
+    pub fn embedding_model_name(&self) -> &str {
+        self.embedding_model.as_deref().unwrap_or(&self.model)
+    }
+
+    /// How many tokens a prompt may take, leaving a quarter of the context for the reply.
+    pub fn prompt_budget(&self) -> usize {
+        self.context_length - self.context_length / 4
+    }
+
+    /// Reads `INFER_BACKEND`, `INFER_URL`, `DEFAULT_MODEL` and the `MODEL_*` variables.
+    pub fn from_env() -> Result<Self, ProfileError> {
+        Self::default().with_env_overrides("")
+    }
+
+    /// Replaces the fields whose variable is set, each name prefixed with `prefix`.
+    pub fn with_env_overrides(mut self, prefix: &str) -> Result<Self, ProfileError> {
+        let var = |name: &str| env::var(format!("{prefix}{name}")).ok();
+        fn parse<T: FromStr>(prefix: &str, name: &str, value: String) -> Result<T, ProfileError> {
+            value.parse::<T>().map_err(|_| ProfileError::InvalidEnv {
+                name: format!("{prefix}{name}"),
+                value,
+            })
+        }
+
+        if let Some(backend) = var("INFER_BACKEND") {
+            self.backend = backend.parse().map_err(|_| ProfileError::InvalidEnv {
+                name: format!("{prefix}INFER_BACKEND"),
+                value: backend,
+            })?;
+        }
+        if let Some(url) = var("INFER_URL") {
+            self.url = url.into();
+        }
+        if let Some(model) = var("DEFAULT_MODEL") {
+            self.model = model.into();
+        }
+        if let Some(value) = var("MODEL_USE_SYSTEM_PROMPT") {
+            self.use_system_prompt = parse(prefix, "MODEL_USE_SYSTEM_PROMPT", value)?;
+        }
+        if let Some(postfix) = var("MODEL_THINK_ON_POSTFIX") {
+            self.think_on_postfix = postfix.into();
+        }
+        if let Some(postfix) = var("MODEL_THINK_OFF_POSTFIX") {
+            self.think_off_postfix = postfix.into();
+        }
+        if let Some(value) = var("MODEL_HAS_REASONING") {
+            self.has_reasoning = parse(prefix, "MODEL_HAS_REASONING", value)?;
+        }
+        if let Some(value) = var("MODEL_CONTEXT_LENGTH") {
+            self.context_length = parse(prefix, "MODEL_CONTEXT_LENGTH", value)?;
+        }
+        if let Some(model) = var("EMBEDDING_MODEL") {
+            self.embedding_model = Some(model.into());
+        }
+        Ok(self)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, ProfileError> {
+        Ok(toml::from_str(source)?)
+    }
+
+    pub fn from_yaml(source: &str) -> Result<Self, ProfileError> {
+        Ok(serde_yaml::from_str(source)?)
+    }
+
+    /// Picks the format by extension.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&source),
+            Some("yaml" | "yml") => Self::from_yaml(&source),
+            _ => Err(ProfileError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    pub fn build_backend(&self) -> Arc<dyn Backend> {
+        match self.backend {
+            BackendKind::OpenAI => Arc::new(
+                OpenAIBackend::new(self.url.clone(), self.model.clone())
+                    .with_embedding_model(self.embedding_model.clone()),
+            ),
+            BackendKind::Ollama => Arc::new(
+                OllamaBackend::new(self.url.clone(), self.model.clone())
+                    .with_embedding_model(self.embedding_model.clone()),
+            ),
+            BackendKind::LlamaCpp => Arc::new(LlamaCppBackend::new(self.url.clone())),
+        }
+    }
+}
+
+/// A profile together with the backend serving it.
+#[derive(Clone)]
+pub struct Model {
+    pub profile: Arc<ModelProfile>,
+    pub(super) backend: Arc<dyn Backend>,
+}
+
+impl Model {
+    pub fn new(profile: ModelProfile) -> Self {
+        Self {
+            backend: profile.build_backend(),
+            profile: Arc::new(profile),
+        }
+    }
+
+    /// Pairs a profile with a backend it doesn't describe, e.g. a `MockBackend`.
+    pub fn with_backend(profile: ModelProfile, backend: impl Backend + 'static) -> Self {
+        Self {
+            profile: Arc::new(profile),
+            backend: Arc::new(backend),
+        }
+    }
+}
+
+impl std::fmt::Debug for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Model").field("profile", &self.profile).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_partial_toml_with_defaults() {
+        let profile = ModelProfile::from_toml(
+            r#"
+            backend = "ollama"
+            url = "http://localhost:11434"
+            model = "qwen3:4b"
+            think_off_postfix = "/no_think"
+            context_length = 32768
+            "#,
+        )
+        .unwrap();
+        assert_eq!(profile.backend, BackendKind::Ollama);
+        assert_eq!(&*profile.model, "qwen3:4b");
+        assert!(profile.use_system_prompt);
+        assert!(profile.has_toggleable_reasoning());
+        assert_eq!(profile.prompt_budget(), 24576);
+    }
+
+    #[test]
+    fn parses_yaml() {
+        let profile = ModelProfile::from_yaml(indoc::indoc! {"
+            backend: llama.cpp
+            url: http://localhost:8080
+            use_system_prompt: false
+            has_reasoning: true
+        "})
+        .unwrap();
+        assert_eq!(profile.backend, BackendKind::LlamaCpp);
+        assert!(!profile.use_system_prompt);
+        assert!(profile.has_reasoning);
+        assert_eq!(&*profile.model, "default");
+    }
+
+    #[test]
+    fn overrides_from_prefixed_env() {
+        // Unique prefix, so this doesn't race with other tests reading the environment
+        unsafe {
+            env::set_var("PROFILE_TEST_DEFAULT_MODEL", "small");
+            env::set_var("PROFILE_TEST_MODEL_HAS_REASONING", "yes");
+        }
+        let base = ModelProfile::new(BackendKind::OpenAI, "http://big", "big");
+        let result = base.clone().with_env_overrides("PROFILE_TEST_");
+        assert!(matches!(result, Err(ProfileError::InvalidEnv { name, .. }) if name == "PROFILE_TEST_MODEL_HAS_REASONING"));
+
+        unsafe { env::set_var("PROFILE_TEST_MODEL_HAS_REASONING", "true") };
+        let profile = base.with_env_overrides("PROFILE_TEST_").unwrap();
+        assert_eq!(&*profile.model, "small");
+        assert_eq!(&*profile.url, "http://big");
+        assert!(profile.has_reasoning);
+    }
+}
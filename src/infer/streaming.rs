@@ -0,0 +1,159 @@
+const THINK_OPEN_TAG: &str = "<think>";
+const THINK_CLOSE_TAG: &str = "</think>";
+
+/// A piece of a streamed reply, already separated into reasoning and answer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InferDelta {
+    Reasoning(Box<str>),
+    Content(Box<str>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SplitterState {
+    /// Haven't seen enough to tell whether the reply opens with `<think>`
+    Start,
+    Reasoning,
+    /// Right after `</think>`, whitespace is dropped the same way `WithReasoning::parse` trims it
+    AfterReasoning,
+    Content,
+}
+
+/// Incremental counterpart of `WithReasoning::parse`: splits a streamed reply
+/// on `<think>…</think>`, holding back only as much text as could be part of a tag.
+#[derive(Debug)]
+pub struct ReasoningSplitter {
+    state: SplitterState,
+    buffer: String,
+}
+
+impl Default for ReasoningSplitter {
+    fn default() -> Self {
+        Self {
+            state: SplitterState::Start,
+            buffer: String::new(),
+        }
+    }
+}
+
+impl ReasoningSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &str) -> Vec<InferDelta> {
+        self.buffer.push_str(chunk);
+        let mut deltas = Vec::new();
+        loop {
+            match self.state {
+                SplitterState::Start => {
+                    let trimmed = self.buffer.trim_start();
+                    if let Some(rest) = trimmed.strip_prefix(THINK_OPEN_TAG) {
+                        self.buffer = rest.to_string();
+                        self.state = SplitterState::Reasoning;
+                    } else if THINK_OPEN_TAG.starts_with(trimmed) {
+                        break;
+                    } else {
+                        self.state = SplitterState::Content;
+                    }
+                }
+                SplitterState::Reasoning => match self.buffer.find(THINK_CLOSE_TAG) {
+                    Some(position) => {
+                        push_delta(&mut deltas, InferDelta::Reasoning, &self.buffer[..position]);
+                        self.buffer.drain(..position + THINK_CLOSE_TAG.len());
+                        self.state = SplitterState::AfterReasoning;
+                    }
+                    None => {
+                        let keep = partial_tag_suffix_len(&self.buffer, THINK_CLOSE_TAG);
+                        let emit_len = self.buffer.len() - keep;
+                        push_delta(&mut deltas, InferDelta::Reasoning, &self.buffer[..emit_len]);
+                        self.buffer.drain(..emit_len);
+                        break;
+                    }
+                },
+                SplitterState::AfterReasoning => {
+                    let trimmed_len = self.buffer.trim_start().len();
+                    self.buffer.drain(..self.buffer.len() - trimmed_len);
+                    if self.buffer.is_empty() {
+                        break;
+                    }
+                    self.state = SplitterState::Content;
+                }
+                SplitterState::Content => {
+                    push_delta(&mut deltas, InferDelta::Content, &self.buffer);
+                    self.buffer.clear();
+                    break;
+                }
+            }
+        }
+        deltas
+    }
+
+    /// Flushes whatever was held back waiting for a possible tag.
+    pub fn finish(&mut self) -> Vec<InferDelta> {
+        let mut deltas = Vec::new();
+        let rest = std::mem::take(&mut self.buffer);
+        match self.state {
+            SplitterState::Reasoning => push_delta(&mut deltas, InferDelta::Reasoning, &rest),
+            _ => push_delta(&mut deltas, InferDelta::Content, &rest),
+        }
+        deltas
+    }
+}
+
+fn push_delta(deltas: &mut Vec<InferDelta>, make: fn(Box<str>) -> InferDelta, text: &str) {
+    if !text.is_empty() {
+        deltas.push(make(text.into()));
+    }
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of `tag`.
+fn partial_tag_suffix_len(text: &str, tag: &str) -> usize {
+    (1..tag.len().min(text.len() + 1))
+        .rev()
+        .find(|&len| text.is_char_boundary(text.len() - len) && text.ends_with(&tag[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_all(chunks: &[&str]) -> (String, String) {
+        let mut splitter = ReasoningSplitter::new();
+        let mut deltas = Vec::new();
+        for chunk in chunks {
+            deltas.extend(splitter.push(chunk));
+        }
+        deltas.extend(splitter.finish());
+        let mut reasoning = String::new();
+        let mut content = String::new();
+        for delta in deltas {
+            match delta {
+                InferDelta::Reasoning(text) => reasoning.push_str(&text),
+                InferDelta::Content(text) => content.push_str(&text),
+            }
+        }
+        (reasoning, content)
+    }
+
+    #[test]
+    fn splits_tags_broken_across_chunks() {
+        let (reasoning, content) = split_all(&["\n<th", "ink>Let me", " see.</th", "ink>\n\n", "Hello", "!"]);
+        assert_eq!(reasoning, "Let me see.");
+        assert_eq!(content, "Hello!");
+    }
+
+    #[test]
+    fn passes_through_replies_without_reasoning() {
+        let (reasoning, content) = split_all(&["<", "b>Hi</b>"]);
+        assert_eq!(reasoning, "");
+        assert_eq!(content, "<b>Hi</b>");
+    }
+
+    #[test]
+    fn flushes_unterminated_reasoning() {
+        let (reasoning, content) = split_all(&["<think>Hmm </", "thi"]);
+        assert_eq!(reasoning, "Hmm </thi");
+        assert_eq!(content, "");
+    }
+}
@@ -0,0 +1,107 @@
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    task::JoinHandle,
+};
+
+pub struct MockResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    /// Written one chunk at a time, flushing in between
+    pub chunks: Vec<String>,
+}
+
+impl MockResponse {
+    pub fn json(body: impl Into<String>) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            chunks: vec![body.into()],
+        }
+    }
+
+    pub fn sse<I, S>(frames: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            status: 200,
+            content_type: "text/event-stream",
+            chunks: frames.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+pub struct MockRequest {
+    pub head: String,
+    pub body: String,
+}
+
+/// Serves the canned responses in order, one per connection, and returns the
+/// base URL plus a handle resolving to the requests that were received.
+pub async fn mock_server(responses: Vec<MockResponse>) -> (String, JoinHandle<Vec<MockRequest>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            requests.push(read_request(&mut socket).await);
+
+            let head = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: {}\r\nConnection: close\r\n\r\n",
+                response.status, response.content_type,
+            );
+            socket.write_all(head.as_bytes()).await.unwrap();
+            for chunk in response.chunks {
+                socket.write_all(chunk.as_bytes()).await.unwrap();
+                socket.flush().await.unwrap();
+            }
+            socket.shutdown().await.ok();
+        }
+        requests
+    });
+    (url, handle)
+}
+
+async fn read_request(socket: &mut tokio::net::TcpStream) -> MockRequest {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let read = socket.read(&mut chunk).await.unwrap();
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(position) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            break position + 4;
+        }
+        if read == 0 {
+            break buffer.len();
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+    let content_length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse::<usize>().ok())
+                .flatten()
+        })
+        .unwrap_or(0);
+    while buffer.len() < head_end + content_length {
+        let read = socket.read(&mut chunk).await.unwrap();
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    MockRequest {
+        head,
+        body: String::from_utf8_lossy(&buffer[head_end..]).to_string(),
+    }
+}
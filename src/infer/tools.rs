@@ -0,0 +1,149 @@
+use std::{future::Future, sync::Arc};
+
+use futures_util::{FutureExt, future::BoxFuture};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use super::openai::{OpenAIFunction, OpenAITool};
+
+#[derive(Error, Debug)]
+pub enum ToolError {
+    #[error("Unknown tool: {0}")]
+    UnknownTool(Box<str>),
+
+    #[error("Invalid tool arguments: {0}")]
+    InvalidArguments(#[from] serde_json::Error),
+
+    #[error("Tool failed: {0}")]
+    Failed(Box<str>),
+}
+
+type ToolHandler = dyn Fn(serde_json::Value) -> BoxFuture<'static, Result<String, ToolError>> + Send + Sync;
+
+/// A function the model may call, described by a JSON schema of its arguments.
+pub struct Tool {
+    pub name: Box<str>,
+    pub description: Box<str>,
+    pub parameters: serde_json::Value,
+    handler: Arc<ToolHandler>,
+}
+
+impl Tool {
+    pub fn new<F, Fut>(
+        name: impl Into<Box<str>>,
+        description: impl Into<Box<str>>,
+        parameters: serde_json::Value,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, ToolError>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+            handler: Arc::new(move |arguments| handler(arguments).boxed()),
+        }
+    }
+
+    /// Like `new`, but deserializes the arguments into `A` first.
+    pub fn typed<A, F, Fut>(
+        name: impl Into<Box<str>>,
+        description: impl Into<Box<str>>,
+        parameters: serde_json::Value,
+        handler: F,
+    ) -> Self
+    where
+        A: DeserializeOwned + Send + 'static,
+        F: Fn(A) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, ToolError>> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        Self::new(name, description, parameters, move |arguments| {
+            let handler = handler.clone();
+            async move { handler(serde_json::from_value::<A>(arguments)?).await }
+        })
+    }
+
+    pub async fn call(&self, arguments: serde_json::Value) -> Result<String, ToolError> {
+        (self.handler)(arguments).await
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<Tool>>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, tool: Tool) {
+        self.tools.retain(|existing| existing.name != tool.name);
+        self.tools.push(Arc::new(tool));
+    }
+
+    pub fn with_tool(mut self, tool: Tool) -> Self {
+        self.register(tool);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Tool>> {
+        self.tools.iter().find(|tool| &*tool.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Runs a call the model requested. Malformed arguments and unknown tools
+    /// are reported as errors so they can be fed back to the model.
+    pub async fn call(&self, name: &str, arguments: &str) -> Result<String, ToolError> {
+        let tool = self.get(name).ok_or_else(|| ToolError::UnknownTool(name.into()))?;
+        let arguments = match arguments.trim() {
+            "" => serde_json::Value::Object(Default::default()),
+            arguments => serde_json::from_str(arguments)?,
+        };
+        tool.call(arguments).await
+    }
+
+    pub(super) fn as_openai_tools(&self) -> Vec<OpenAITool<'_>> {
+        self.tools
+            .iter()
+            .map(|tool| OpenAITool {
+                kind: "function",
+                function: OpenAIFunction {
+                    name: &tool.name,
+                    description: &tool.description,
+                    parameters: &tool.parameters,
+                },
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub enum ToolChoice {
+    #[default]
+    Auto,
+    None,
+    Required,
+    Function(Box<str>),
+}
+
+impl ToolChoice {
+    pub(super) fn as_openai_value(&self) -> serde_json::Value {
+        match self {
+            Self::Auto => "auto".into(),
+            Self::None => "none".into(),
+            Self::Required => "required".into(),
+            Self::Function(name) => serde_json::json!({
+                "type": "function",
+                "function": { "name": name },
+            }),
+        }
+    }
+}
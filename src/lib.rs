@@ -15,6 +15,9 @@ pub mod infer;
 #[cfg(feature = "infer")]
 pub mod prompts;
 
+#[cfg(any(feature = "infer", feature = "frontend"))]
+pub mod sse;
+
 pub trait Authenticated {
     fn get_from_user_id(&self) -> Uuid;
     fn set_from_user_id(&mut self, id: Uuid);
@@ -56,8 +56,8 @@ impl From<actix::MailboxError> for Error {
 #[cfg(any(feature = "server-http2", feature = "client-http2"))]
 #[cfg_attr(feature = "server-http2", derive(Serialize))]
 #[cfg_attr(feature = "client-http2", derive(Deserialize))]
-struct HttpErrorBody {
-    error: Box<str>,
+pub(crate) struct HttpErrorBody {
+    pub(crate) error: Box<str>,
 }
 
 #[cfg(feature = "server-http2")]
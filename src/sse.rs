@@ -0,0 +1,134 @@
+use futures_util::{Stream, StreamExt, stream};
+
+/// A single server-sent event, with its `data:` lines joined by newlines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<Box<str>>,
+    pub data: Box<str>,
+}
+
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds raw bytes into the decoder and returns every event completed by them.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some((end, separator_len)) = find_event_end(&self.buffer) {
+            let block = self.buffer.drain(..end + separator_len).collect::<Vec<_>>();
+            if let Some(event) = parse_event(&block[..end]) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flushes an event left unterminated at the end of the stream.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let block = std::mem::take(&mut self.buffer);
+        parse_event(&block)
+    }
+}
+
+fn find_event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    (0..buffer.len()).find_map(|i| {
+        let rest = &buffer[i..];
+        if rest.starts_with(b"\r\n\r\n") {
+            Some((i, 4))
+        } else if rest.starts_with(b"\n\n") || rest.starts_with(b"\r\r") {
+            Some((i, 2))
+        } else {
+            None
+        }
+    })
+}
+
+fn parse_event(block: &[u8]) -> Option<SseEvent> {
+    let block = String::from_utf8_lossy(block);
+    let mut event = None;
+    let mut data: Option<String> = None;
+    for line in block.lines() {
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => event = Some(value.into()),
+            "data" => match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_string()),
+            },
+            // Comments (empty field) and `id`/`retry` are not used by our consumers
+            _ => {}
+        }
+    }
+    data.map(|data| SseEvent {
+        event,
+        data: data.into(),
+    })
+}
+
+/// Turns a stream of byte chunks into a stream of server-sent events.
+pub fn decode<S, B, E>(bytes: S) -> impl Stream<Item = Result<SseEvent, E>>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    stream::unfold(
+        (bytes, SseDecoder::new(), false),
+        |(mut bytes, mut decoder, is_done)| async move {
+            if is_done {
+                return None;
+            }
+            match bytes.next().await {
+                Some(Ok(chunk)) => {
+                    let events = decoder.push(chunk.as_ref());
+                    Some((stream::iter(events.into_iter().map(Ok).collect::<Vec<_>>()), (bytes, decoder, false)))
+                }
+                Some(Err(error)) => Some((stream::iter(vec![Err(error)]), (bytes, decoder, true))),
+                None => {
+                    let events = decoder.finish().into_iter().map(Ok).collect::<Vec<_>>();
+                    Some((stream::iter(events), (bytes, decoder, true)))
+                }
+            }
+        },
+    )
+    .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_events_split_across_chunks() {
+        let mut decoder = SseDecoder::new();
+        assert!(decoder.push(b"data: {\"a\"").is_empty());
+        let events = decoder.push(b": 1}\n\nevent: update\ndata: x\ndata: y\n\n: keep-alive\n\n");
+        assert_eq!(
+            events,
+            vec![
+                SseEvent { event: None, data: "{\"a\": 1}".into() },
+                SseEvent { event: Some("update".into()), data: "x\ny".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_crlf_and_unterminated_tail() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.push(b"data: one\r\n\r\ndata: two");
+        assert_eq!(events, vec![SseEvent { event: None, data: "one".into() }]);
+        assert_eq!(decoder.finish(), Some(SseEvent { event: None, data: "two".into() }));
+    }
+}